@@ -0,0 +1,15 @@
+use std::io;
+use std::process::ExitCode;
+
+/// Language server for markdownit documents over standard input and output
+fn main()-> ExitCode {
+    match markdownit::lsp::run(io::stdin().lock(), io::stdout().lock()) {
+        Ok(true) => ExitCode::SUCCESS,
+        // Exiting without a shutdown request first
+        Ok(false) => ExitCode::from(1),
+        Err(error) => {
+            eprintln!("markdownit-lsp: {}", error);
+            ExitCode::from(1)
+        }
+    }
+}
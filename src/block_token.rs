@@ -1,15 +1,75 @@
 use std::iter::Peekable;
 
+use crate::html::{is_block_tag, scan_html_tag};
+use crate::slug::is_valid_id;
+use crate::toc::TocRange;
+use crate::TokenizerOptions;
+
 #[derive(Debug, Clone)]
 pub struct HeadingToken{
     pub level: usize,
+    pub value: String,
+    /// Explicit id given with the ```{#custom-id}``` suffix
+    pub id: Option<String>,
+    /// 0 based index of the input line the heading is on, the text line of a setext heading
+    pub line: usize
+}
+
+#[derive(Debug, Clone)]
+/// A ```[^label]: note``` footnote definition
+pub struct FootnoteToken {
+    pub label: String,
     pub value: String
 }
 
 #[derive(Debug, Clone)]
+/// A glossary term with its ```: definition``` lines
+pub struct DefinitionItem {
+    pub term: String,
+    pub definitions: Vec<String>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// What a caption describes, each kind being numbered on its own
+pub enum CaptionKind {
+    /// Code blocks and images
+    Figure,
+    Table
+}
+
+impl CaptionKind {
+    /// Word leading the caption number, as in ```Figure 2```
+    pub fn label(&self)-> &str {
+        match self {
+            CaptionKind::Figure => "Figure",
+            CaptionKind::Table => "Table",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A ```**caption**``` line attached to the code block, image or table right above it
+pub struct CaptionToken {
+    pub kind: CaptionKind,
+    pub value: String,
+    /// Explicit id given with the ```{#custom-id}``` suffix
+    pub id: Option<String>
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum HrToken{
+    /// ```---```, ```***``` or ```___```, with or without spaces between the characters
     Simple,
-    Bold
+    /// ```____```
+    Bold,
+    /// ```---(dashed)```
+    Dashed,
+    /// ```---(dotted)```
+    Dotted,
+    /// ```---(double)```
+    Double,
+    /// ```--- Section ---```
+    Labeled(String)
 }
 
 #[derive(Debug, Clone)]
@@ -40,23 +100,100 @@ pub enum BlockToken {
     Table(String),
     List(ListToken),
     CodeBlock(CodeBlock),
-    Blockquote(String),
+    /// The quoted lines, continuation lines included
+    Blockquote(Vec<String>),
     Heading(HeadingToken),
     Alignment(AlignmentToken),
     Image(String),
+    Toc(TocRange),
+    Footnote(FootnoteToken),
+    DefinitionList(Vec<DefinitionItem>),
+    /// Display math between ```$$``` fences, kept verbatim
+    Math(String),
+    /// Raw html block running until the next empty line
+    Html(String),
+    Caption(CaptionToken),
 }
 
 #[derive(Debug, Clone, PartialEq)]
+/// Shape of a ```-(shape)``` bullet, ```-``` alone being a disc
 pub enum BulletType {
     Default,
-    Custom(String)
+    Circle,
+    Square,
+    Arrow,
+    Check,
+    Star,
+    /// Any other symbol or emoji given as the shape, like ```-(❯)```
+    Glyph(String)
 }
 
-#[derive(Debug, Clone)]
+impl BulletType {
+    /// Bullet for the ```shape``` written between the parentheses
+    ///
+    /// Unknown names fall back to the default disc, as do glyphs longer than a few characters
+    pub fn from_shape(shape: &str)-> BulletType {
+        let shape = shape.trim();
+        match shape.to_ascii_lowercase().as_str() {
+            "" | "disc" => BulletType::Default,
+            "circle" => BulletType::Circle,
+            "square" => BulletType::Square,
+            "arrow" => BulletType::Arrow,
+            "check" => BulletType::Check,
+            "star" => BulletType::Star,
+            _ if shape.chars().count() <= 8 && !shape.contains(|c: char| c.is_ascii_alphanumeric() || c.is_whitespace() || c.is_control()) => {
+                BulletType::Glyph(shape.to_owned())
+            }
+            _ => BulletType::Default,
+        }
+    }
+
+    /// Name of the shape, used for the list class
+    pub fn name(&self)-> &str {
+        match self {
+            BulletType::Default => "disc",
+            BulletType::Circle => "circle",
+            BulletType::Square => "square",
+            BulletType::Arrow => "arrow",
+            BulletType::Check => "check",
+            BulletType::Star => "star",
+            BulletType::Glyph(_) => "glyph",
+        }
+    }
+
+    /// Value of the css ```list-style-type``` drawing the bullet as the ```::marker``` of each item
+    ///
+    /// Symbols are written as a css string of escaped code points, so no glyph can break out of the style
+    pub fn list_style(&self)-> String {
+        match self {
+            BulletType::Default | BulletType::Circle | BulletType::Square => self.name().to_owned(),
+            BulletType::Arrow => css_string("→ "),
+            BulletType::Check => css_string("✓ "),
+            BulletType::Star => css_string("★ "),
+            BulletType::Glyph(glyph) => css_string(&format!("{} ", glyph)),
+        }
+    }
+}
+
+fn css_string(text: &str)-> String {
+    let escaped: String = text.chars().map(|c| format!("\\{:x} ", c as u32)).collect();
+    format!("'{}'", escaped)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// State of a task list item
 pub enum ListMeta {
     None,
+    /// ```[x]```
     Checked,
+    /// ```[ ]```
     Unchecked,
+    /// ```[-]```
+    InProgress,
+    /// ```[~]```
+    Cancelled,
+    /// ```[!]```
+    Important,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -93,7 +230,9 @@ pub enum ItemValue {
 #[derive(Debug, Clone)]
 pub struct ListItem {
     pub value: ItemValue,
-    pub if_meta: ListMeta
+    pub if_meta: ListMeta,
+    /// 0 based index of the input line the item is on
+    pub line: usize
 }
 
 #[derive(Debug, Clone)]
@@ -106,6 +245,10 @@ pub struct ListToken {
 enum MultiLineFlag {
     None,
     Blockquote,
+    Math,
+    Html,
+    /// Inside a ```<!--``` comment, up to the line closing it
+    Comment,
 }
 
 struct MultiLineToken {
@@ -118,13 +261,98 @@ impl MultiLineToken {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The kind of block an input line was read into
+pub enum LineKind {
+    Empty,
+    /// A line of a paragraph, setext heading text and definition terms included
+    Text,
+    /// A ```#``` heading line or a setext underline
+    Heading,
+    ListItem,
+    Blockquote,
+    /// A ```` ``` ```` fence, the lines between them being read as usual
+    CodeFence,
+    Math,
+    Html,
+    Definition,
+    Footnote,
+    Caption,
+    /// Rules, directives and alignment markers
+    Other,
+}
+
+impl LineKind {
+    /// Kind of the block a line that left ```token``` last in the container was read into
+    fn of(token: Option<&BlockToken>)-> LineKind {
+        match token {
+            None | Some(BlockToken::P) => LineKind::Empty,
+            Some(BlockToken::Text(_)) => LineKind::Text,
+            Some(BlockToken::Heading(_)) => LineKind::Heading,
+            Some(BlockToken::List(_)) => LineKind::ListItem,
+            Some(BlockToken::Blockquote(_)) => LineKind::Blockquote,
+            Some(BlockToken::CodeBlock(_)) => LineKind::CodeFence,
+            Some(BlockToken::Math(_)) => LineKind::Math,
+            Some(BlockToken::Html(_)) => LineKind::Html,
+            Some(BlockToken::DefinitionList(_)) => LineKind::Definition,
+            Some(BlockToken::Footnote(_)) => LineKind::Footnote,
+            Some(BlockToken::Caption(_)) => LineKind::Caption,
+            Some(_) => LineKind::Other,
+        }
+    }
+}
+
 pub fn block_tokenization(container: &mut Vec<BlockToken>, input_lines: &Vec<&str>) {
+    block_tokenization_with_options(container, input_lines, &TokenizerOptions::default())
+}
+
+/// Tokenizes the lines into blocks, reading only the extensions turned on in ```options```
+pub fn block_tokenization_with_options(container: &mut Vec<BlockToken>, input_lines: &[&str], options: &TokenizerOptions) {
+    block_tokenization_with_lines(container, input_lines, options);
+}
+
+/// Tokenizes the lines like ```block_tokenization_with_options```, returning the kind of block
+/// each line was read into
+pub fn block_tokenization_with_lines(container: &mut Vec<BlockToken>, input_lines: &[&str], options: &TokenizerOptions)-> Vec<LineKind> {
     let mut lines_iter: Peekable<std::slice::Iter<'_, &str>> = input_lines.iter().peekable();
     let mut multi_line_id = MultiLineToken{value: MultiLineFlag::None};
+    let mut line_kinds = Vec::with_capacity(input_lines.len());
 
     while let Some(&&line) = lines_iter.peek() {
+        let line_number = input_lines.len() - lines_iter.len();
+        // Every pass reads a single line, the previous one being done with once the next starts
+        if line_number > 0 {
+            line_kinds.push(LineKind::of(container.last()));
+        }
         let mut line_chars: Peekable<std::str::Chars<'_>> = line.chars().peekable();
 
+        // Math blocks keep every line verbatim, empty ones included, until the closing fence
+        if let MultiLineFlag::Math = multi_line_id.value {
+            if line.trim() == "$$" {
+                multi_line_id.set(MultiLineFlag::None);
+            } else if let Some(BlockToken::Math(formula)) = container.last_mut() {
+                if !formula.is_empty() {
+                    formula.push('\n');
+                }
+                formula.push_str(line);
+            }
+            lines_iter.next();
+            continue;
+        }
+
+        // Comments keep every line, empty ones included, until the one holding ```-->```
+        if let MultiLineFlag::Comment = multi_line_id.value {
+            if line.contains("-->") {
+                multi_line_id.set(MultiLineFlag::None);
+            }
+            if let Some(BlockToken::Html(raw)) = container.last_mut() {
+                raw.push('\n');
+                raw.push_str(line);
+            }
+            lines_iter.next();
+            continue;
+        }
+
         // Checking for empty line
         if line.trim().is_empty() {
             container.push(BlockToken::P);
@@ -135,7 +363,7 @@ pub fn block_tokenization(container: &mut Vec<BlockToken>, input_lines: &Vec<&st
 
         // tokenize for Alignment
         if line.starts_with("|") {
-            match line.split_at(3).0 {
+            match line.get(..3).unwrap_or_default() {
                 "|< " => {
                     container.push(BlockToken::Alignment(AlignmentToken::Left));
                     for _ in 0..3 {
@@ -175,9 +403,23 @@ pub fn block_tokenization(container: &mut Vec<BlockToken>, input_lines: &Vec<&st
                 }).unwrap();
 
                 match last_occur {
-                    BlockToken::Blockquote(ref mut value) => {
-                        let content = format!("<br>\n<span>{}</span>", line);
-                        value.push_str(&content);
+                    BlockToken::Blockquote(ref mut lines) => {
+                        lines.push(line.to_owned());
+                        lines_iter.next();
+                        continue;
+                    },
+                    _ => {}
+                }
+            },
+            MultiLineFlag::Html => {
+                // A caption line right under a closed table ends the block to caption it
+                let captions_table = caption_line(line).is_some() && matches!(container.last(),
+                    Some(token @ BlockToken::Html(raw)) if raw.trim_end().ends_with("</table>") && caption_kind(token) == Some(CaptionKind::Table));
+                match (captions_table, container.last_mut()) {
+                    (true, _) => multi_line_id.set(MultiLineFlag::None),
+                    (false, Some(BlockToken::Html(raw))) => {
+                        raw.push('\n');
+                        raw.push_str(line);
                         lines_iter.next();
                         continue;
                     },
@@ -187,11 +429,29 @@ pub fn block_tokenization(container: &mut Vec<BlockToken>, input_lines: &Vec<&st
             _ => {}
         }
 
+        // Setext headings underline the preceding paragraph with ```===``` or ```---```
+        if let (Some(level), Some(BlockToken::Text(_))) = (setext_level(line), container.last()) {
+            let mut lines = vec![];
+            while let Some(BlockToken::Text(_)) = container.last() {
+                if let Some(BlockToken::Text(value)) = container.pop() {
+                    lines.push(value.trim().to_owned());
+                }
+                if let Some(BlockToken::Br) = container.last() {
+                    container.pop();
+                }
+            }
+            lines.reverse();
+            let (value, id) = split_heading_id(&lines.join("\n"));
+            container.push(BlockToken::Heading(HeadingToken { level, value, id, line: line_number - lines.len() }));
+            lines_iter.next();
+            continue;
+        }
+
         //Incase no preveous multiline element, continue to match any otehr block level element
         if let Some(c) = line_chars.peek() {
             match c {
                 '#' => {
-                    tokenize_heading(line, container);
+                    tokenize_heading(line, line_number, container);
                 },
                 '_' => {
                     tokenize_horizontal_line(line, container);
@@ -202,6 +462,21 @@ pub fn block_tokenization(container: &mut Vec<BlockToken>, input_lines: &Vec<&st
                 '`' => {
                     tokenize_codeblock(line, container);
                 }
+                ':' if line.starts_with("::") => {
+                    tokenize_directive(line, container);
+                }
+                ':' if options.definition_lists => {
+                    tokenize_definition(line, container);
+                }
+                '[' if options.footnotes => {
+                    tokenize_footnote(line, container);
+                }
+                '$' if options.math => {
+                    tokenize_math(line, container, &mut multi_line_id);
+                }
+                '<' => {
+                    tokenize_html(line, container, &mut multi_line_id);
+                }
                 ' ' => {
                     let mut level = 0;
                     let mut step = line.get(level..4);
@@ -219,19 +494,11 @@ pub fn block_tokenization(container: &mut Vec<BlockToken>, input_lines: &Vec<&st
                                         continue;
                                     }
                                     '-' => {
-                                        tokenize_bulleted_list(line, container, level+4);
-                                        break;
-                                    }
-                                    num if num.is_numeric() => {
-                                        tokenize_numbered_list(line, container, level+4);
+                                        tokenize_bulleted_list(line, line_number, container, level+4, options);
                                         break;
                                     }
-                                    alpha if alpha.is_alphabetic() => {
-                                        if alpha.is_uppercase() {
-                                            tokenize_alpha_list(line, container, true, level+4);
-                                        } else {
-                                            tokenize_alpha_list(line, container, false, level+4);
-                                        }
+                                    marker if marker.is_ascii_alphanumeric() => {
+                                        tokenize_ordered_list(line, line_number, container, level+4);
                                         break;
                                     }
                                     _ => {
@@ -262,21 +529,13 @@ pub fn block_tokenization(container: &mut Vec<BlockToken>, input_lines: &Vec<&st
                                 level += 1;
                             }
                             '-' => {
-                                tokenize_bulleted_list(line, container, level);
+                                tokenize_bulleted_list(line, line_number, container, level, options);
                                 break;
                             }
-                            num if num.is_numeric() => {
-                                tokenize_numbered_list(line, container, level);
+                            marker if marker.is_ascii_alphanumeric() => {
+                                tokenize_ordered_list(line, line_number, container, level);
                                 break;
                             }
-                            alpha if alpha.is_alphabetic() => {
-                                if alpha.is_uppercase() {
-                                    tokenize_alpha_list(line, container, true, level);
-                                } else {
-                                    tokenize_alpha_list(line, container, false, level);
-                                }
-                                continue;
-                            }
                             _ => {
                                 container.push(BlockToken::Br);
                                 container.push(BlockToken::Text(line.to_owned()));
@@ -291,18 +550,20 @@ pub fn block_tokenization(container: &mut Vec<BlockToken>, input_lines: &Vec<&st
                     }
                 }
                 '-' => {
-                    tokenize_bulleted_list(line, container, 0);
-                }
-                num if num.is_numeric() => {
-                    tokenize_numbered_list(line, container, 0);
+                    match horizontal_rule(line) {
+                        Some(_) => tokenize_horizontal_line(line, container),
+                        None => tokenize_bulleted_list(line, line_number, container, 0, options),
+                    }
                 }
-                alpha if alpha.is_alphabetic() => {
-                    if alpha.is_uppercase() {
-                        tokenize_alpha_list(line, container, true, 0);
-                    } else {
-                        tokenize_alpha_list(line, container, false, 0);
+                '*' => {
+                    match horizontal_rule(line) {
+                        Some(_) => tokenize_horizontal_line(line, container),
+                        None => tokenize_caption(line, container),
                     }
                 }
+                marker if marker.is_ascii_alphanumeric() => {
+                    tokenize_ordered_list(line, line_number, container, 0);
+                }
                 _ => {
                     container.push(BlockToken::Br);
                     container.push(BlockToken::Text(line.to_owned()));
@@ -312,6 +573,198 @@ pub fn block_tokenization(container: &mut Vec<BlockToken>, input_lines: &Vec<&st
 
         lines_iter.next();
     }
+    if !input_lines.is_empty() {
+        line_kinds.push(LineKind::of(container.last()));
+    }
+    line_kinds
+}
+
+/// Splits space separated directive attributes like ```min="2" max=3``` into key value pairs
+pub fn directive_attributes(attributes: &str)-> Vec<(String, String)> {
+    let mut pairs = vec![];
+    let mut chars = attributes.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut key = String::new();
+        while let Some(&c) = chars.peek() {
+            if c == '=' || c.is_whitespace() {
+                break;
+            }
+            key.push(c);
+            chars.next();
+        }
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'=') {
+            chars.next();
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                for c in chars.by_ref() {
+                    if c == '"' {
+                        break;
+                    }
+                    value.push(c);
+                }
+            } else {
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    value.push(c);
+                    chars.next();
+                }
+            }
+        }
+        pairs.push((key, value));
+    }
+
+    pairs
+}
+
+/// Returns the label and note of a ```[^label]: note``` footnote definition line
+pub fn footnote_definition(line: &str)-> Option<(&str, &str)> {
+    let rest = line.strip_prefix("[^")?;
+    let (label, note) = rest.split_once("]:")?;
+    if label.is_empty() || label.contains(|c: char| c.is_whitespace() || c == '[' || c == ']' || c == '^') {
+        return None;
+    }
+    Some((label, note.trim()))
+}
+
+/// Tokenizes a line opening with a block level html tag (```<div>```, ```<details>```, ...) as an html
+/// block running until the next empty line
+///
+/// A ```<!--``` comment is a block of its own ending on the line that closes it, so the markdown
+/// after it is read as usual. Lines starting with inline tags like ```<kbd>``` stay text for the
+/// inline tokenizer
+fn tokenize_html(line: &str, container: &mut Vec<BlockToken>, multiline_flag: &mut MultiLineToken) {
+    if let Some(comment) = line.strip_prefix("<!--") {
+        container.push(BlockToken::Html(line.to_owned()));
+        if !comment.contains("-->") {
+            multiline_flag.set(MultiLineFlag::Comment);
+        }
+        return;
+    }
+
+    match scan_html_tag(line) {
+        Some((tag, _)) if is_block_tag(&tag) => {
+            container.push(BlockToken::Html(line.to_owned()));
+            multiline_flag.set(MultiLineFlag::Html);
+        }
+        _ => {
+            container.push(BlockToken::Br);
+            container.push(BlockToken::Text(line.to_owned()));
+        }
+    }
+}
+
+/// Tokenizes a ```$$``` fence opening a display math block, or a whole ```$$ formula $$``` line
+///
+/// Any other line starting with ```$``` is left to the inline tokenizer
+fn tokenize_math(line: &str, container: &mut Vec<BlockToken>, multiline_flag: &mut MultiLineToken) {
+    let trimmed = line.trim();
+    if trimmed == "$$" {
+        container.push(BlockToken::Math(String::new()));
+        multiline_flag.set(MultiLineFlag::Math);
+        return;
+    }
+
+    match trimmed.strip_prefix("$$").and_then(|rest| rest.strip_suffix("$$")) {
+        Some(formula) if !formula.trim().is_empty() && !formula.contains("$$") => {
+            container.push(BlockToken::Math(formula.trim().to_owned()));
+        }
+        _ => {
+            container.push(BlockToken::Br);
+            container.push(BlockToken::Text(line.to_owned()));
+        }
+    }
+}
+
+fn tokenize_footnote(line: &str, container: &mut Vec<BlockToken>) {
+    match footnote_definition(line) {
+        Some((label, value)) => container.push(BlockToken::Footnote(
+            FootnoteToken { label: label.to_owned(), value: value.to_owned() }
+        )),
+        None => {
+            container.push(BlockToken::Br);
+            container.push(BlockToken::Text(line.to_owned()));
+        }
+    }
+}
+
+/// Tokenizes a ```: definition``` line, the term being the text line right above it
+///
+/// Consecutive definitions add to the same term, and a term separated from the previous
+/// definition list by at most one empty line joins that list
+fn tokenize_definition(line: &str, container: &mut Vec<BlockToken>) {
+    let Some(definition) = line.strip_prefix(": ").map(str::trim) else {
+        container.push(BlockToken::Br);
+        container.push(BlockToken::Text(line.to_owned()));
+        return;
+    };
+
+    match container.last_mut() {
+        Some(BlockToken::DefinitionList(items)) => {
+            if let Some(item) = items.last_mut() {
+                item.definitions.push(definition.to_owned());
+            }
+        }
+        Some(BlockToken::Text(_)) => {
+            let Some(BlockToken::Text(term)) = container.pop() else { return };
+            if let Some(BlockToken::Br) = container.last() {
+                container.pop();
+            }
+            if let Some(BlockToken::P) = container.last() {
+                if let Some(BlockToken::DefinitionList(_)) = container.iter().rev().nth(1) {
+                    container.pop();
+                }
+            }
+
+            let item = DefinitionItem { term: term.trim().to_owned(), definitions: vec![definition.to_owned()] };
+            match container.last_mut() {
+                Some(BlockToken::DefinitionList(items)) => items.push(item),
+                _ => container.push(BlockToken::DefinitionList(vec![item])),
+            }
+        }
+        _ => {
+            container.push(BlockToken::Br);
+            container.push(BlockToken::Text(line.to_owned()));
+        }
+    }
+}
+
+/// Tokenizes ```::name attributes``` directive lines, currently only ```::toc```
+fn tokenize_directive(line: &str, container: &mut Vec<BlockToken>) {
+    let Some(directive) = line.trim_end().strip_prefix("::") else {
+        container.push(BlockToken::Br);
+        container.push(BlockToken::Text(line.to_owned()));
+        return;
+    };
+    let (name, attributes) = directive.split_once(' ').unwrap_or((directive, ""));
+
+    match name {
+        "toc" => {
+            let mut range = TocRange::default();
+            for (key, value) in directive_attributes(attributes) {
+                let Ok(level) = value.parse::<usize>() else { continue };
+                match key.as_str() {
+                    "min" => range.min = level.clamp(1, 6),
+                    "max" => range.max = level.clamp(1, 6),
+                    _ => {}
+                }
+            }
+            container.push(BlockToken::Toc(range));
+        }
+        _ => {
+            container.push(BlockToken::Br);
+            container.push(BlockToken::Text(line.to_owned()));
+        }
+    }
 }
 
 fn tokenize_codeblock(line: &str, container: &mut Vec<BlockToken>){
@@ -431,7 +884,7 @@ fn tokenize_blockquote(line: &str, container: &mut Vec<BlockToken>, multiline_fl
         match nxt_c {
             ' ' => {
                 let value = line.get(2..).unwrap();
-                container.push(BlockToken::Blockquote(format!("<span>{}</span>", value).to_owned()));
+                container.push(BlockToken::Blockquote(vec![value.to_owned()]));
                 multiline_flag.set(MultiLineFlag::Blockquote);
                 break;
             },
@@ -445,521 +898,547 @@ fn tokenize_blockquote(line: &str, container: &mut Vec<BlockToken>, multiline_fl
 
 }
 
-fn tokenize_alpha_list(line: &str, container: &mut Vec<BlockToken>, is_uppercase: bool, nesting: usize) {
-    let mut line_chars = if nesting > 0 {
-        line.chars().skip(nesting).peekable()
+/// Tokenizes a line opening with an ordered list marker, ```nesting``` being the length of its indentation
+///
+/// Lines that don't match the marker grammar, like sentences starting with a letter, stay text
+fn tokenize_ordered_list(line: &str, line_number: usize, container: &mut Vec<BlockToken>, nesting: usize) {
+    let depth = list_depth(&line[..nesting]);
+    let previous = match container.last() {
+        Some(BlockToken::List(list_token)) => open_list_type(list_token, depth),
+        _ => None,
+    };
+
+    let Some((list_type, marker_len)) = ordered_list_marker(&line[nesting..], previous) else {
+        container.push(BlockToken::Br);
+        container.push(BlockToken::Text(line.to_owned()));
+        return;
+    };
+    let content = &line[nesting + marker_len..];
+    push_list_item(container, list_type, ListItem { value: ItemValue::Value(content.to_owned()), if_meta: ListMeta::None, line: line_number }, depth, line);
+}
+
+/// Returns the list type and length, following space included, of an ordered list marker opening the line
+///
+/// A marker is a number, a letter or a roman numeral followed by ```.``` or ```)``` and a space, as in
+/// ```12. item```, ```b) item``` or ```iv. item```. ```previous``` is the type of the list the line would
+/// continue, telling a single ```i```, ```v``` or ```x``` apart: it continues an alphabetic list after
+/// ```h.``` and a roman one after ```iv.```, while a new list opening with ```i.``` is roman. Longer
+/// numerals only continue a roman list, so sentences like ```mix. it well``` stay text
+pub fn ordered_list_marker(line: &str, previous: Option<&ListType>)-> Option<(ListType, usize)> {
+    let marker_len = line.find(['.', ')'])?;
+    let marker = &line[..marker_len];
+    if marker.is_empty() || !line[marker_len + 1..].starts_with(' ') {
+        return None;
+    }
+
+    let list_type = if marker.chars().all(|c| c.is_ascii_digit()) {
+        if marker.len() > 9 {
+            return None;
+        }
+        ListType::Numbered
+    } else if marker.chars().all(|c| c.is_ascii_lowercase()) || marker.chars().all(|c| c.is_ascii_uppercase()) {
+        let is_uppercase = marker.starts_with(|c: char| c.is_ascii_uppercase());
+        let alphabetic = ListType::Alphabetic(if is_uppercase { AlphaType::Upper } else { AlphaType::Lower });
+        let roman = ListType::Roman(if is_uppercase { RomanType::Upper } else { RomanType::Lower });
+        let is_roman = roman_value(marker).is_some();
+
+        match marker.len() {
+            1 if is_roman => match previous {
+                Some(list_type) if *list_type == alphabetic => alphabetic,
+                Some(list_type) if *list_type == roman => roman,
+                _ if marker.eq_ignore_ascii_case("i") => roman,
+                _ => alphabetic,
+            },
+            1 => alphabetic,
+            _ if is_roman && previous == Some(&roman) => roman,
+            _ => return None,
+        }
     } else {
-        line.chars().skip(nesting).peekable()
+        return None;
     };
-    let mut level = 0;
-    while let Some(nxt_char) = line_chars.peek() {
-        match nxt_char {
-            ' '=> {
-                let content = line.get(2..).unwrap();
-                if let Some(token) = container.last_mut() {
-                    match token {
-                        BlockToken::List(inner) => {
-                            if nesting > 0 {
-                                let content =content.get(nesting..).unwrap();
-                                
-                                match inner.nests.last_mut() {
-                                    Some(list) => {
-                                        list.items.push(ListItem { value: ItemValue::Value(content.to_owned()), if_meta: ListMeta::None });
-                                    }
-                                    None => {
-                                        inner.nests.push(ListToken { 
-                                            r#type: ListType::Alphabetic(if is_uppercase { AlphaType::Upper } else { AlphaType::Lower }), 
-                                            items: vec![ListItem { value: ItemValue::Value(content.to_owned()), if_meta: ListMeta::None }], 
-                                            nests: Vec::new() 
-                                        });
-                                        inner.items.push(
-                                            ListItem { value: ItemValue::Nesting(inner.items.len()-1), if_meta: ListMeta::None }
-                                        );
-                                    }
-                                }
-                            } else {
-                                if inner.r#type != ListType::Alphabetic(if is_uppercase { AlphaType::Upper } else { AlphaType::Lower }) {
-                                    let list = ListToken{
-                                        r#type: ListType::Alphabetic(if is_uppercase { AlphaType::Upper } else { AlphaType::Lower }),
-                                        items: vec![ListItem{value: ItemValue::Value(content.to_owned()), if_meta : ListMeta::None}],
-                                        nests: vec![]
-                                    };
-                                    container.push(BlockToken::List(list));
-                                    break;
-                                }
-                                inner.items.push(ListItem { value: ItemValue::Value(content.to_owned()), if_meta: ListMeta::None });
-                            }
-                            break;
-                        }
-                        _ => {
-                            let list = ListToken{
-                                r#type: match is_uppercase {
-                                    true => ListType::Alphabetic(AlphaType::Upper),
-                                    false => ListType::Alphabetic(AlphaType::Lower),
-                                },
-                                items: vec![ListItem{value: ItemValue::Value(content.to_owned()), if_meta: ListMeta::None}],
-                                nests: vec![]
-                            };
-                            container.push(BlockToken::List(list));
-                            break;
-                        }
-                    }
-                } else {
-                    if nesting > 0 {
-                        container.push(BlockToken::Br);
-                        container.push(BlockToken::Text(line.to_owned()));
-                        break;
-                    }
-                    
-                    let list = ListToken{
-                        r#type: match is_uppercase {
-                            true => ListType::Alphabetic(AlphaType::Upper),
-                            false => ListType::Alphabetic(AlphaType::Lower),
-                        },
-                        items: vec![ListItem{value: ItemValue::Value(content.to_owned()), if_meta: ListMeta::None}],
-                        nests: vec![]
-                    };
-                    container.push(BlockToken::List(list));
-                    break;   
-                }
-            }
-            '.' => {
-                line_chars.next();
-                level += 1;
 
-                if let None = line_chars.peek() {
-                    container.push(BlockToken::Br);
-                    container.push(BlockToken::Text(line.to_owned()));
-                    break;
-                }
-            }
-            ')' => {
-                line_chars.next();
-                if let Some(nxt_char) = line_chars.peek(){
-                    match nxt_char {
-                        ' '=> {
+    Some((list_type, marker_len + 2))
+}
 
-                            continue;
-                        }
-                        _  => {
-                            container.push(BlockToken::Br);
-                            container.push(BlockToken::Text(line.to_owned()));
-                            break;
-                        }
-                    }
-                } else {
-                    container.push(BlockToken::Br);
-                    container.push(BlockToken::Text(line.to_owned()));
-                    break;
-                }
-            }
-            alpha if alpha.is_alphabetic() => {
-                if *alpha == 'i' {
-                    line_chars.next();
-                    level += 1;
-                    continue;
-                }
-                line_chars.next();
-                level += 1;
-                if level > 1 {
-                    container.push(BlockToken::Br);
-                    container.push(BlockToken::Text(line.to_owned()));
-                    break;
+/// Value of a roman numeral written in canonical form (```iv``` but not ```iiii```), in either case
+fn roman_value(numeral: &str)-> Option<usize> {
+    const NUMERALS: [(&str, usize); 13] = [
+        ("m", 1000), ("cm", 900), ("d", 500), ("cd", 400), ("c", 100), ("xc", 90),
+        ("l", 50), ("xl", 40), ("x", 10), ("ix", 9), ("v", 5), ("iv", 4), ("i", 1),
+    ];
+
+    let numeral = numeral.to_ascii_lowercase();
+    let mut rest = numeral.as_str();
+    let mut value = 0;
+    for (symbol, symbol_value) in NUMERALS {
+        let limit = if symbol.len() == 1 && symbol != "m" { 3 } else { 1 };
+        for _ in 0..limit {
+            match rest.strip_prefix(symbol) {
+                Some(after) => {
+                    rest = after;
+                    value += symbol_value;
                 }
-            }
-            _ => {
-                container.push(BlockToken::Br);
-                container.push(BlockToken::Text(line.to_owned()));
-                break;
+                None => break,
             }
         }
     }
+
+    match rest.is_empty() && value > 0 {
+        true => Some(value),
+        false => None,
+    }
 }
 
-fn tokenize_numbered_list(line: &str, container: &mut Vec<BlockToken>, nesting: usize) {
-    let mut line_chars = if nesting > 0 {
-        line.chars().skip(nesting).peekable()
-    } else {
-        line.chars().skip(0).peekable()
-    };
-    let mut level = 0;
-    while let Some(nxt_char) = line_chars.peek() {
-        match nxt_char {
-            '.' => {
-                line_chars.next();
-                if let Some(nxt_char) = line_chars.peek(){
-                    match nxt_char {
-                        ' '=> {
-                            let content = line.get(level+2..).unwrap();
-                            if let Some(token) = container.last_mut() {
-                                match token {
-                                    BlockToken::List(list_token) => {
-                                        if nesting > 0 {
-                                            let content = content.get(nesting..).unwrap();
-                                            match list_token.nests.last_mut() {
-                                                Some(list) => {
-                                                    list.items.push(ListItem { value: ItemValue::Value(content.to_owned()), if_meta: ListMeta::None });
-                                                }
-                                                None => {
-                                                    list_token.nests.push(ListToken { 
-                                                        r#type: ListType::Numbered, 
-                                                        items: vec![ListItem { value: ItemValue::Value(content.to_owned()), if_meta: ListMeta::None }], 
-                                                        nests: Vec::new() 
-                                                    });
-                                                    list_token.items.push(
-                                                        ListItem { value: ItemValue::Nesting(list_token.items.len()-1), if_meta: ListMeta::None }
-                                                    );
-                                                }
-                                            }
-                                        } else {
-                                            if list_token.r#type != ListType::Numbered {
-                                                let list = ListToken{
-                                                    r#type: ListType::Numbered,
-                                                    items: vec![ListItem{value: ItemValue::Value(content.to_owned()), if_meta: ListMeta::None}],
-                                                    nests: vec![]
-                                                };
-                                                container.push(BlockToken::List(list));
-                                                break;
-                                            } else {
-                                                list_token.items.push(ListItem { value: ItemValue::Value(content.to_owned()), if_meta: ListMeta::None });
-                                            }
-                                        }
-                                        break;
-                                    },
-                                    _ => {
-                                        let list = ListToken{
-                                            r#type: ListType::Numbered,
-                                            items: vec![ListItem{value: ItemValue::Value(content.to_owned()), if_meta : ListMeta::None}],
-                                            nests: vec![]
-                                        };
-                                        container.push(BlockToken::List(list));
-                                        break;
-                                    }
-                                } 
-                            } else {
-                                if nesting > 0 {
-                                    container.push(BlockToken::Br);
-                                    container.push(BlockToken::Text(line.to_owned()));
-                                    break;
-                                }
+/// Nesting depth of a list item from its indentation, a tab or four spaces per level
+fn list_depth(indentation: &str)-> usize {
+    indentation.chars().map(|c| if c == '\t' { 4 } else { 1 }).sum::<usize>() / 4
+}
 
-                                let list = ListToken{
-                                    r#type: ListType::Numbered,
-                                    items: vec![ListItem{value: ItemValue::Value(content.to_owned()), if_meta : ListMeta::None}],
-                                    nests: vec![]
-                                };
-                                container.push(BlockToken::List(list));
-                                break;
-                            }
-                        }
-                        _  => {
-                            container.push(BlockToken::Br);
-                            container.push(BlockToken::Text(line.to_owned()));
-                            break;
-                        }
-                    }
-                }
-            }
-            num if num.is_numeric() => {
-                line_chars.next();
-                level += 1;
-            }
-            _ => {
-                container.push(BlockToken::Br);
-                container.push(BlockToken::Text(line.to_owned()));
-                break;
-            }
-        }
+/// Type of the list an item at ```depth``` would join, following the trailing nestings of ```list_token```
+fn open_list_type(list_token: &ListToken, depth: usize)-> Option<&ListType> {
+    if depth == 0 {
+        return Some(&list_token.r#type);
+    }
+    match list_token.items.last() {
+        Some(ListItem { value: ItemValue::Nesting(index), .. }) => open_list_type(list_token.nests.get(*index)?, depth - 1),
+        _ => None,
     }
 }
 
-fn tokenize_bulleted_list(line: &str, container: &mut Vec<BlockToken>, nesting: usize) {
-    let mut line_chars = if nesting > 0 {
-        line.chars().skip(nesting).peekable()
-    } else {
-        line.chars().skip(0).peekable()
-    };
+/// Adds an item to the list preceding it at ```depth```, starting a new list when the types differ
+///
+/// A nested item with no list above it is text
+fn push_list_item(container: &mut Vec<BlockToken>, list_type: ListType, item: ListItem, depth: usize, line: &str) {
+    match container.last_mut() {
+        Some(BlockToken::List(list_token)) if depth > 0 || list_token.r#type == list_type => {
+            nest_list_item(list_token, list_type, item, depth);
+        }
+        _ if depth > 0 => {
+            container.push(BlockToken::Br);
+            container.push(BlockToken::Text(line.to_owned()));
+        }
+        _ => container.push(BlockToken::List(ListToken { r#type: list_type, items: vec![item], nests: vec![] })),
+    }
+}
 
-    let mut level = 0;
-    let mut list_type = ListType::Bullet(BulletType::Default);
-    let mut list_meta = ListMeta::None;
-    
-    while let Some(nxt_char) = line_chars.peek() {
-        match nxt_char {
-            ' ' => {
-                let content = line.get(level+2..).unwrap();
-                if let Some(token) = container.last_mut() {
-                    match token {
-                        BlockToken::List(list_token) => {
-                            if nesting > 0 {
-                                let content = content.get(nesting..).unwrap();
-                                match list_token.nests.last_mut() {
-                                    Some(list) => {
-                                        list.items.push(ListItem { value: ItemValue::Value(content.to_owned()), if_meta: list_meta });
-                                    }
-                                    None => {
-                                        list_token.nests.push(ListToken { 
-                                            r#type: ListType::Bullet(BulletType::Default), 
-                                            items: vec![ListItem { value: ItemValue::Value(content.to_owned()), if_meta: list_meta }], 
-                                            nests: Vec::new() 
-                                        });
-                                        list_token.items.push(
-                                            ListItem { value: ItemValue::Nesting(list_token.items.len()-1), if_meta: ListMeta::None }
-                                        );
-                                    }
-                                }
-                            } else {
-                                if list_token.r#type != list_type {
-                                    let list = ListToken{
-                                        r#type: list_type,
-                                        items: vec![ListItem{value: ItemValue::Value(content.to_owned()), if_meta: list_meta}],
-                                        nests: vec![]
-                                    };
-                                    container.push(BlockToken::List(list));
-                                    break;
-                                }
-                                list_token.items.push(ListItem { value: ItemValue::Value(content.to_owned()), if_meta: list_meta });
-                            }
-                            break;
-                        },
-                        _ => {
-                            let list = ListToken{
-                                r#type: list_type,
-                                items: vec![ListItem{value: ItemValue::Value(content.to_owned()), if_meta : list_meta}],
-                                nests: vec![]
-                            };
-                            container.push(BlockToken::List(list));
-                            break;
-                        }
-                    }
-                } else {
-                    if nesting > 0 {
-                        container.push(BlockToken::Br);
-                        container.push(BlockToken::Text(line.to_owned()));
-                        break;
-                    }
+fn nest_list_item(list_token: &mut ListToken, list_type: ListType, item: ListItem, depth: usize) {
+    if depth == 0 {
+        list_token.items.push(item);
+        return;
+    }
 
-                    let list = ListToken{
-                        r#type: ListType::Bullet(BulletType::Default),
-                        items: vec![ListItem{value: ItemValue::Value(content.to_owned()), if_meta : ListMeta::None}],
-                        nests: vec![]
-                    };
-                    container.push(BlockToken::List(list));
-                    break;
-                }
-            }
-            '[' => {
-                line_chars.next();
-                level += 1;
-                if let Some(nxt_char) = line_chars.peek() {
-                    match nxt_char {
-                        sign if *sign == 'x' || *sign == 'X' || *sign == ' ' => {
-                            let mut kind = ListMeta::Checked;
-                            if *sign == ' ' {
-                                kind = ListMeta::Unchecked
-                            }
-                            line_chars.next();
-                            level += 1;
-                            if let Some(nxt_char) = line_chars.peek() {
-                                match nxt_char {
-                                    ']' => {
-                                        line_chars.next();
-                                        if let Some(nxt_char) = line_chars.peek() {
-                                            match nxt_char {
-                                                ' ' => {
-                                                    list_type = ListType::Todo;
-                                                    list_meta = kind;
-                                                    continue;
-                                                }
-                                                _ => {
-                                                    container.push(BlockToken::Br);
-                                                    container.push(BlockToken::Text(line.to_owned()));
-                                                    break; 
-                                                }
-                                            }
-                                        } else {
-                                            container.push(BlockToken::Br);
-                                            container.push(BlockToken::Text(line.to_owned()));
-                                            break; 
-                                        }
-                                    }
-                                    _ => {
-                                        container.push(BlockToken::Br);
-                                        container.push(BlockToken::Text(line.to_owned()));
-                                        break; 
-                                    }
-                                }  
-                            } else {
-                                container.push(BlockToken::Br);
-                                container.push(BlockToken::Text(line.to_owned()));
-                                break; 
-                            }
-                        }
-                        _ => {
-                            container.push(BlockToken::Br);
-                            container.push(BlockToken::Text(line.to_owned()));
-                            break; 
-                        }
-                    }
-                } else {
-                    container.push(BlockToken::Br);
-                    container.push(BlockToken::Text(line.to_owned()));
-                    break;   
-                }
-            }
-            '-' => {
-                if level > 1 {
-                    container.push(BlockToken::Br);
-                    container.push(BlockToken::Text(line.to_owned()));
-                    break;
-                }
-                line_chars.next();
-                level += 1;
-                if let None = line_chars.peek() {
-                    container.push(BlockToken::Br);
-                    container.push(BlockToken::Text(line.to_owned()));
-                    break;
-                }
-                continue
-            }
-            '(' => {
-                line_chars.next();
-                level += 1;
-                let mut bullet_buffer = String::new();
-                while let Some(nxt_char) = line_chars.peek() {
-                    match nxt_char {
-                        ')' => {
-                            line_chars.next();
-                            if let Some(nxt_char) = line_chars.peek() {
-                                match nxt_char {
-                                    ' ' => {
-                                        list_type = ListType::Bullet(BulletType::Custom(bullet_buffer.clone()));
-                                        list_meta = ListMeta::None;
-                                        continue;
-                                    }
-                                    _ => {
-                                        container.push(BlockToken::Br);
-                                        container.push(BlockToken::Text(line.to_owned()));
-                                        break; 
-                                    }
-                                }
-                            } else {
-                                container.push(BlockToken::Br);
-                                container.push(BlockToken::Text(line.to_owned()));
-                                break; 
-                            }
-                        }
-                        _ => {
-                            if bullet_buffer.len() > 10 {
-                                container.push(BlockToken::Br);
-                                container.push(BlockToken::Text(line.to_owned()));
-                                break; 
-                            }
-                            bullet_buffer.push(*nxt_char);
-                            line_chars.next();
-                            if let None = line_chars.peek() {
-                                container.push(BlockToken::Br);
-                                container.push(BlockToken::Text(line.to_owned()));
-                                break; 
-                            }
-                        }
-                    }
-                }
-            }
-            _ => {
-                container.push(BlockToken::Br);
-                container.push(BlockToken::Text(line.to_owned()));
-                break;
-            }
+    if let Some(ListItem { value: ItemValue::Nesting(index), .. }) = list_token.items.last() {
+        let nested = &mut list_token.nests[*index];
+        if depth > 1 || nested.r#type == list_type {
+            nest_list_item(nested, list_type, item, depth - 1);
+            return;
         }
     }
+
+    let line = item.line;
+    let mut nested = ListToken { r#type: list_type.clone(), items: vec![], nests: vec![] };
+    nest_list_item(&mut nested, list_type, item, depth - 1);
+    list_token.items.push(ListItem { value: ItemValue::Nesting(list_token.nests.len()), if_meta: ListMeta::None, line });
+    list_token.nests.push(nested);
 }
 
-fn tokenize_horizontal_line(line: &str, container: &mut Vec<BlockToken>) {
-    let mut line_chars = line.chars().peekable();
-    if line.contains(|non_hr: char|{
-        match non_hr {
-            target if target == '_' || target.is_ascii_whitespace() => false,
-            _ => true
+/// Tokenizes a line opening with a bullet marker, ```nesting``` being the length of its indentation
+fn tokenize_bulleted_list(line: &str, line_number: usize, container: &mut Vec<BlockToken>, nesting: usize, options: &TokenizerOptions) {
+    let marker = match bullet_marker(&line[nesting..]) {
+        // Without task lists ```- [x] item``` is a plain item reading ```[x] item```
+        Some((ListType::Todo, _, _)) if !options.task_lists => {
+            line[nesting..].starts_with("- ").then_some((ListType::Bullet(BulletType::Default), ListMeta::None, 2))
         }
-    }) {
+        marker => marker,
+    };
+    let Some((list_type, if_meta, marker_len)) = marker else {
         container.push(BlockToken::Br);
         container.push(BlockToken::Text(line.to_owned()));
         return;
+    };
+    let content = &line[nesting + marker_len..];
+    push_list_item(container, list_type, ListItem { value: ItemValue::Value(content.to_owned()), if_meta, line: line_number }, list_depth(&line[..nesting]), line);
+}
+
+/// Returns the list type, task state and length, following space included, of a bullet marker opening the line
+///
+/// Markers are ```- item```, ```-(shape) item``` and the tasks ```-[ ] item``` or ```- [ ] item```,
+/// with ```x```, ```-```, ```~``` or ```!``` in the brackets for the other task states
+pub fn bullet_marker(line: &str)-> Option<(ListType, ListMeta, usize)> {
+    let rest = line.strip_prefix('-')?;
+
+    if let Some(shape) = rest.strip_prefix('(') {
+        let (shape, content) = shape.split_once(')')?;
+        if !content.starts_with(' ') {
+            return None;
+        }
+        return Some((ListType::Bullet(BulletType::from_shape(shape)), ListMeta::None, shape.len() + 4));
     }
-    let mut level = 0;
-    while let Some(nxt_c) = line_chars.peek() {
-        match nxt_c {
-            '_' => {
-                if level > 4 {
-                    container.push(BlockToken::Br);
-                    container.push(BlockToken::Text(line.to_owned()));
-                    break;
-                }
-                line_chars.next();
-                level += 1;
-                if let None = line_chars.peek() {
-                    if level >= 3{ 
-                        if level == 3 {
-                            container.push(BlockToken::Hr(HrToken::Simple));
-                        } else if level == 4 {
-                            container.push(BlockToken::Hr(HrToken::Bold));
-                        }
-                    } else {
-                        container.push(BlockToken::Br);
-                        container.push(BlockToken::Text(line.to_owned()));
-                    }
-                }
-            },
-            ' ' => {
-                if level > 2 {
-                    if level == 3 {
-                        container.push(BlockToken::Hr(HrToken::Simple));
-                    } else if level == 4 {
-                        container.push(BlockToken::Hr(HrToken::Bold));
-                    }
-                } else {
-                    container.push(BlockToken::Br);
-                    container.push(BlockToken::Text(line.to_owned()));
-                }
-                break;
-            }
-            _ => {
-                container.push(BlockToken::Br);
-                container.push(BlockToken::Text(line.to_owned()));
-                break;
+
+    let task = rest.strip_prefix(' ').unwrap_or(rest);
+    if let Some(state) = task.strip_prefix('[').and_then(|state| state.get(..3)) {
+        let meta = match state {
+            " ] " => Some(ListMeta::Unchecked),
+            "x] " | "X] " => Some(ListMeta::Checked),
+            "-] " => Some(ListMeta::InProgress),
+            "~] " => Some(ListMeta::Cancelled),
+            "!] " => Some(ListMeta::Important),
+            _ => None,
+        };
+        if let Some(meta) = meta {
+            return Some((ListType::Todo, meta, line.len() - task.len() + 4));
+        }
+    }
+
+    match rest.starts_with(' ') {
+        true => Some((ListType::Bullet(BulletType::Default), ListMeta::None, 2)),
+        false => None,
+    }
+}
+
+/// Tokenizes a ```**caption**``` line, optionally ending with a ```{#custom-id}```
+///
+/// The caption belongs to the code block, image line or html table above it, at most one empty
+/// line apart. Anywhere else the line is text, its ```**``` making an inline caption
+fn tokenize_caption(line: &str, container: &mut Vec<BlockToken>) {
+    let captioned = match container.last() {
+        Some(BlockToken::P) => container.iter().rev().nth(1),
+        last => last,
+    };
+
+    match (caption_line(line), captioned.and_then(caption_kind)) {
+        (Some((value, id)), Some(kind)) => {
+            if let Some(BlockToken::P) = container.last() {
+                container.pop();
             }
+            container.push(BlockToken::Caption(CaptionToken { kind, value, id }));
+        }
+        _ => {
+            container.push(BlockToken::Br);
+            container.push(BlockToken::Text(line.to_owned()));
         }
     }
+}
+
+/// Returns the text and explicit id of a line made of a single ```**caption**```
+pub fn caption_line(line: &str)-> Option<(String, Option<String>)> {
+    let (value, id) = split_heading_id(line.trim());
+    let text = value.trim().strip_prefix("**")?.strip_suffix("**")?;
+    if text.is_empty() || text.starts_with(char::is_whitespace) || text.ends_with(char::is_whitespace) || text.contains("**") {
+        return None;
+    }
+    Some((text.to_owned(), id))
+}
 
+/// The kind of caption a block takes, if any: code blocks and lines holding a lone ```<img>```
+/// are figures and html blocks opening with ```<table>``` are tables
+fn caption_kind(token: &BlockToken)-> Option<CaptionKind> {
+    match token {
+        BlockToken::CodeBlock(CodeBlock::Close) => Some(CaptionKind::Figure),
+        BlockToken::Text(line) => match scan_html_tag(line.trim()) {
+            Some((tag, _)) if tag.name == "img" && tag.raw.len() == line.trim().len() => Some(CaptionKind::Figure),
+            _ => None,
+        },
+        BlockToken::Html(raw) => match scan_html_tag(raw.trim_start()) {
+            Some((tag, _)) if tag.name == "table" && !tag.closing => Some(CaptionKind::Table),
+            _ => None,
+        },
+        _ => None,
+    }
 }
 
-fn tokenize_heading(line: &str, container: &mut Vec<BlockToken>) {
-    let mut line_chars = line.chars().peekable();
-    let mut level = 0;
-    while let Some(&nxt_c) = line_chars.peek() {
-        match nxt_c {
-            ' ' => {
-                let value = line.get(level+1..).unwrap().to_owned();
-                container.push(BlockToken::Heading(
-                    HeadingToken { level, value }
-                ));
-                break;
-            },
-            '#' => {
-                if level > 6 {
-                    container.push(BlockToken::Br);
-                    container.push(BlockToken::Text(line.to_owned()));
-                    break;
-                }
-                level += 1;
-                line_chars.next();
-            },
-            _ => {
-                container.push(BlockToken::Br);
-                container.push(BlockToken::Text(line.to_owned()));
-                break;
+fn tokenize_horizontal_line(line: &str, container: &mut Vec<BlockToken>) {
+    match horizontal_rule(line) {
+        Some(hr_token) => container.push(BlockToken::Hr(hr_token)),
+        None => {
+            container.push(BlockToken::Br);
+            container.push(BlockToken::Text(line.to_owned()));
+        }
+    }
+}
+
+/// Matches a horizontal rule line made of ```-```, ```*``` or ```_```
+///
+/// Three or more of the same character (spaces in between allowed) make a simple rule, four or
+/// more underscores a bold one. A style can follow in parentheses, ```---(dashed)```, ```---(dotted)```
+/// or ```---(double)```, and text enclosed by the characters labels the rule, ```--- Section ---```
+pub fn horizontal_rule(line: &str)-> Option<HrToken> {
+    if line.len() - line.trim_start().len() > 3 {
+        return None;
+    }
+    let rule = line.trim();
+    let marker = rule.chars().next().filter(|c| matches!(c, '-' | '*' | '_'))?;
+    let run = rule.chars().take_while(|c| *c == marker).count();
+
+    if rule.chars().all(|c| c == marker || c == ' ' || c == '\t') {
+        let count = rule.chars().filter(|c| *c == marker).count();
+        return match (count, marker) {
+            (0..=2, _) => None,
+            (_, '_') if count == run && count >= 4 => Some(HrToken::Bold),
+            _ => Some(HrToken::Simple),
+        };
+    }
+    if run < 3 {
+        return None;
+    }
+
+    let rest = &rule[run..];
+    if let Some(style) = rest.strip_prefix('(').and_then(|style| style.strip_suffix(')')) {
+        return match style.trim() {
+            "dashed" => Some(HrToken::Dashed),
+            "dotted" => Some(HrToken::Dotted),
+            "double" => Some(HrToken::Double),
+            _ => None,
+        };
+    }
+
+    let closing = rest.chars().rev().take_while(|c| *c == marker).count();
+    let label = &rest[..rest.len() - closing];
+    if closing < 3 || !label.starts_with(' ') || !label.ends_with(' ') || label.trim().is_empty() {
+        return None;
+    }
+    Some(HrToken::Labeled(label.trim().to_owned()))
+}
+
+fn tokenize_heading(line: &str, line_number: usize, container: &mut Vec<BlockToken>) {
+    match atx_heading(line) {
+        Some((level, text)) => {
+            let (value, id) = split_heading_id(text);
+            let value = strip_closing_hashes(value.trim());
+            container.push(BlockToken::Heading(HeadingToken { level, value, id, line: line_number }));
+        }
+        None => {
+            container.push(BlockToken::Br);
+            container.push(BlockToken::Text(line.to_owned()));
+        }
+    }
+}
+
+/// Returns the level of a ```#``` heading line and the text after its hashes
+///
+/// The hashes need a space or a tab after them, a line of only hashes being an empty heading
+pub(crate) fn atx_heading(line: &str)-> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let text = &line[level..];
+    match level {
+        1..=6 if text.is_empty() || text.starts_with([' ', '\t']) => Some((level, text)),
+        _ => None,
+    }
+}
+
+/// Removes an optional closing sequence of hashes as in ```## Title ##```
+///
+/// The sequence only counts when it is the whole value or is preceded by whitespace,
+/// so ```C#``` is kept as is
+fn strip_closing_hashes(value: &str)-> String {
+    let without_hashes = value.trim_end_matches('#');
+    if without_hashes.len() == value.len() {
+        return value.to_owned();
+    }
+    match without_hashes.chars().last() {
+        None => String::new(),
+        Some(c) if c.is_whitespace() => without_hashes.trim_end().to_owned(),
+        Some(_) => value.to_owned(),
+    }
+}
+
+/// Returns the heading level for a setext underline, ```1``` for ```===``` and ```2``` for ```---```
+pub(crate) fn setext_level(line: &str)-> Option<usize> {
+    let underline = line.trim();
+    if underline.len() < 3 || line.len() - line.trim_start().len() > 3 {
+        return None;
+    }
+    if underline.chars().all(|c| c == '=') {
+        Some(1)
+    } else if underline.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Separates a trailing ```{#custom-id}``` from the heading text
+///
+/// Returns the remaining heading text and the id if one was given and is valid
+fn split_heading_id(value: &str) -> (String, Option<String>) {
+    let trimmed = value.trim_end();
+    if let Some(open) = trimmed.rfind("{#") {
+        if trimmed.ends_with('}') {
+            let id = &trimmed[open+2..trimmed.len()-1];
+            if is_valid_id(id) {
+                return (trimmed[..open].trim_end().to_owned(), Some(id.to_owned()));
             }
         }
     }
+    (value.to_owned(), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marker(line: &str, previous: Option<&ListType>)-> Option<ListType> {
+        ordered_list_marker(line, previous).map(|(list_type, _)| list_type)
+    }
+
+    #[test]
+    fn markers_need_a_delimiter_and_a_space() {
+        assert_eq!(ordered_list_marker("12. item", None).map(|(_, length)| length), Some(4));
+        assert!(matches!(marker("1) item", None), Some(ListType::Numbered)));
+        assert!(matches!(marker("b. item", None), Some(ListType::Alphabetic(AlphaType::Lower))));
+        assert!(matches!(marker("B) item", None), Some(ListType::Alphabetic(AlphaType::Upper))));
+        assert!(marker("1234567890. item", None).is_none());
+        assert!(marker("Ab. item", None).is_none());
+    }
+
+    /// simple.txt writes ```2 Frameworks``` and ```i)Actix Web```, which the strict grammar leaves as text
+    #[test]
+    fn loose_markers_stay_text() {
+        assert!(marker("2 Frameworks", None).is_none());
+        assert!(marker("i)Actix Web", Some(&ListType::Roman(RomanType::Lower))).is_none());
+        assert!(marker("e.g. a sentence", None).is_none());
+    }
+
+    #[test]
+    fn roman_and_alphabetic_markers_follow_the_list() {
+        let roman = ListType::Roman(RomanType::Lower);
+        let alphabetic = ListType::Alphabetic(AlphaType::Lower);
+        assert_eq!(marker("i. item", None), Some(roman.clone()));
+        assert_eq!(marker("i. item", Some(&alphabetic)), Some(alphabetic.clone()));
+        assert_eq!(marker("v. item", None), Some(alphabetic.clone()));
+        assert_eq!(marker("v. item", Some(&roman)), Some(roman.clone()));
+        assert_eq!(marker("iv. item", Some(&roman)), Some(roman.clone()));
+        assert_eq!(marker("IV) item", Some(&ListType::Roman(RomanType::Upper))), Some(ListType::Roman(RomanType::Upper)));
+    }
+
+    #[test]
+    fn words_that_are_numerals_stay_text() {
+        for line in ["mix. it well", "cd. into the folder", "civil. rights", "ii. item"] {
+            assert!(marker(line, None).is_none(), "{line}");
+        }
+        assert!(marker("iiii. item", Some(&ListType::Roman(RomanType::Lower))).is_none());
+    }
+
+    fn headings(input: &str)-> Vec<(usize, String, usize)> {
+        let mut container = vec![];
+        block_tokenization(&mut container, &input.lines().collect::<Vec<&str>>());
+        container.into_iter().filter_map(|token| match token {
+            BlockToken::Heading(heading) => Some((heading.level, heading.value, heading.line)),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn setext_underlines_make_headings() {
+        assert_eq!(headings("Title\n=====\n\nSub\n---\n"), [(1, String::from("Title"), 0), (2, String::from("Sub"), 3)]);
+        assert_eq!(headings("Intro\n\nline one\n  line two {#both}\n===\n"), [(1, String::from("line one\nline two"), 2)]);
+        assert_eq!(setext_level("    ==="), None);
+        assert_eq!(setext_level("=="), None);
+        assert_eq!(setext_level("-=-"), None);
+    }
+
+    #[test]
+    fn closing_hashes_are_dropped() {
+        assert_eq!(headings("## Closing ##\n### a # b ###\n"), [(2, String::from("Closing"), 0), (3, String::from("a # b"), 1)]);
+    }
+
+    #[test]
+    fn hash_lines_need_a_space_and_at_most_six_hashes() {
+        assert_eq!(headings("#\n###### six\n"), [(1, String::new(), 0), (6, String::from("six"), 1)]);
+        assert!(headings("####### seven\n#hash\n").is_empty());
+    }
+
+    #[test]
+    fn rule_variants() {
+        for line in ["***", "---", "___", " - - -", "*\t* *"] {
+            assert_eq!(horizontal_rule(line), Some(HrToken::Simple), "{line}");
+        }
+        assert_eq!(horizontal_rule("____"), Some(HrToken::Bold));
+        assert_eq!(horizontal_rule("__ __"), Some(HrToken::Simple));
+        assert_eq!(horizontal_rule("---(dashed)"), Some(HrToken::Dashed));
+        assert_eq!(horizontal_rule("***( dotted )"), Some(HrToken::Dotted));
+        assert_eq!(horizontal_rule("___(double)"), Some(HrToken::Double));
+        assert_eq!(horizontal_rule("--- Part 2 ---"), Some(HrToken::Labeled(String::from("Part 2"))));
+    }
+
+    #[test]
+    fn rule_lookalikes_stay_text() {
+        for line in ["--", "*-*", "    ---", "---(wavy)", "---Part---", "--- Part --", "---  ---x", "==="] {
+            assert_eq!(horizontal_rule(line), None, "{line}");
+        }
+    }
+
+    #[test]
+    fn bullet_shapes_and_task_states() {
+        assert_eq!(bullet_marker("- item"), Some((ListType::Bullet(BulletType::Default), ListMeta::None, 2)));
+        assert_eq!(bullet_marker("-(Star) item"), Some((ListType::Bullet(BulletType::Star), ListMeta::None, 8)));
+        assert_eq!(bullet_marker("-(❯) item"), Some((ListType::Bullet(BulletType::Glyph(String::from("❯"))), ListMeta::None, 7)));
+        assert_eq!(bullet_marker("-[ ] item"), Some((ListType::Todo, ListMeta::Unchecked, 5)));
+        assert_eq!(bullet_marker("- [X] item"), Some((ListType::Todo, ListMeta::Checked, 6)));
+        for (state, meta) in [('-', ListMeta::InProgress), ('~', ListMeta::Cancelled), ('!', ListMeta::Important)] {
+            assert_eq!(bullet_marker(&format!("- [{state}] item")), Some((ListType::Todo, meta, 6)));
+        }
+        assert_eq!(bullet_marker("- [?] item"), Some((ListType::Bullet(BulletType::Default), ListMeta::None, 2)));
+        assert_eq!(bullet_marker("-item"), None);
+        assert_eq!(bullet_marker("-(star)item"), None);
+    }
+
+    #[test]
+    fn glyphs_are_escaped_into_the_style() {
+        assert_eq!(BulletType::from_shape("custom").list_style(), "disc");
+        assert_eq!(BulletType::from_shape("a;b").list_style(), "disc");
+        assert_eq!(BulletType::from_shape("';}").list_style(), r"'\27 \3b \7d \20 '");
+        assert_eq!(BulletType::Arrow.list_style(), r"'\2192 \20 '");
+    }
+
+    fn captions(input: &str)-> Vec<(CaptionKind, String, Option<String>)> {
+        let mut container = vec![];
+        block_tokenization(&mut container, &input.lines().collect::<Vec<&str>>());
+        container.into_iter().filter_map(|token| match token {
+            BlockToken::Caption(caption) => Some((caption.kind, caption.value, caption.id)),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn captions_attach_to_the_block_above() {
+        let input = "```\ncode\n```\n**Code** {#code}\n\n<img src=\"a.png\">\n\n**Logo**\n\n<table><tr><td>1</td></tr></table>\n**Data**\n";
+        assert_eq!(captions(input), [
+            (CaptionKind::Figure, String::from("Code"), Some(String::from("code"))),
+            (CaptionKind::Figure, String::from("Logo"), None),
+            (CaptionKind::Table, String::from("Data"), None),
+        ]);
+    }
+
+    #[test]
+    fn captions_need_a_block_to_caption() {
+        assert!(captions("Text\n**Lonely**\n").is_empty());
+        assert!(captions("```\ncode\n```\n\n\n**Too far**\n").is_empty());
+        assert!(captions("<div>\n</table>\n**Not a table**\n").is_empty());
+        assert_eq!(caption_line("** padded **"), None);
+        assert_eq!(caption_line("**a** and **b**"), None);
+    }
+
+    #[test]
+    fn comments_end_on_their_closing_line() {
+        let mut container = vec![];
+        block_tokenization(&mut container, &vec!["<!-- note -->", "- item", "<!--", "", "# hidden", "-->", "# Title"]);
+        let html: Vec<&str> = container.iter().filter_map(|token| match token {
+            BlockToken::Html(raw) => Some(raw.as_str()),
+            _ => None,
+        }).collect();
+        assert_eq!(html, ["<!-- note -->", "<!--\n\n# hidden\n-->"]);
+        assert!(container.iter().any(|token| matches!(token, BlockToken::List(_))));
+        assert_eq!(headings("<!--\n# hidden\n-->\n# Title\n"), [(1, String::from("Title"), 3)]);
+    }
+
+    #[test]
+    fn lines_keep_the_kind_of_their_block() {
+        let lines = ["Title", "===", "", "- item", "<!-- note -->", "text", "$$", "x", "$$", "Term", ": definition", "```"];
+        let kinds = block_tokenization_with_lines(&mut vec![], &lines, &TokenizerOptions::default());
+        assert_eq!(kinds, [
+            LineKind::Text, LineKind::Heading, LineKind::Empty, LineKind::ListItem, LineKind::Html, LineKind::Text,
+            LineKind::Math, LineKind::Math, LineKind::Math, LineKind::Text, LineKind::Definition, LineKind::CodeFence,
+        ]);
+    }
 }
@@ -0,0 +1,174 @@
+use crate::inline_token::{InlineToken, InlineTokenPos};
+
+/// Known ```:shortcode:``` emoji, sorted by shortcode
+pub const SHORTCODES: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("alarm_clock", "⏰"),
+    ("angry", "😠"),
+    ("apple", "🍎"),
+    ("arrow_down", "⬇️"),
+    ("arrow_left", "⬅️"),
+    ("arrow_right", "➡️"),
+    ("arrow_up", "⬆️"),
+    ("baby", "👶"),
+    ("balloon", "🎈"),
+    ("bee", "🐝"),
+    ("beer", "🍺"),
+    ("bell", "🔔"),
+    ("bike", "🚲"),
+    ("bird", "🐦"),
+    ("blush", "😊"),
+    ("bomb", "💣"),
+    ("book", "📖"),
+    ("books", "📚"),
+    ("boom", "💥"),
+    ("bow", "🙇"),
+    ("broken_heart", "💔"),
+    ("bug", "🐛"),
+    ("bulb", "💡"),
+    ("cake", "🍰"),
+    ("calendar", "📆"),
+    ("camera", "📷"),
+    ("car", "🚗"),
+    ("cat", "🐱"),
+    ("chart_with_upwards_trend", "📈"),
+    ("check", "✔️"),
+    ("clap", "👏"),
+    ("clipboard", "📋"),
+    ("closed_lock_with_key", "🔐"),
+    ("cloud", "☁️"),
+    ("coffee", "☕"),
+    ("computer", "💻"),
+    ("confused", "😕"),
+    ("construction", "🚧"),
+    ("cookie", "🍪"),
+    ("cool", "🆒"),
+    ("cry", "😢"),
+    ("dart", "🎯"),
+    ("dog", "🐶"),
+    ("dollar", "💵"),
+    ("earth_africa", "🌍"),
+    ("email", "📧"),
+    ("exclamation", "❗"),
+    ("eyes", "👀"),
+    ("facepalm", "🤦"),
+    ("fire", "🔥"),
+    ("flag", "🚩"),
+    ("flushed", "😳"),
+    ("gear", "⚙️"),
+    ("gem", "💎"),
+    ("ghost", "👻"),
+    ("gift", "🎁"),
+    ("globe_with_meridians", "🌐"),
+    ("grin", "😁"),
+    ("grinning", "😀"),
+    ("hammer", "🔨"),
+    ("hand", "✋"),
+    ("heart", "❤️"),
+    ("heart_eyes", "😍"),
+    ("hourglass", "⌛"),
+    ("house", "🏠"),
+    ("hugs", "🤗"),
+    ("information_source", "ℹ️"),
+    ("innocent", "😇"),
+    ("joy", "😂"),
+    ("key", "🔑"),
+    ("kiss", "💋"),
+    ("laughing", "😆"),
+    ("link", "🔗"),
+    ("lock", "🔒"),
+    ("mag", "🔍"),
+    ("memo", "📝"),
+    ("moon", "🌙"),
+    ("muscle", "💪"),
+    ("musical_note", "🎵"),
+    ("neutral_face", "😐"),
+    ("new", "🆕"),
+    ("no_entry", "⛔"),
+    ("ok", "🆗"),
+    ("ok_hand", "👌"),
+    ("package", "📦"),
+    ("pencil", "📝"),
+    ("pencil2", "✏️"),
+    ("point_down", "👇"),
+    ("point_left", "👈"),
+    ("point_right", "👉"),
+    ("point_up", "☝️"),
+    ("pray", "🙏"),
+    ("pushpin", "📌"),
+    ("question", "❓"),
+    ("rage", "😡"),
+    ("rainbow", "🌈"),
+    ("raised_hands", "🙌"),
+    ("recycle", "♻️"),
+    ("relaxed", "☺️"),
+    ("rocket", "🚀"),
+    ("rofl", "🤣"),
+    ("rose", "🌹"),
+    ("scream", "😱"),
+    ("see_no_evil", "🙈"),
+    ("shield", "🛡️"),
+    ("shrug", "🤷"),
+    ("skull", "💀"),
+    ("sleeping", "😴"),
+    ("slightly_smiling_face", "🙂"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("smirk", "😏"),
+    ("snowflake", "❄️"),
+    ("sob", "😭"),
+    ("sparkles", "✨"),
+    ("speech_balloon", "💬"),
+    ("star", "⭐"),
+    ("star2", "🌟"),
+    ("stop_sign", "🛑"),
+    ("sunglasses", "😎"),
+    ("sunny", "☀️"),
+    ("sweat_smile", "😅"),
+    ("tada", "🎉"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("tired_face", "😫"),
+    ("trophy", "🏆"),
+    ("unamused", "😒"),
+    ("unlock", "🔓"),
+    ("v", "✌️"),
+    ("warning", "⚠️"),
+    ("wave", "👋"),
+    ("white_check_mark", "✅"),
+    ("wink", "😉"),
+    ("wrench", "🔧"),
+    ("x", "❌"),
+    ("yum", "😋"),
+    ("zap", "⚡"),
+    ("zzz", "💤"),
+];
+
+/// The emoji of a shortcode, given without its colons
+pub fn emoji(shortcode: &str)-> Option<&'static str> {
+    SHORTCODES.binary_search_by(|(name, _)| name.cmp(&shortcode)).ok().map(|index| SHORTCODES[index].1)
+}
+
+/// Whether the character may appear in a shortcode, like ```+1``` or ```white_check_mark```
+pub fn is_shortcode_char(c: char)-> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '+' | '-')
+}
+
+/// The emoji of a known ```:shortcode:``` opening at ```index```, along with the text following its
+/// closing colon
+///
+/// The tokenizer reads ```:smile: text``` as an open emoji, the shortcode and a text starting with
+/// the closing colon, so an emoji always spans three tokens
+pub fn emoji_at(tokens: &[InlineToken], index: usize)-> Option<(&'static str, &str)> {
+    let InlineToken::Emoji(InlineTokenPos::Open) = tokens.get(index)? else { return None };
+    let InlineToken::Text(shortcode) = tokens.get(index + 1)? else { return None };
+    let after = match tokens.get(index + 2)? {
+        InlineToken::Text(after) => after.strip_prefix(':')?,
+        InlineToken::Emoji(InlineTokenPos::Close) => "",
+        _ => return None,
+    };
+    Some((emoji(shortcode)?, after))
+}
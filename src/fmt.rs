@@ -0,0 +1,441 @@
+use crate::block_token::{atx_heading, bullet_marker, horizontal_rule, ordered_list_marker, setext_level, ListMeta, ListType};
+use crate::front_matter::extract_front_matter;
+use crate::inline_token::InlineToken;
+use crate::parse_document;
+
+/// Width paragraphs are wrapped at unless told otherwise
+pub const DEFAULT_WIDTH: usize = 80;
+
+#[derive(Debug, Clone)]
+/// Switches for ```format_document```
+pub struct FormatOptions {
+    /// Paragraphs are wrapped at this many characters, ```0``` keeps their lines as written
+    pub width: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions { width: DEFAULT_WIDTH }
+    }
+}
+
+/// Lines copied as they are until the block they belong to ends
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Verbatim {
+    None,
+    /// Inside a ```` ``` ```` code block, up to the closing fence
+    Code,
+    /// Inside a ```$$``` math block, up to the closing fence
+    Math,
+    /// Inside a ```<!--``` comment, up to the line closing it
+    Comment,
+    /// Blockquotes and html blocks, up to the next empty line
+    UntilEmpty,
+}
+
+/// What the line after a paragraph is, telling whether the paragraph's last line break matters
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Next {
+    /// An empty line or the end of the document
+    End,
+    /// A heading or a list item
+    Block,
+    /// A line kept as written, which may well continue the paragraph
+    Verbatim,
+}
+
+/// Writes the document back in canonical form
+///
+/// - headings, setext ones included, are written ```# Title``` with an empty line around them
+/// - list items are indented by four spaces per level, numbered on from their list's first item,
+///   with ```.``` after ordered markers and ```- [x]``` task markers
+/// - paragraphs are wrapped at ```options.width```, hard breaks ending their line with a backslash
+/// - trailing whitespace and runs of empty lines are removed
+///
+/// The front matter, code and math blocks, blockquotes, html blocks and comments and any line the formatter
+/// isn't sure about are kept as written. Emphasis needs no normalizing, each style having a single
+/// marker in the dialect.
+///
+/// The result tokenizes to the same tree as the input, up to the whitespace inside inline text
+/// which renders the same either way. When wrapping would change the tree the paragraphs keep
+/// their lines, and a document that can't be formatted without changing it is an error
+pub fn format_document(input: &str, options: &FormatOptions)-> Result<String, String> {
+    let tree = comparable_tree(input);
+    let widths = match options.width {
+        0 => vec![0],
+        width => vec![width, 0],
+    };
+
+    for width in widths {
+        let formatted = format_with_width(input, width);
+        if comparable_tree(&formatted) == tree {
+            return Ok(formatted);
+        }
+    }
+    Err(String::from("formatting would change how the document parses"))
+}
+
+fn format_with_width(input: &str, width: usize)-> String {
+    let (front_matter, body) = match extract_front_matter(input) {
+        Some((_, body, _)) => (&input[..input.len() - body.len()], body),
+        None => ("", input),
+    };
+
+    let mut formatter = Formatter::new(width);
+    for line in body.lines() {
+        formatter.line(line);
+    }
+    formatter.flush_paragraph(Next::End);
+
+    let mut output = front_matter.to_owned();
+    if !output.is_empty() && !output.ends_with('\n') {
+        output.push('\n');
+    }
+    if !output.is_empty() && !formatter.lines.is_empty() {
+        output.push('\n');
+    }
+    for line in &formatter.lines {
+        output.push_str(line);
+        output.push('\n');
+    }
+    output
+}
+
+struct Formatter<'a> {
+    width: usize,
+    lines: Vec<String>,
+    verbatim: Verbatim,
+    /// Lines of the paragraph being read, written out once it ends
+    paragraph: Vec<&'a str>,
+    /// Type and last item number of the open list at each nesting level
+    lists: Vec<(ListType, usize)>,
+    /// An empty line is due before the next line, after a heading
+    empty_line_due: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(width: usize)-> Formatter<'a> {
+        Formatter { width, lines: vec![], verbatim: Verbatim::None, paragraph: vec![], lists: vec![], empty_line_due: false }
+    }
+
+    fn line(&mut self, line: &'a str) {
+        match self.verbatim {
+            Verbatim::Code | Verbatim::Math | Verbatim::Comment => {
+                let closing = match self.verbatim {
+                    Verbatim::Code => line.trim_end() == "```",
+                    Verbatim::Math => line.trim() == "$$",
+                    _ => line.contains("-->"),
+                };
+                if closing {
+                    self.verbatim = Verbatim::None;
+                }
+                self.lines.push(line.to_owned());
+                return;
+            }
+            Verbatim::UntilEmpty if !line.trim().is_empty() => {
+                self.push(line.to_owned());
+                return;
+            }
+            _ => self.verbatim = Verbatim::None,
+        }
+
+        if line.trim().is_empty() {
+            self.flush_paragraph(Next::End);
+            self.lists.clear();
+            if self.lines.last().is_some_and(|last| !last.is_empty()) {
+                self.lines.push(String::new());
+            }
+            self.empty_line_due = false;
+            return;
+        }
+
+        // The underline of a setext heading turns the paragraph into the heading
+        if let Some(level) = setext_level(line) {
+            if !self.paragraph.is_empty() {
+                let text = std::mem::take(&mut self.paragraph).iter().map(|line| line.trim()).collect::<Vec<&str>>().join(" ");
+                self.heading(level, &text);
+                return;
+            }
+        }
+
+        if let Some(item) = self.list_item(line) {
+            self.flush_paragraph(Next::Block);
+            self.push(item);
+            return;
+        }
+        self.lists.clear();
+
+        if let Some((level, text)) = atx_heading(line) {
+            self.flush_paragraph(Next::Block);
+            self.heading(level, text.trim());
+            return;
+        }
+        if starts_text(line.trim_start()) {
+            self.paragraph.push(line);
+            return;
+        }
+
+        self.flush_paragraph(Next::Verbatim);
+        if line.trim_end() == "```" {
+            self.verbatim = Verbatim::Code;
+        } else if line.starts_with('$') && line.trim() == "$$" {
+            self.verbatim = Verbatim::Math;
+        } else if let Some(comment) = line.strip_prefix("<!--") {
+            if !comment.contains("-->") {
+                self.verbatim = Verbatim::Comment;
+            }
+        } else if line.starts_with(['>', '<']) {
+            self.verbatim = Verbatim::UntilEmpty;
+        }
+        self.push(line.to_owned());
+    }
+
+    /// Adds a line, after the empty line a heading before it asks for
+    fn push(&mut self, line: String) {
+        if std::mem::take(&mut self.empty_line_due) && self.lines.last().is_some_and(|last| !last.is_empty()) {
+            self.lines.push(String::new());
+        }
+        self.lines.push(line);
+    }
+
+    fn heading(&mut self, level: usize, text: &str) {
+        self.lists.clear();
+        if self.lines.last().is_some_and(|last| !last.is_empty()) {
+            self.lines.push(String::new());
+        }
+        let hashes = "#".repeat(level);
+        self.lines.push(match text.is_empty() {
+            true => hashes,
+            false => format!("{} {}", hashes, text),
+        });
+        self.empty_line_due = true;
+    }
+
+    /// The canonical form of a list item line, or ```None``` when the line is no list item
+    fn list_item(&mut self, line: &str)-> Option<String> {
+        let content = line.trim_start_matches([' ', '\t']);
+        let indentation = &line[..line.len() - content.len()];
+        let depth = match indentation {
+            "" => 0,
+            _ if indentation.chars().all(|c| c == '\t') => indentation.len(),
+            _ if indentation.chars().all(|c| c == ' ') && indentation.len().is_multiple_of(4) => indentation.len() / 4,
+            _ => return None,
+        };
+        // A nested item continues the list right above it, it is text anywhere else
+        if depth > self.lists.len() {
+            return None;
+        }
+
+        let (list_type, marker, marker_len) = if content.starts_with('-') {
+            if depth == 0 && horizontal_rule(line).is_some() {
+                return None;
+            }
+            let (list_type, meta, marker_len) = bullet_marker(content)?;
+            let marker = match meta {
+                ListMeta::None => content[..marker_len - 1].to_owned(),
+                ListMeta::Checked => String::from("- [x]"),
+                ListMeta::Unchecked => String::from("- [ ]"),
+                ListMeta::InProgress => String::from("- [-]"),
+                ListMeta::Cancelled => String::from("- [~]"),
+                ListMeta::Important => String::from("- [!]"),
+            };
+            (list_type, marker, marker_len)
+        } else if content.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+            let previous = self.lists.get(depth).map(|(list_type, _)| list_type);
+            let (list_type, marker_len) = ordered_list_marker(content, previous)?;
+            (list_type, format!("{}.", &content[..marker_len - 2]), marker_len)
+        } else {
+            return None;
+        };
+
+        self.lists.truncate(depth + 1);
+        match self.lists.get_mut(depth) {
+            Some((open_type, number)) if *open_type == list_type => *number += 1,
+            _ => {
+                // Numbering goes on from the first item's number as written
+                let first = content[..marker_len - 2].parse().unwrap_or(1);
+                self.lists.truncate(depth);
+                self.lists.push((list_type.clone(), first));
+            }
+        }
+        let marker = match list_type {
+            ListType::Numbered => format!("{}.", self.lists[depth].1),
+            _ => marker,
+        };
+
+        // An item with no text keeps the space after its marker, without which it would be no item
+        let text = content[marker_len..].trim();
+        Some(format!("{}{} {}", "    ".repeat(depth), marker, text))
+    }
+
+    /// Writes out the paragraph read so far, ```next``` being the line that ended it
+    fn flush_paragraph(&mut self, next: Next) {
+        if self.paragraph.is_empty() {
+            return;
+        }
+        let paragraph = std::mem::take(&mut self.paragraph);
+        let last = paragraph.len() - 1;
+
+        // Each line's text and whether a hard break follows it
+        let mut texts: Vec<(String, bool)> = vec![];
+        for (index, line) in paragraph.iter().enumerate() {
+            let text = line.trim_start();
+            if index < last {
+                match text.strip_suffix('\\') {
+                    Some(text) => texts.push((text.trim_end().to_owned(), true)),
+                    None => texts.push((text.trim_end().to_owned(), text.ends_with("  "))),
+                }
+            } else {
+                // A backslash ending the paragraph is text, and two spaces may break the line
+                // before a verbatim line continuing the paragraph
+                let text = match text.ends_with("  ") && next == Next::Verbatim {
+                    true => format!("{}  ", text.trim_end()),
+                    false => text.trim_end().to_owned(),
+                };
+                texts.push((text, false));
+            }
+        }
+
+        let mut lines = vec![];
+        match self.width {
+            0 => for (text, hard_break) in texts {
+                lines.push(match hard_break {
+                    true => format!("{}\\", text),
+                    false => text,
+                });
+            },
+            width => {
+                let mut words: Vec<&str> = vec![];
+                for (index, (text, hard_break)) in texts.iter().enumerate() {
+                    words.extend(text.split_whitespace());
+                    if *hard_break || index == last {
+                        let start = lines.len();
+                        wrap(&words, width, &mut lines);
+                        if *hard_break {
+                            if let Some(line) = lines[start..].last_mut() {
+                                line.push('\\');
+                            }
+                        } else if text.ends_with("  ") {
+                            if let Some(line) = lines.last_mut() {
+                                line.push_str("  ");
+                            }
+                        }
+                        words.clear();
+                    }
+                }
+            }
+        }
+        for line in lines {
+            self.push(line);
+        }
+    }
+}
+
+/// Fills lines of at most ```width``` characters with the words, a word that would be taken for
+/// the start of another block staying on the line before
+fn wrap(words: &[&str], width: usize, lines: &mut Vec<String>) {
+    let mut line = String::new();
+    let mut length = 0;
+    for word in words {
+        let word_length = word.chars().count();
+        if length > 0 && length + 1 + word_length > width && starts_text(&format!("{} ", word)) {
+            lines.push(std::mem::take(&mut line));
+            length = 0;
+        }
+        if length > 0 {
+            line.push(' ');
+            length += 1;
+        }
+        line.push_str(word);
+        length += word_length;
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+}
+
+/// Whether a line starting with ```text``` is paragraph text, rather than opening another block
+fn starts_text(text: &str)-> bool {
+    match text.chars().next() {
+        Some(c) if c.is_ascii_alphanumeric() => ordered_list_marker(text, None).is_none(),
+        Some(c) => !matches!(c, '#' | '_' | '>' | '`' | ':' | '[' | '$' | '<' | '|' | '-' | '*' | '=' | ' ' | '\t'),
+        None => false,
+    }
+}
+
+/// The tokens of the document for comparing, ignoring heading lines and the whitespace inside
+/// inline text: runs of it are one space and it's dropped around line breaks and at the ends
+fn comparable_tree(input: &str)-> String {
+    let mut tokens = parse_document(input).tokens;
+    for token in tokens.iter_mut() {
+        if let crate::SecondaryToken::Heading(heading) = token {
+            heading.line = 0;
+        }
+        for run in token.inline_tokens_mut() {
+            let count = run.len();
+            for index in 0..count {
+                let trim_start = index == 0 || matches!(run[index - 1], InlineToken::HardBreak);
+                let trim_end = index + 1 == count || matches!(run[index + 1], InlineToken::HardBreak);
+                let text = match &mut run[index] {
+                    InlineToken::Text(text) => text,
+                    InlineToken::Link(link) => &mut link.text,
+                    _ => continue,
+                };
+                let mut collapsed = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+                if text.starts_with(char::is_whitespace) && !trim_start && !collapsed.is_empty() {
+                    collapsed.insert(0, ' ');
+                }
+                if text.ends_with(char::is_whitespace) && !trim_end && !collapsed.is_empty() {
+                    collapsed.push(' ');
+                }
+                *text = collapsed;
+            }
+        }
+    }
+    format!("{:?}", tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENTS: [(&str, &str); 2] = [
+        ("README.md", include_str!("../README.md")),
+        ("doc.md", include_str!("../doc.md")),
+    ];
+
+    #[test]
+    fn formatting_keeps_the_tree() {
+        for (name, input) in DOCUMENTS {
+            for width in [DEFAULT_WIDTH, 0] {
+                let formatted = format_document(input, &FormatOptions { width }).unwrap_or_else(|error| panic!("{name}: {error}"));
+                assert_eq!(comparable_tree(&formatted), comparable_tree(input), "{name} at width {width}");
+            }
+        }
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        for (name, input) in DOCUMENTS {
+            let formatted = format_document(input, &FormatOptions::default()).unwrap();
+            assert_eq!(format_document(&formatted, &FormatOptions::default()).unwrap(), formatted, "{name}");
+        }
+    }
+
+    #[test]
+    fn setext_paragraphs_become_one_heading() {
+        assert_eq!(format_document("line one\n  line two\n---\ntext\n", &FormatOptions::default()).unwrap(), "## line one line two\n\ntext\n");
+    }
+
+    #[test]
+    fn formats_markdown_after_comments() {
+        let formatted = format_document("<!-- note -->\n*  a\n\n<!--\n\n#   kept\n-->\n#   Title\n", &FormatOptions::default()).unwrap();
+        assert_eq!(formatted, "<!-- note -->\n*  a\n\n<!--\n\n#   kept\n-->\n\n# Title\n");
+    }
+
+    #[test]
+    fn refuses_documents_it_would_change() {
+        // The line under the item is dropped by the parser, nothing the formatter writes parses the same
+        assert!(format_document("- a\n  b\n", &FormatOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+use crate::block_token::footnote_definition;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::inline_token::InlineToken;
+use crate::{escape_html, inline_tokens_parser_with_options, ParserOptions, SecondaryToken};
+
+/// Numbers the footnotes in order of their first reference and counts the references to each
+///
+/// Only the first definition of a label is numbered, so repeated definitions and
+/// definitions nothing refers to are left out of the footnotes section
+pub fn resolve_footnotes(tokens: &mut [SecondaryToken]) {
+    let mut defined: Vec<String> = vec![];
+    for token in tokens.iter() {
+        if let SecondaryToken::Footnote(footnote) = token {
+            defined.push(footnote.label.clone());
+        }
+    }
+
+    let mut numbers: HashMap<String, usize> = HashMap::new();
+    let mut references: HashMap<String, usize> = HashMap::new();
+    for token in tokens.iter_mut() {
+        for group in token.inline_tokens_mut() {
+            for inline_token in group.iter_mut() {
+                let InlineToken::FootnoteRef(reference) = inline_token else { continue };
+
+                let count = references.entry(reference.label.clone()).or_insert(0);
+                *count += 1;
+                reference.occurrence = *count;
+
+                if defined.contains(&reference.label) {
+                    let next = numbers.len() + 1;
+                    reference.number = Some(*numbers.entry(reference.label.clone()).or_insert(next));
+                }
+            }
+        }
+    }
+
+    let mut numbered: Vec<String> = vec![];
+    for token in tokens.iter_mut() {
+        if let SecondaryToken::Footnote(footnote) = token {
+            if numbered.contains(&footnote.label) {
+                continue;
+            }
+            numbered.push(footnote.label.clone());
+            footnote.number = numbers.get(&footnote.label).copied();
+            footnote.references = references.get(&footnote.label).copied().unwrap_or(0);
+        }
+    }
+}
+
+/// Reports references to undefined footnotes, unused and repeated definitions
+///
+/// ```line_offset``` is the number of source lines before ```source```, like a front matter block
+pub fn footnote_diagnostics(tokens: &[SecondaryToken], source: &str, line_offset: usize)-> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    let mut reported: Vec<&str> = vec![];
+
+    for token in tokens {
+        for group in token.inline_tokens() {
+            for inline_token in group {
+                let InlineToken::FootnoteRef(reference) = inline_token else { continue };
+                if reference.number.is_some() || reported.contains(&reference.label.as_str()) {
+                    continue;
+                }
+                reported.push(&reference.label);
+
+                let needle = format!("[^{}]", reference.label);
+                let line = source.lines().position(|line| {
+                    line.contains(&needle) && footnote_definition(line).map(|(label, _)| label) != Some(reference.label.as_str())
+                });
+                diagnostics.push(Diagnostic::new(
+                    Severity::Error,
+                    line.map(|line| line + line_offset + 1),
+                    format!("footnote [^{}] is referenced but never defined", reference.label),
+                ));
+            }
+        }
+    }
+
+    let mut defined: Vec<&str> = vec![];
+    let definition_lines: Vec<(usize, &str)> = source.lines().enumerate()
+        .filter_map(|(index, line)| footnote_definition(line).map(|(label, _)| (index + line_offset + 1, label)))
+        .collect();
+    for token in tokens {
+        let SecondaryToken::Footnote(footnote) = token else { continue };
+        let occurrences = defined.iter().filter(|label| **label == footnote.label).count();
+        let line = definition_lines.iter()
+            .filter(|(_, label)| *label == footnote.label)
+            .nth(occurrences)
+            .map(|(line, _)| *line);
+        defined.push(&footnote.label);
+
+        if occurrences > 0 {
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                line,
+                format!("footnote [^{}] is defined more than once", footnote.label),
+            ));
+        } else if footnote.references == 0 {
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                line,
+                format!("footnote [^{}] is defined but never referenced", footnote.label),
+            ));
+        }
+    }
+
+    diagnostics
+}
+
+/// Id of the ```occurrence```-th reference to a footnote, the target of its back-link
+///
+/// The occurrence goes before the label (```fnref2-a```), as a label like ```a-2``` could
+/// otherwise take the id of the second reference to ```a```
+pub fn footnote_ref_id(label: &str, occurrence: usize)-> String {
+    match occurrence {
+        0 | 1 => format!("fnref-{}", label),
+        _ => format!("fnref{}-{}", occurrence, label),
+    }
+}
+
+/// Renders the numbered footnotes as an ordered list with back-links to their references
+///
+/// Returns an empty string when the document has no referenced footnotes
+pub fn footnotes_parser(tokens: &[SecondaryToken], options: &ParserOptions)-> String {
+    let mut footnotes: Vec<_> = tokens.iter().filter_map(|token| match token {
+        SecondaryToken::Footnote(footnote) => footnote.number.map(|number| (number, footnote)),
+        _ => None,
+    }).collect();
+    if footnotes.is_empty() {
+        return String::new();
+    }
+    footnotes.sort_by_key(|(number, _)| *number);
+
+    let mut html = String::from("<section class=\"footnotes\">\n<hr class=\"hr\">\n<ol>\n");
+    for (_, footnote) in footnotes {
+        let mut item = format!("<li id=\"fn-{}\">{}", escape_html(&footnote.label), inline_tokens_parser_with_options(&mut footnote.tokens.clone(), options));
+        for occurrence in 1..=footnote.references {
+            item.push_str(&format!(" <a href=\"#{}\" class=\"footnote-backref\">↩</a>", escape_html(&footnote_ref_id(&footnote.label, occurrence))));
+        }
+        item.push_str("</li>\n");
+        html.push_str(&item);
+    }
+    html.push_str("</ol>\n</section>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_document, parser};
+
+    #[test]
+    fn distinct_labels_get_distinct_ids() {
+        let html = parser(&parse_document("A[^a.b] B[^ab]\n\n[^a.b]: first\n[^ab]: second\n").tokens);
+        assert!(html.contains("<li id=\"fn-a.b\">") && html.contains("<li id=\"fn-ab\">"), "{html}");
+        assert_ne!(footnote_ref_id("a", 2), footnote_ref_id("a-2", 1));
+    }
+
+    #[test]
+    fn footnotes_are_numbered_by_first_reference() {
+        let document = parse_document("B[^b] A[^a] B[^b]\n\n[^a]: first\n[^b]: second\n[^c]: unused\n");
+        let html = parser(&document.tokens);
+        assert!(html.contains("<a href=\"#fn-b\">1</a>"));
+        assert!(html.contains("<a href=\"#fn-a\">2</a>"));
+        assert!(html.contains("<a href=\"#fnref2-b\" class=\"footnote-backref\">"));
+        assert!(!html.contains("unused"));
+        assert!(document.diagnostics.iter().any(|diagnostic| diagnostic.message.contains("[^c]")));
+    }
+}
@@ -0,0 +1,201 @@
+#[derive(Debug, Clone, PartialEq)]
+/// A front matter value, either a single text or a list like ```tags: [rust, docs]```
+pub enum MetaValue {
+    Text(String),
+    List(Vec<String>),
+}
+
+impl MetaValue {
+    /// Returns the value as display text, list items are joined with ```", "```
+    pub fn to_text(&self)-> String {
+        match self {
+            MetaValue::Text(value) => value.clone(),
+            MetaValue::List(items) => items.join(", "),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Key value pairs from the leading front matter block, kept in order of appearance
+pub struct Metadata {
+    entries: Vec<(String, MetaValue)>,
+}
+
+impl Metadata {
+    pub fn get(&self, key: &str)-> Option<&MetaValue> {
+        self.entries.iter().find(|(name, _)| name == key).map(|(_, value)| value)
+    }
+
+    /// Sets ```key``` to ```value```, replacing a previous value of the same key
+    pub fn insert(&mut self, key: String, value: MetaValue) {
+        match self.entries.iter_mut().find(|(name, _)| *name == key) {
+            Some(entry) => entry.1 = value,
+            None => self.entries.push((key, value)),
+        }
+    }
+
+    pub fn iter(&self)-> impl Iterator<Item = (&String, &MetaValue)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+
+    pub fn is_empty(&self)-> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn title(&self)-> Option<String> {
+        self.get("title").map(MetaValue::to_text)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Flavor {
+    Yaml,
+    Toml,
+}
+
+/// Splits a leading front matter block from the document
+///
+/// The block opens on the very first line with ```---``` (YAML like) or ```+++``` (TOML)
+/// and closes with the same fence. Returns the parsed metadata, the rest of the document
+/// and the number of lines the block took, or ```None``` when the document has no front matter
+pub fn extract_front_matter(input: &str)-> Option<(Metadata, &str, usize)> {
+    let first_line = input.lines().next()?;
+    let (flavor, fence) = match first_line.trim_end() {
+        "---" => (Flavor::Yaml, "---"),
+        "+++" => (Flavor::Toml, "+++"),
+        _ => return None,
+    };
+
+    let mut offset = first_line.len() + line_ending_len(&input[first_line.len()..]);
+    let mut block = vec![];
+    for line in input[offset..].lines() {
+        offset += line.len() + line_ending_len(&input[offset + line.len()..]);
+        if line.trim_end() == fence || (matches!(flavor, Flavor::Yaml) && line.trim_end() == "...") {
+            let lines = block.len() + 2;
+            return Some((parse_block(&block, flavor), &input[offset..], lines));
+        }
+        block.push(line);
+    }
+
+    None
+}
+
+fn line_ending_len(rest: &str)-> usize {
+    if rest.starts_with("\r\n") {
+        2
+    } else if rest.starts_with('\n') {
+        1
+    } else {
+        0
+    }
+}
+
+fn parse_block(lines: &[&str], flavor: Flavor)-> Metadata {
+    let mut metadata = Metadata::default();
+    let separator = match flavor {
+        Flavor::Yaml => ':',
+        Flavor::Toml => '=',
+    };
+    let mut pending_list: Option<(String, Vec<String>)> = None;
+
+    for line in lines {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        // YAML block sequences list their items on the following lines
+        if let (Some((_, items)), Some(item)) = (pending_list.as_mut(), trimmed.strip_prefix("- ")) {
+            items.push(unquote(item.trim()));
+            continue;
+        }
+        if let Some((key, items)) = pending_list.take() {
+            insert_list(&mut metadata, key, items);
+        }
+
+        let Some((key, value)) = trimmed.split_once(separator) else { continue };
+        let (key, value) = (key.trim().to_owned(), value.trim());
+
+        if value.is_empty() && matches!(flavor, Flavor::Yaml) {
+            pending_list = Some((key, vec![]));
+        } else if value.starts_with('[') && value.ends_with(']') {
+            let items = value[1..value.len()-1].split(',')
+                .map(|item| unquote(item.trim()))
+                .filter(|item| !item.is_empty())
+                .collect();
+            metadata.insert(key, MetaValue::List(items));
+        } else {
+            metadata.insert(key, MetaValue::Text(unquote(value)));
+        }
+    }
+
+    if let Some((key, items)) = pending_list {
+        insert_list(&mut metadata, key, items);
+    }
+
+    metadata
+}
+
+/// A YAML key with no inline value is a list only if items followed it
+fn insert_list(metadata: &mut Metadata, key: String, items: Vec<String>) {
+    match items.is_empty() {
+        true => metadata.insert(key, MetaValue::Text(String::new())),
+        false => metadata.insert(key, MetaValue::List(items)),
+    }
+}
+
+fn unquote(value: &str)-> String {
+    for quote in ['"', '\''] {
+        if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+            return value[1..value.len()-1].to_owned();
+        }
+    }
+    value.to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(value: &str)-> Option<MetaValue> {
+        Some(MetaValue::Text(value.to_owned()))
+    }
+
+    fn list(items: &[&str])-> Option<MetaValue> {
+        Some(MetaValue::List(items.iter().map(|item| item.to_string()).collect()))
+    }
+
+    #[test]
+    fn reads_yaml_front_matter() {
+        let input = "---\ntitle: \"Guide: part 1\"\n# comment\ntags: [rust, 'docs']\nauthors:\n  - Ana\n  - Bo\ndraft:\n---\n# Body\n";
+        let (metadata, body, lines) = extract_front_matter(input).unwrap();
+        assert_eq!(metadata.title().as_deref(), Some("Guide: part 1"));
+        assert_eq!(metadata.get("tags").cloned(), list(&["rust", "docs"]));
+        assert_eq!(metadata.get("authors").cloned(), list(&["Ana", "Bo"]));
+        assert_eq!(metadata.get("draft").cloned(), text(""));
+        assert_eq!((body, lines), ("# Body\n", 9));
+    }
+
+    #[test]
+    fn reads_toml_front_matter() {
+        let (metadata, body, lines) = extract_front_matter("+++\r\ntitle = 'Notes'\r\nweight = 3\r\n+++\r\nText").unwrap();
+        assert_eq!(metadata.get("title").cloned(), text("Notes"));
+        assert_eq!(metadata.get("weight").cloned(), text("3"));
+        assert_eq!((body, lines), ("Text", 4));
+    }
+
+    #[test]
+    fn needs_a_fence_on_the_first_line_and_a_closing_one() {
+        assert!(extract_front_matter("\n---\ntitle: a\n---\n").is_none());
+        assert!(extract_front_matter("---\ntitle: a\n").is_none());
+        assert!(extract_front_matter("---\ntitle: a\n+++\n").is_none());
+        assert!(extract_front_matter("---\ntitle: a\n...\nText").is_some_and(|(_, body, _)| body == "Text"));
+    }
+
+    #[test]
+    fn later_keys_replace_earlier_ones() {
+        let (metadata, _, _) = extract_front_matter("---\ntitle: a\ntitle: b\n---\n").unwrap();
+        assert_eq!(metadata.iter().count(), 1);
+        assert_eq!(metadata.title().as_deref(), Some("b"));
+    }
+}
@@ -0,0 +1,322 @@
+use crate::escape_html;
+
+#[derive(Debug, Clone)]
+/// A raw html tag or comment found in the source
+pub struct HtmlTag {
+    /// The tag exactly as written, like ```<kbd class="key">```
+    pub raw: String,
+    /// Lowercased tag name, ```!--``` for comments
+    pub name: String,
+    pub closing: bool
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+/// What to do with raw html that isn't on the allowlist
+pub enum HtmlPolicy {
+    /// Emit it as is, for trusted documents
+    #[default]
+    Passthrough,
+    /// Emit it as visible text
+    Escape,
+    /// Drop the tags, keeping the text between them
+    Strip,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HtmlOptions {
+    pub policy: HtmlPolicy,
+    /// Tag names that are always kept whatever the policy, like ```kbd``` or ```sup```
+    ///
+    /// Outside of passthrough an allowed tag is rebuilt with the ```SAFE_ATTRIBUTES``` only,
+    /// and its links only when they use one of the ```SAFE_SCHEMES```
+    pub allowed_tags: Vec<String>,
+}
+
+impl HtmlOptions {
+    fn allows(&self, tag: &HtmlTag)-> bool {
+        self.allowed_tags.iter().any(|name| name.eq_ignore_ascii_case(&tag.name))
+    }
+}
+
+/// Attributes an allowed tag keeps when the html isn't passed through, none of them able to run script
+pub const SAFE_ATTRIBUTES: &[&str] = &[
+    "abbr", "align", "alt", "cite", "class", "colspan", "datetime", "dir", "headers", "height", "href",
+    "id", "lang", "name", "open", "rowspan", "scope", "span", "src", "start", "title", "type", "width",
+];
+
+/// Schemes an ```href```, ```src``` or ```cite``` of an allowed tag may use, relative urls being always safe
+pub const SAFE_SCHEMES: &[&str] = &["http", "https", "mailto", "tel"];
+
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "cite"];
+
+const BLOCK_TAGS: &[&str] = &[
+    "!--", "address", "article", "aside", "audio", "blockquote", "canvas", "details", "dialog", "div",
+    "dl", "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6",
+    "header", "hr", "iframe", "li", "main", "nav", "noscript", "ol", "p", "pre", "script", "section",
+    "style", "summary", "table", "tbody", "td", "template", "tfoot", "th", "thead", "tr", "ul", "video",
+];
+
+/// Whether the tag starts an html block rather than flowing inline with the text
+pub fn is_block_tag(tag: &HtmlTag)-> bool {
+    BLOCK_TAGS.contains(&tag.name.as_str())
+}
+
+/// Looks ahead from a ```<``` for an html tag (```<tag attr="value">```, ```</tag>```, ```<tag/>```)
+/// or a ```<!-- comment -->```
+///
+/// Returns the tag together with the number of characters it spans
+pub fn scan_html_tag(content: &str)-> Option<(HtmlTag, usize)> {
+    let rest = content.strip_prefix('<')?;
+
+    if rest.starts_with("!--") {
+        let end = rest.find("-->")?;
+        let raw = &content[..end + 4];
+        return Some((HtmlTag { raw: raw.to_owned(), name: String::from("!--"), closing: false }, raw.chars().count()));
+    }
+
+    let (closing, rest) = match rest.strip_prefix('/') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    if !rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let name_len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '-')).unwrap_or(rest.len());
+    let name = rest[..name_len].to_ascii_lowercase();
+
+    let mut quote: Option<char> = None;
+    let mut end = None;
+    for (index, c) in rest[name_len..].char_indices() {
+        if index == 0 && !(c.is_whitespace() || c == '>' || c == '/') {
+            return None;
+        }
+        match quote {
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' if !closing => quote = Some(c),
+                '>' => {
+                    end = Some(name_len + index);
+                    break;
+                }
+                '<' | '\n' => return None,
+                _ => {}
+            },
+        }
+    }
+
+    let raw_len = content.len() - rest.len() + end? + 1;
+    let raw = &content[..raw_len];
+    Some((HtmlTag { raw: raw.to_owned(), name, closing }, raw.chars().count()))
+}
+
+/// Renders a single tag according to the html options
+pub fn html_tag_parser(tag: &HtmlTag, options: &HtmlOptions)-> String {
+    match options.policy {
+        HtmlPolicy::Passthrough => tag.raw.clone(),
+        _ if options.allows(tag) => sanitized_tag(tag),
+        HtmlPolicy::Escape => escape_html(&tag.raw),
+        HtmlPolicy::Strip => String::new(),
+    }
+}
+
+/// Rebuilds an allowed tag from its name and ```SAFE_ATTRIBUTES```, so that ```<kbd onclick=...>```
+/// or ```<a href="javascript:...">``` lose what could run script
+fn sanitized_tag(tag: &HtmlTag)-> String {
+    if tag.name == "!--" {
+        return tag.raw.clone();
+    }
+    if tag.closing {
+        return format!("</{}>", tag.name);
+    }
+
+    let mut sanitized = format!("<{}", tag.name);
+    for (name, value) in tag_attributes(&tag.raw) {
+        if !SAFE_ATTRIBUTES.contains(&name.as_str()) {
+            continue;
+        }
+        match value {
+            Some(value) if URL_ATTRIBUTES.contains(&name.as_str()) && !is_safe_url(&value) => continue,
+            Some(value) => sanitized.push_str(&format!(" {}=\"{}\"", name, escape_text(&value).replace('"', "&quot;"))),
+            None => sanitized.push_str(&format!(" {}", name)),
+        }
+    }
+    sanitized.push('>');
+    sanitized
+}
+
+/// The attributes of a raw opening tag as lowercased names with their unquoted values
+fn tag_attributes(raw: &str)-> Vec<(String, Option<String>)> {
+    let rest = raw.trim_start_matches('<').trim_end_matches('>');
+    let mut chars = rest.chars().skip_while(|c| !c.is_whitespace()).peekable();
+    let mut attributes = vec![];
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace() || *c == '/').is_some() {}
+        let mut name = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !matches!(c, '=' | '/')) {
+            name.push(c.to_ascii_lowercase());
+        }
+        if name.is_empty() {
+            break;
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.next_if_eq(&'=').is_none() {
+            attributes.push((name, None));
+            continue;
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let mut value = String::new();
+        match chars.next_if(|c| matches!(c, '"' | '\'')) {
+            Some(quote) => {
+                for c in chars.by_ref() {
+                    if c == quote {
+                        break;
+                    }
+                    value.push(c);
+                }
+            }
+            None => {
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    value.push(c);
+                }
+            }
+        }
+        attributes.push((name, Some(value)));
+    }
+    attributes
+}
+
+/// Whether the url is relative or uses one of the ```SAFE_SCHEMES```
+///
+/// Browsers drop tabs and newlines from urls and decode entities in attributes, so the scheme is
+/// read without them and an entity before the end of the scheme makes the url unsafe
+fn is_safe_url(url: &str)-> bool {
+    let scheme_end = url.find(['/', '?', '#']).unwrap_or(url.len());
+    let scheme: String = url[..scheme_end].chars().filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control()).collect();
+    if scheme.contains('&') {
+        return false;
+    }
+    match scheme.split_once(':') {
+        Some((scheme, _)) => SAFE_SCHEMES.iter().any(|safe| safe.eq_ignore_ascii_case(scheme)),
+        None => true,
+    }
+}
+
+/// Renders an html block, applying the options to each of its tags
+///
+/// Text between the tags is kept as is when the whole block passes, escaped otherwise
+pub fn html_block_parser(raw: &str, options: &HtmlOptions)-> String {
+    let mut html = String::new();
+    let mut rest = raw;
+
+    while !rest.is_empty() {
+        let text_len = rest.find('<').unwrap_or(rest.len());
+        let (text, after) = rest.split_at(text_len);
+        match options.policy {
+            HtmlPolicy::Passthrough => html.push_str(text),
+            HtmlPolicy::Escape | HtmlPolicy::Strip => html.push_str(&escape_text(text)),
+        }
+        rest = after;
+        if rest.is_empty() {
+            break;
+        }
+
+        match scan_html_tag(rest) {
+            Some((tag, _)) => {
+                html.push_str(&html_tag_parser(&tag, options));
+                rest = &rest[tag.raw.len()..];
+            }
+            None => {
+                match options.policy {
+                    HtmlPolicy::Passthrough => html.push('<'),
+                    HtmlPolicy::Escape | HtmlPolicy::Strip => html.push_str("&lt;"),
+                }
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    html
+}
+
+/// Escapes ```<```, ```>``` and ```&``` in document text, leaving entity references like
+/// ```&amp;``` or ```&#169;``` written in the source untouched
+pub fn escape_text(text: &str)-> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (index, c) in text.char_indices() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' if !starts_with_entity(&text[index..]) => escaped.push_str("&amp;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn starts_with_entity(text: &str)-> bool {
+    let Some(end) = text.find(';') else { return false };
+    let name = &text[1..end];
+    match name.strip_prefix('#') {
+        Some(number) => match number.strip_prefix(['x', 'X']) {
+            Some(hex) => !hex.is_empty() && hex.len() <= 6 && hex.chars().all(|c| c.is_ascii_hexdigit()),
+            None => !number.is_empty() && number.len() <= 7 && number.chars().all(|c| c.is_ascii_digit()),
+        },
+        None => !name.is_empty() && name.len() <= 32 && name.chars().all(|c| c.is_ascii_alphanumeric()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(raw: &str)-> HtmlTag {
+        scan_html_tag(raw).unwrap().0
+    }
+
+    fn options(policy: HtmlPolicy, allowed: &[&str])-> HtmlOptions {
+        HtmlOptions { policy, allowed_tags: allowed.iter().map(|name| name.to_string()).collect() }
+    }
+
+    #[test]
+    fn scans_tags_and_comments() {
+        let (kbd, length) = scan_html_tag("<kbd class=\"a>b\">Ctrl").unwrap();
+        assert_eq!((kbd.name.as_str(), kbd.closing, length), ("kbd", false, 17));
+        assert!(tag("</KBD>").closing);
+        assert_eq!(tag("<!-- note -->").name, "!--");
+        assert!(scan_html_tag("< kbd>").is_none());
+        assert!(scan_html_tag("<3 and more").is_none());
+    }
+
+    #[test]
+    fn applies_the_policy() {
+        let kbd = tag("<kbd>");
+        assert_eq!(html_tag_parser(&kbd, &options(HtmlPolicy::Passthrough, &[])), "<kbd>");
+        assert_eq!(html_tag_parser(&kbd, &options(HtmlPolicy::Escape, &[])), "&lt;kbd&gt;");
+        assert_eq!(html_tag_parser(&kbd, &options(HtmlPolicy::Strip, &[])), "");
+        assert_eq!(html_block_parser("<div>a & b</div>", &options(HtmlPolicy::Strip, &[])), "a &amp; b");
+    }
+
+    #[test]
+    fn allowed_tags_lose_event_handlers() {
+        let escape = options(HtmlPolicy::Escape, &["kbd"]);
+        assert_eq!(html_tag_parser(&tag("<kbd onmouseover=alert(1) class=\"key\">"), &escape), "<kbd class=\"key\">");
+        assert_eq!(html_tag_parser(&tag("</kbd>"), &escape), "</kbd>");
+        assert_eq!(html_tag_parser(&tag("<KBD STYLE='x' Title='a \"b\"'>"), &escape), "<kbd title=\"a &quot;b&quot;\">");
+    }
+
+    #[test]
+    fn allowed_links_keep_safe_schemes_only() {
+        let strip = options(HtmlPolicy::Strip, &["a"]);
+        let link = |raw: &str| html_tag_parser(&tag(raw), &strip);
+        assert_eq!(link("<a href=\"javascript:alert(1)\">"), "<a>");
+        assert_eq!(link("<a href=\"jav&#x61;script:alert(1)\">"), "<a>");
+        assert_eq!(link("<a href=\" JavaScript:alert(1)\">"), "<a>");
+        assert_eq!(link("<a href=\"data:text/html,x\">"), "<a>");
+        assert_eq!(link("<a href=\"https://example.com/?a=1&amp;b=2\">"), "<a href=\"https://example.com/?a=1&amp;b=2\">");
+        assert_eq!(link("<a href=/docs/>"), "<a href=\"/docs/\">");
+        assert_eq!(html_tag_parser(&tag("<img src=a.png onerror=x />"), &options(HtmlPolicy::Escape, &["img"])), "<img src=\"a.png\">");
+    }
+}
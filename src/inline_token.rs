@@ -1,5 +1,8 @@
 use std::iter::Peekable;
 
+use crate::html::{scan_html_tag, HtmlTag};
+use crate::TokenizerOptions;
+
 #[derive(Debug, Clone)]
 /// Specifies whether the inline token is a close or open token on the given formating
 pub enum InlineTokenPos {
@@ -8,16 +11,66 @@ pub enum InlineTokenPos {
     None
 }
 
+#[derive(Debug, Clone)]
+/// Where a ```Link``` token points to
+pub enum LinkTarget {
+    /// On page reference as in ```[text][id-ref]```, resolved against heading ids
+    Reference(String),
+    /// Full address, like the ```http://``` or ```mailto:``` href of an autolink
+    Url(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct LinkToken {
+    pub text: String,
+    pub target: LinkTarget
+}
+
+impl LinkToken {
+    /// Returns the value for the ```href``` attribute of the rendered link
+    pub fn href(&self)-> String {
+        match &self.target {
+            LinkTarget::Reference(id) => format!("#{id}"),
+            LinkTarget::Url(url) => url.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A ```[^label]``` footnote reference
+///
+/// ```number``` and ```occurrence``` are resolved once the whole document is tokenized;
+/// ```number``` stays ```None``` for labels with no ```[^label]: note``` definition
+pub struct FootnoteRef {
+    pub label: String,
+    pub number: Option<usize>,
+    /// Which reference to the same footnote this is, starting at 1
+    pub occurrence: usize
+}
+
+#[derive(Debug, Clone)]
+/// A formula kept verbatim, ```$inline$``` or ```$$display$$```
+pub struct MathToken {
+    pub formula: String,
+    pub display: bool
+}
+
 #[derive(Debug, Clone)]
 pub enum InlineToken {
-    Text(String), Link(String), Emoji(InlineTokenPos),
+    Text(String), Link(LinkToken), Emoji(InlineTokenPos),
+    FootnoteRef(FootnoteRef), Math(MathToken),
+    Html(HtmlTag), HardBreak,
     Code(InlineTokenPos), Bold(InlineTokenPos),
     Strike(InlineTokenPos), Italic(InlineTokenPos),
     Caption(InlineTokenPos), Underline(InlineTokenPos),
-    Highlight(InlineTokenPos),
+    /// ```==text==``` or ```==(color)text==```, the color repeated on the close token
+    Highlight { color: String, pos: InlineTokenPos },
     Size(), Color(), Family(),
 }
 
+/// Background of a highlight written without a ```(color)```
+pub const DEFAULT_HIGHLIGHT_COLOR: &str = "yellow";
+
 impl InlineToken {
     ///Returns contained variant(```InlineTokenPos::Open``` or ```InlineTokenPos::Close```) of subjected token
     pub fn get_pos(&self)-> &InlineTokenPos {
@@ -25,7 +78,7 @@ impl InlineToken {
             InlineToken::Code(pos) | InlineToken::Bold(pos) |
             InlineToken::Strike(pos) | InlineToken::Italic(pos) |
             InlineToken::Caption(pos) | InlineToken::Underline(pos) |
-            InlineToken::Highlight(pos) => pos,
+            InlineToken::Highlight { pos, .. } => pos,
             _ => &InlineTokenPos::None
         }
     }
@@ -38,8 +91,10 @@ impl InlineToken {
             InlineToken::Size() => InlineId::Size, InlineToken::Color() => InlineId::Color,
             InlineToken::Family() => InlineId::Family, InlineToken::Strike(_) => InlineId::Strike,
             InlineToken::Italic(_) => InlineId::Italic, InlineToken::Caption(_) => InlineId::Caption,
-            InlineToken::Underline(_) => InlineId::Underline, InlineToken::Highlight(_) => InlineId::Highlight,
-            InlineToken::Emoji(_) => InlineId::Emoji
+            InlineToken::Underline(_) => InlineId::Underline, InlineToken::Highlight { .. } => InlineId::Highlight,
+            InlineToken::Emoji(_) => InlineId::Emoji, InlineToken::FootnoteRef(_) => InlineId::FootnoteRef,
+            InlineToken::Math(_) => InlineId::Math, InlineToken::Html(_) => InlineId::Html,
+            InlineToken::HardBreak => InlineId::HardBreak
         }
     }
 }
@@ -51,7 +106,8 @@ pub enum InlineId {
     Link, Size, Color,
     Family, Strike, Italic,
     Caption, Underline, Emoji,
-    Highlight
+    Highlight, FootnoteRef, Math,
+    Html, HardBreak
 }
 
 /// A specifier type for distinguishing between ```Text``` token and other **Inline** tokens
@@ -72,7 +128,9 @@ pub enum RuleResult{
 /// 
 /// Forexample;
 /// ```
-/// let text = id2text_format(InlineId::Italic, 2);
+/// # use markdownit::inline_token::{id2text_format, InlineId};
+/// let text = id2text_format(&InlineId::Italic, 2);
+/// # assert_eq!(text, "__");
 /// ```
 /// text would be **"__"**
 pub fn id2text_format(id: &InlineId, count: usize)-> String {
@@ -86,7 +144,8 @@ pub fn id2text_format(id: &InlineId, count: usize)-> String {
         InlineId::Emoji => String::from(":".repeat(count)),
 
         // TODO: Implement with corresponding identifier text for font style and linkd
-        InlineId::Link => String::from("".repeat(count)),
+        InlineId::Math => "$".repeat(count),
+        InlineId::Link | InlineId::FootnoteRef | InlineId::Html | InlineId::HardBreak => String::new(),
         InlineId::Size | InlineId::Color | InlineId::Family => String::from("".repeat(count)),
     }
 }
@@ -208,7 +267,7 @@ pub fn nxt_is_whitespace_rule(stack: &mut Vec<InlineToken>, content_chars: &mut
                     return;
                 }
                 match content.clone().chars().nth(content.clone().chars().count()-1).unwrap() {
-                    ' ' => {
+                    ' ' | '\n' => {
                         cxt_stack_push(&cxt, stack, Push::Text(id2text_format(&cxt, movements)));
                     },
                     // If anything else except whitespace, check for open bold
@@ -226,6 +285,15 @@ pub fn nxt_is_whitespace_rule(stack: &mut Vec<InlineToken>, content_chars: &mut
                     }
                 }
             }
+            // Links, math and footnote references end in text so they can be closed over
+            InlineToken::Link(_) | InlineToken::Math(_) | InlineToken::FootnoteRef(_) | InlineToken::Html(_) => {
+                let mut rev_stack = stack.clone();
+                rev_stack.reverse();
+                match open_cxt_checkup(&mut rev_stack, &cxt) {
+                    true => cxt_stack_push(&cxt, stack, Push::Other(InlineTokenPos::Close)),
+                    false => cxt_stack_push(&cxt, stack, Push::Text(id2text_format(&cxt, movements))),
+                }
+            }
             _ => {
                 let mut rev_stack = stack.clone();
                 rev_stack.reverse();
@@ -255,7 +323,7 @@ pub fn nxt_is_whitespace_rule(stack: &mut Vec<InlineToken>, content_chars: &mut
 /// - ```close_possibility``` specifies whether this is possibly a close token if the stack top value is text with
 /// the last character not whitespace or is a close token too
 pub fn nxt_non_whitespace_rule<'a>(stack: &mut Vec<InlineToken>, buffer: &mut String, cxt: &'a InlineId, movements: usize, close_possibility: bool)->RuleResult {
-    let mut can_nest = [InlineId::Bold, InlineId::Caption, InlineId::Italic, InlineId::Underline, InlineId::Strike];
+    let mut can_nest = [InlineId::Bold, InlineId::Caption, InlineId::Italic, InlineId::Underline, InlineId::Strike, InlineId::Highlight];
     for (i, id) in can_nest.iter().enumerate() {
         if id == cxt {
             can_nest[i] = InlineId::Text;
@@ -283,7 +351,7 @@ pub fn nxt_non_whitespace_rule<'a>(stack: &mut Vec<InlineToken>, buffer: &mut St
                     return RuleResult::Failure;
                 }
                 match content.clone().chars().nth(content.clone().chars().count()-1).unwrap() {
-                    ' '=> {
+                    ' ' | '\n' => {
                         cxt_stack_push(cxt, stack, Push::Other(InlineTokenPos::Open));
                         return RuleResult::Failure;
                     },
@@ -298,6 +366,12 @@ pub fn nxt_non_whitespace_rule<'a>(stack: &mut Vec<InlineToken>, buffer: &mut St
                 }
             },
 
+            // Formatting may open right after a tag like ```<kbd>*Ctrl*</kbd>``` or a hard break
+            InlineToken::Html(HtmlTag { closing: false, .. }) | InlineToken::HardBreak => {
+                cxt_stack_push(cxt, stack, Push::Other(InlineTokenPos::Open));
+                RuleResult::Failure
+            },
+
             nest if can_nest.contains(&nest.get_id()) => {
                 match nest.get_pos() {
                     InlineTokenPos::Open => {
@@ -309,6 +383,13 @@ pub fn nxt_non_whitespace_rule<'a>(stack: &mut Vec<InlineToken>, buffer: &mut St
                             cxt_stack_push(cxt, stack, Push::Other(InlineTokenPos::Close));
                             return RuleResult::Success;
                         }
+                        // Formatting may open right after another one closes, as in ```==note==*bold*```
+                        let mut rev_stack = stack.clone();
+                        rev_stack.reverse();
+                        if !open_cxt_checkup(&mut rev_stack, cxt) {
+                            cxt_stack_push(cxt, stack, Push::Other(InlineTokenPos::Open));
+                            return RuleResult::Failure;
+                        }
                         cxt_stack_push(cxt, stack, Push::Text(id2text_format(&cxt, movements)));
                         return RuleResult::Failure;
                     },
@@ -335,7 +416,7 @@ pub fn nxt_non_whitespace_rule<'a>(stack: &mut Vec<InlineToken>, buffer: &mut St
 /// - ```cxt``` which is the context of type ```InlineId``` with a lifetime ```'a``` specifying the tokenization context
 /// - ```movements``` this specifies the steps from top tokenization the anlyser has moved (consumed characters)
 pub fn is_last_char_rule<'a>(stack: &mut Vec<InlineToken>, buffer: &mut String, cxt: &'a InlineId, movements: usize) {
-    let mut can_nest = [InlineId::Bold, InlineId::Caption, InlineId::Italic, InlineId::Underline, InlineId::Strike];
+    let mut can_nest = [InlineId::Bold, InlineId::Caption, InlineId::Italic, InlineId::Underline, InlineId::Strike, InlineId::Highlight];
     for (i, id) in can_nest.iter().enumerate() {
         if id == cxt {
             can_nest[i] = InlineId::Text;
@@ -359,7 +440,7 @@ pub fn is_last_char_rule<'a>(stack: &mut Vec<InlineToken>, buffer: &mut String,
         match token {
             InlineToken::Text(content) => {
                 match content.clone().chars().nth(content.chars().count()-1).unwrap() {
-                    ' ' => {
+                    ' ' | '\n' => {
                         cxt_stack_push(cxt, stack, Push::Text(id2text_format(&cxt, movements)));
                         return;
                     }
@@ -377,6 +458,15 @@ pub fn is_last_char_rule<'a>(stack: &mut Vec<InlineToken>, buffer: &mut String,
                 }
             },
 
+            InlineToken::Link(_) | InlineToken::Math(_) | InlineToken::FootnoteRef(_) | InlineToken::Html(_) => {
+                let mut reverse_stack = stack.clone();
+                reverse_stack.reverse();
+                match open_cxt_checkup(&mut reverse_stack, cxt) {
+                    true => cxt_stack_push(cxt, stack, Push::Other(InlineTokenPos::Close)),
+                    false => cxt_stack_push(cxt, stack, Push::Text(id2text_format(cxt, movements))),
+                }
+            },
+
             nest if can_nest.contains(&nest.get_id()) => {
                 match nest.get_pos() {
                     InlineTokenPos::Open => cxt_stack_push(cxt, stack, Push::Text(id2text_format(&cxt, movements))),
@@ -394,19 +484,403 @@ pub fn is_last_char_rule<'a>(stack: &mut Vec<InlineToken>, buffer: &mut String,
     }
 }
 
+/// Collects the readable text of the given tokens, dropping any formatting
+///
+/// Used wherever a plain string is needed out of formatted content, like heading ids
+pub fn plain_text(tokens: &[InlineToken])-> String {
+    let mut text = String::new();
+    for token in tokens {
+        match token {
+            InlineToken::Text(content) => text.push_str(content),
+            InlineToken::Link(link) => text.push_str(&link.text),
+            InlineToken::HardBreak => text.push('\n'),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Whether the next character starts a new word, autolinks are only detected at word starts
+fn at_word_start(stack: &[InlineToken], buffer: &str)-> bool {
+    let previous = match buffer.chars().last() {
+        Some(c) => Some(c),
+        None => match stack.last() {
+            Some(InlineToken::Text(content)) => content.chars().last(),
+            _ => None,
+        },
+    };
+    !previous.is_some_and(|c| c.is_alphanumeric() || matches!(c, '.' | '@' | '/' | '-' | '_' | '+'))
+}
+
+/// Looks ahead for a bare ```http(s)://``` or ```www.``` url or an email address
+///
+/// Trailing punctuation and unbalanced closing parentheses are left out of the link, so
+/// ```(see https://example.com/a_b).``` links ```https://example.com/a_b``` only. Returns the
+/// link together with the number of characters it spans
+pub fn scan_autolink(content_chars: Peekable<std::str::Chars<'_>>)-> Option<(LinkToken, usize)> {
+    let rest: String = content_chars.take_while(|c| !c.is_whitespace() && !matches!(c, '<' | '>' | '"' | '`')).collect();
+
+    let is_url = ["http://", "https://", "www."].iter().any(|scheme| {
+        rest.len() > scheme.len() && rest.get(..scheme.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme))
+    });
+    if is_url {
+        let mut url = rest.as_str();
+        loop {
+            let trimmed = url.trim_end_matches(['.', ',', ':', ';', '!', '?', '\'', '"', '*', '_', '~']);
+            let trimmed = match trimmed.ends_with(')') && trimmed.matches(')').count() > trimmed.matches('(').count() {
+                true => &trimmed[..trimmed.len()-1],
+                false => trimmed,
+            };
+            if trimmed.len() == url.len() {
+                break;
+            }
+            url = trimmed;
+        }
+
+        let host = url.split_once("://").map_or(url, |(_, host)| host);
+        if host.trim_start_matches("www.").is_empty() {
+            return None;
+        }
+        let href = match url.to_ascii_lowercase().starts_with("www.") {
+            true => format!("http://{url}"),
+            false => url.to_owned(),
+        };
+        return Some((LinkToken { text: url.to_owned(), target: LinkTarget::Url(href) }, url.chars().count()));
+    }
+
+    let (local, domain) = rest.split_once('@')?;
+    let domain = domain.trim_end_matches(|c: char| !c.is_ascii_alphanumeric());
+    let valid_local = !local.is_empty() && local.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '%' | '+' | '-'));
+    let labels: Vec<&str> = domain.split('.').collect();
+    let valid_domain = labels.len() > 1 && labels.iter().all(|label| {
+        !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    });
+    if !valid_local || !valid_domain {
+        return None;
+    }
+
+    let address = format!("{local}@{domain}");
+    let consumed = address.chars().count();
+    Some((LinkToken { text: address.clone(), target: LinkTarget::Url(format!("mailto:{address}")) }, consumed))
+}
+
+/// Looks ahead from an opening ```$``` for a ```$formula$``` or ```$$formula$$```
+///
+/// An inline formula may not start or end with whitespace and its closing ```$``` may not be
+/// followed by a digit, so amounts like ```$5 and $10``` stay text. A ```\$``` inside the
+/// formula doesn't close it. Returns the formula together with the number of characters it spans
+pub fn scan_math(content_chars: Peekable<std::str::Chars<'_>>)-> Option<(MathToken, usize)> {
+    let chars: Vec<char> = content_chars.collect();
+    let display = chars.get(1) == Some(&'$');
+    let fence = if display { 2 } else { 1 };
+
+    let mut index = fence;
+    while index < chars.len() {
+        match chars[index] {
+            '\\' => index += 2,
+            '$' => break,
+            _ => index += 1,
+        }
+    }
+    if index >= chars.len() || index == fence {
+        return None;
+    }
+
+    let formula: String = chars[fence..index].iter().collect();
+    if display {
+        if chars.get(index + 1) != Some(&'$') {
+            return None;
+        }
+    } else if formula.starts_with(char::is_whitespace) || formula.ends_with(char::is_whitespace)
+        || chars.get(index + 1).is_some_and(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let consumed = index + fence;
+    Some((MathToken { formula: formula.trim().to_owned(), display }, consumed))
+}
+
+/// Looks ahead from an opening ```==``` for a ```==text==``` or ```==(color)text==``` highlight
+///
+/// The color is a name or a ```#hex``` value, ```DEFAULT_HIGHLIGHT_COLOR``` when left out. The text may
+/// run over soft line breaks but may not start or end with whitespace. Returns the color and text
+/// together with the number of characters the highlight spans
+pub fn scan_highlight(content_chars: Peekable<std::str::Chars<'_>>)-> Option<(String, String, usize)> {
+    let chars: Vec<char> = content_chars.collect();
+    if chars.get(..2) != Some(&['=', '=']) {
+        return None;
+    }
+
+    let mut start = 2;
+    let mut color = DEFAULT_HIGHLIGHT_COLOR.to_owned();
+    if chars.get(start) == Some(&'(') {
+        if let Some(end) = chars[start..].iter().position(|c| *c == ')') {
+            let parameter: String = chars[start + 1..start + end].iter().collect();
+            if is_color(parameter.trim()) {
+                color = parameter.trim().to_owned();
+                start += end + 1;
+            }
+        }
+    }
+    if chars.get(start).is_none_or(|c| c.is_whitespace() || *c == '=') {
+        return None;
+    }
+
+    let mut index = start + 1;
+    while index + 1 < chars.len() {
+        if chars[index] == '=' && chars[index + 1] == '=' && !chars[index - 1].is_whitespace() {
+            let text = chars[start..index].iter().collect();
+            return Some((color, text, index + 2));
+        }
+        index += 1;
+    }
+    None
+}
+
+/// Looks ahead from a single backtick for a ```` `code` ```` span
+///
+/// Returns the code together with the number of characters the span spans, ```None``` for a
+/// double backtick or a backtick closing nothing
+pub fn scan_code_span(content_chars: Peekable<std::str::Chars<'_>>)-> Option<(String, usize)> {
+    let chars: Vec<char> = content_chars.collect();
+    if chars.first() != Some(&'`') || chars.get(1).is_none_or(|c| *c == '`') {
+        return None;
+    }
+    let end = chars[1..].iter().position(|c| *c == '`')? + 1;
+    Some((chars[1..end].iter().collect(), end + 1))
+}
+
+/// Turns formatting tokens left without their open or close counterpart back into the text they were written as
+fn unmatched_to_text(tokens: &mut [InlineToken]) {
+    let mut unmatched: Vec<usize> = vec![];
+    for index in 0..tokens.len() {
+        match tokens[index].get_pos() {
+            InlineTokenPos::Open => unmatched.push(index),
+            InlineTokenPos::Close => {
+                let id = tokens[index].get_id();
+                let open = unmatched.iter().rposition(|open| {
+                    matches!(tokens[*open].get_pos(), InlineTokenPos::Open) && tokens[*open].get_id() == id
+                });
+                match open {
+                    Some(position) => {
+                        unmatched.remove(position);
+                    }
+                    None => unmatched.push(index),
+                }
+            }
+            InlineTokenPos::None => {}
+        }
+    }
+
+    for index in unmatched {
+        let id = tokens[index].get_id();
+        let count = match id {
+            InlineId::Code | InlineId::Caption | InlineId::Underline | InlineId::Highlight => 2,
+            _ => 1,
+        };
+        tokens[index] = InlineToken::Text(id2text_format(&id, count));
+    }
+}
+
+/// Whether a highlight color is safe to place in a style attribute, a name like ```red``` or a ```#hex``` value
+fn is_color(color: &str)-> bool {
+    match color.strip_prefix('#') {
+        Some(hex) => matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => !color.is_empty() && color.chars().all(|c| c.is_ascii_alphabetic()),
+    }
+}
+
+/// Looks ahead from an opening ```[``` for a ```[^label]``` footnote reference
+///
+/// Returns the label together with the number of characters the reference spans
+pub fn scan_footnote_ref(mut content_chars: Peekable<std::str::Chars<'_>>)-> Option<(String, usize)> {
+    let mut label = String::new();
+
+    content_chars.next()?;
+    if content_chars.next()? != '^' {
+        return None;
+    }
+    loop {
+        match content_chars.next()? {
+            ']' => break,
+            c if c.is_whitespace() || c == '[' || c == '^' => return None,
+            c => label.push(c),
+        }
+    }
+
+    match label.is_empty() {
+        true => None,
+        false => Some((label.clone(), label.chars().count() + 3)),
+    }
+}
+
+/// Looks ahead from an opening ```[``` for an on page reference link ```[text][id-ref]```
+///
+/// Returns the link together with the number of characters it spans, or ```None``` when
+/// the characters ahead don't form a reference link
+pub fn scan_reference_link(mut content_chars: Peekable<std::str::Chars<'_>>)-> Option<(LinkToken, usize)> {
+    let mut text = String::new();
+    let mut reference = String::new();
+
+    content_chars.next()?;
+    let mut consumed = 1;
+    loop {
+        match content_chars.next()? {
+            ']' => break,
+            '[' => return None,
+            c => text.push(c),
+        }
+        consumed += 1;
+    }
+    consumed += 1;
+
+    if content_chars.next()? != '[' {
+        return None;
+    }
+    consumed += 1;
+    loop {
+        match content_chars.next()? {
+            ']' => break,
+            c if c.is_whitespace() || c == '[' => return None,
+            c => reference.push(c),
+        }
+        consumed += 1;
+    }
+    consumed += 1;
+
+    if text.trim().is_empty() || reference.is_empty() {
+        return None;
+    }
+    Some((LinkToken { text, target: LinkTarget::Reference(reference) }, consumed))
+}
+
+/// Looks ahead from an opening ```[``` for an inline link ```[text](url)```
+///
+/// The url may hold balanced parentheses but no whitespace, and ```javascript:```, ```vbscript:``` and
+/// ```data:``` urls are refused, control characters browsers would skip included. Inline links are
+/// read in every document, not only site pages. Returns the link together with the number of
+/// characters it spans
+pub fn scan_inline_link(mut content_chars: Peekable<std::str::Chars<'_>>)-> Option<(LinkToken, usize)> {
+    let mut text = String::new();
+    let mut url = String::new();
+
+    content_chars.next()?;
+    let mut consumed = 1;
+    loop {
+        match content_chars.next()? {
+            ']' => break,
+            '[' => return None,
+            c => text.push(c),
+        }
+        consumed += 1;
+    }
+    consumed += 1;
+
+    if content_chars.next()? != '(' {
+        return None;
+    }
+    consumed += 1;
+    let mut depth = 0;
+    loop {
+        match content_chars.next()? {
+            ')' if depth == 0 => break,
+            ')' => {
+                depth -= 1;
+                url.push(')');
+            }
+            '(' => {
+                depth += 1;
+                url.push('(');
+            }
+            c if c.is_whitespace() => return None,
+            c => url.push(c),
+        }
+        consumed += 1;
+    }
+    consumed += 1;
+
+    let scheme = url.split_once(':').map(|(scheme, _)| {
+        scheme.chars().filter(|c| !c.is_control()).collect::<String>().to_ascii_lowercase()
+    });
+    if text.trim().is_empty() || url.is_empty() || matches!(scheme.as_deref(), Some("javascript" | "vbscript" | "data")) {
+        return None;
+    }
+    Some((LinkToken { text, target: LinkTarget::Url(url) }, consumed))
+}
+
 /// The global formater for inline tokens. It takes the line/string to be formatted as ```block_content``` argument
 /// 
 /// Returns a vector collection of contained **Inline** tokens
 pub fn inline_tokenization(block_content: String)-> Vec<InlineToken> {
+    inline_tokenization_with_options(block_content, &TokenizerOptions::default())
+}
+
+pub fn inline_tokenization_with_options(block_content: String, options: &TokenizerOptions)-> Vec<InlineToken> {
+    tokenize_inline(block_content, options).0
+}
+
+/// Formatting markers of the content left unmatched, either read as text, like the first ```**``` of
+/// ```a ** b**```, or opening a format that never closes, like the ```*``` of ```*a```
+pub fn unbalanced_markers(block_content: &str, options: &TokenizerOptions)-> Vec<&'static str> {
+    let (tokens, fallbacks) = tokenize_inline(block_content.to_owned(), options);
+
+    let mut unclosed = vec![];
+    for (index, token) in tokens.iter().enumerate() {
+        // Emoji are left out, their closing colon is read as text
+        if !matches!(token.get_pos(), InlineTokenPos::Open) || token.get_id() == InlineId::Emoji {
+            continue;
+        }
+        let id = token.get_id();
+        if !tokens[index + 1..].iter().any(|later| later.get_id() == id && matches!(later.get_pos(), InlineTokenPos::Close)) {
+            unclosed.push(id);
+        }
+    }
+    fallbacks.iter().chain(&unclosed).filter_map(marker_text).collect()
+}
+
+/// The characters of a formatting marker
+fn marker_text(id: &InlineId)-> Option<&'static str> {
+    match id {
+        InlineId::Code => Some("`"),
+        InlineId::Bold => Some("*"),
+        InlineId::Strike => Some("~"),
+        InlineId::Italic => Some("_"),
+        InlineId::Caption => Some("**"),
+        InlineId::Underline => Some("__"),
+        InlineId::Emoji => Some(":"),
+        InlineId::Highlight => Some("=="),
+        _ => None,
+    }
+}
+
+/// Tokenizes the content, also returning the markers that fell back to text for closing nothing
+fn tokenize_inline(block_content: String, options: &TokenizerOptions)-> (Vec<InlineToken>, Vec<InlineId>) {
     let mut content_chars: Peekable<std::str::Chars<'_>> = block_content.chars().peekable();
 
     let mut stack: Vec<InlineToken> = Vec::new();
     let mut buffer = String::new();
     let mut concerns = vec![];
-    let reserved_tokens = ['~', '*', '_', ':'];
-    let punctuations = [',', '.', '?', '!', '\'', '\"', ';', '-'];
+    let reserved_tokens = ['~', '*', '_', ':', '='];
+    let punctuations = [',', '.', '?', '!', '\'', '\"', ';', '-', '<'];
 
-    while let Some(nxt_char) = content_chars.peek() {
+    while content_chars.peek().is_some() {
+        // Autolinks are taken whole so formatting characters in them, like the underscores
+        // of ```https://example.com/a_b_c```, stay part of the link
+        if options.autolinks && content_chars.peek().is_some_and(char::is_ascii_alphanumeric) && at_word_start(&stack, &buffer) {
+            if let Some((link, consumed)) = scan_autolink(content_chars.clone()) {
+                if !buffer.is_empty() {
+                    stack.push(InlineToken::Text(buffer.clone()));
+                    buffer.clear();
+                }
+                stack.push(InlineToken::Link(link));
+                for _ in 0..consumed {
+                    content_chars.next();
+                }
+                continue;
+            }
+        }
+
+        let Some(nxt_char) = content_chars.peek() else { break };
         match nxt_char {
             '*' => {
                 // Step to next character to verify for either bold or caption
@@ -579,6 +1053,21 @@ pub fn inline_tokenization(block_content: String)-> Vec<InlineToken> {
                 }
             }
             '`' => {
+                // A single backtick opens a code span kept verbatim, as inside ```==x `c` y==```
+                if let Some((code, consumed)) = scan_code_span(content_chars.clone()) {
+                    if !buffer.is_empty() {
+                        stack.push(InlineToken::Text(buffer.clone()));
+                        buffer.clear();
+                    }
+                    stack.push(InlineToken::Code(InlineTokenPos::Open));
+                    stack.push(InlineToken::Text(code));
+                    stack.push(InlineToken::Code(InlineTokenPos::Close));
+                    for _ in 0..consumed {
+                        content_chars.next();
+                    }
+                    continue;
+                }
+
                 // Step to next 2 characters to verify for Emoji
                 content_chars.next();
                 if let Some(post_char) = content_chars.peek() {
@@ -598,17 +1087,139 @@ pub fn inline_tokenization(block_content: String)-> Vec<InlineToken> {
                                 break;
                             }
                         }
-                        _ => {
-                            stack.push(InlineToken::Text("``".to_owned()));
-                            content_chars.next();
-                        }
+                        // A lone backtick closing nothing is text
+                        _ => buffer.push('`'),
                     }
                 }
                 else {
-                    stack.push(InlineToken::Text("`".to_owned()));
+                    buffer.push('`');
+                    stack.push(InlineToken::Text(buffer.clone()));
+                    buffer.clear();
                     break;
                 }
             }
+            '=' => {
+                // The highlighted text is tokenized on its own, so formatting inside it opens and closes within it
+                if let Some((color, text, consumed)) = scan_highlight(content_chars.clone()).filter(|_| options.highlight) {
+                    if !buffer.is_empty() {
+                        stack.push(InlineToken::Text(buffer.clone()));
+                        buffer.clear();
+                    }
+                    stack.push(InlineToken::Highlight { color: color.clone(), pos: InlineTokenPos::Open });
+                    let mut highlighted = inline_tokenization_with_options(text, options);
+                    unmatched_to_text(&mut highlighted);
+                    stack.extend(highlighted);
+                    stack.push(InlineToken::Highlight { color, pos: InlineTokenPos::Close });
+                    for _ in 0..consumed {
+                        content_chars.next();
+                    }
+                    continue;
+                }
+
+                buffer.push('=');
+                content_chars.next();
+                if content_chars.peek().is_none() {
+                    stack.push(InlineToken::Text(buffer.clone()));
+                    buffer.clear();
+                }
+            }
+            '$' => {
+                if let Some((math, consumed)) = scan_math(content_chars.clone()).filter(|_| options.math) {
+                    if !buffer.is_empty() {
+                        stack.push(InlineToken::Text(buffer.clone()));
+                        buffer.clear();
+                    }
+                    stack.push(InlineToken::Math(math));
+                    for _ in 0..consumed {
+                        content_chars.next();
+                    }
+                    continue;
+                }
+
+                buffer.push('$');
+                content_chars.next();
+                if content_chars.peek().is_none() {
+                    stack.push(InlineToken::Text(buffer.clone()));
+                    buffer.clear();
+                }
+            }
+            '\\' => {
+                content_chars.next();
+                match content_chars.peek() {
+                    // An escaped dollar sign is always literal
+                    Some('$') => {
+                        buffer.push('$');
+                        content_chars.next();
+                    }
+                    // A backslash ending a paragraph line is a hard break
+                    Some('\n') => {
+                        if !buffer.is_empty() {
+                            stack.push(InlineToken::Text(buffer.clone()));
+                            buffer.clear();
+                        }
+                        stack.push(InlineToken::HardBreak);
+                        content_chars.next();
+                        continue;
+                    }
+                    _ => buffer.push('\\'),
+                }
+                if content_chars.peek().is_none() {
+                    stack.push(InlineToken::Text(buffer.clone()));
+                    buffer.clear();
+                }
+            }
+            '<' => {
+                let rest: String = content_chars.clone().collect();
+                if let Some((tag, consumed)) = scan_html_tag(&rest) {
+                    if !buffer.is_empty() {
+                        stack.push(InlineToken::Text(buffer.clone()));
+                        buffer.clear();
+                    }
+                    stack.push(InlineToken::Html(tag));
+                    for _ in 0..consumed {
+                        content_chars.next();
+                    }
+                    continue;
+                }
+
+                buffer.push('<');
+                content_chars.next();
+                if content_chars.peek().is_none() {
+                    stack.push(InlineToken::Text(buffer.clone()));
+                    buffer.clear();
+                }
+            }
+            '[' => {
+                if let Some((label, consumed)) = scan_footnote_ref(content_chars.clone()).filter(|_| options.footnotes) {
+                    if !buffer.is_empty() {
+                        stack.push(InlineToken::Text(buffer.clone()));
+                        buffer.clear();
+                    }
+                    stack.push(InlineToken::FootnoteRef(FootnoteRef { label, number: None, occurrence: 0 }));
+                    for _ in 0..consumed {
+                        content_chars.next();
+                    }
+                    continue;
+                }
+                if let Some((link, consumed)) = scan_reference_link(content_chars.clone()).or_else(|| scan_inline_link(content_chars.clone())) {
+                    if !buffer.is_empty() {
+                        stack.push(InlineToken::Text(buffer.clone()));
+                        buffer.clear();
+                    }
+                    stack.push(InlineToken::Link(link));
+                    for _ in 0..consumed {
+                        content_chars.next();
+                    }
+                    continue;
+                }
+
+                buffer.push('[');
+                content_chars.next();
+                if content_chars.peek().is_none() {
+                    stack.push(InlineToken::Text(buffer.clone()));
+                    buffer.clear();
+                }
+            }
             _ => {
                 buffer.push(*nxt_char);
                 content_chars.next();
@@ -620,10 +1231,12 @@ pub fn inline_tokenization(block_content: String)-> Vec<InlineToken> {
         }
     }
 
+    let mut fallbacks = vec![];
     for index in concerns {
         if let Some(token) = stack.get_mut(index-1) {
             match token.get_pos() {
                 InlineTokenPos::Open | InlineTokenPos::None => {
+                    fallbacks.push(token.get_id());
                     match token.get_id() {
                         InlineId::Code => stack.push(InlineToken::Text("``".to_owned())),
                         InlineId::Bold => stack.push(InlineToken::Text("*".to_owned())),
@@ -641,5 +1254,134 @@ pub fn inline_tokenization(block_content: String)-> Vec<InlineToken> {
             }
         }
     }
-    stack
+    (stack, fallbacks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn autolink(text: &str)-> Option<(LinkToken, usize)> {
+        scan_autolink(text.chars().peekable())
+    }
+
+    #[test]
+    fn autolink_urls_without_trailing_punctuation() {
+        let (link, consumed) = autolink("https://example.com/a_b).").unwrap();
+        assert_eq!(link.text, "https://example.com/a_b");
+        assert_eq!(consumed, 23);
+
+        let (link, _) = autolink("www.example.com").unwrap();
+        assert!(matches!(link.target, LinkTarget::Url(href) if href == "http://www.example.com"));
+    }
+
+    #[test]
+    fn autolink_emails() {
+        let (link, _) = autolink("me@example.com.").unwrap();
+        assert!(matches!(link.target, LinkTarget::Url(href) if href == "mailto:me@example.com"));
+        assert!(autolink("me@localhost").is_none());
+    }
+
+    #[test]
+    fn autolink_skips_multibyte_words() {
+        assert!(autolink("It’s").is_none());
+        assert!(autolink("héllo").is_none());
+        assert!(inline_tokenization(String::from("It’s fine")).iter().all(|token| matches!(token, InlineToken::Text(_))));
+    }
+
+    fn highlighted(text: &str)-> Vec<InlineToken> {
+        let tokens = inline_tokenization(text.to_owned());
+        let open = tokens.iter().position(|token| matches!(token, InlineToken::Highlight { pos: InlineTokenPos::Open, .. })).unwrap();
+        let close = tokens.iter().position(|token| matches!(token, InlineToken::Highlight { pos: InlineTokenPos::Close, .. })).unwrap();
+        tokens[open + 1..close].to_vec()
+    }
+
+    #[test]
+    fn highlights_take_their_color() {
+        let tokens = inline_tokenization(String::from("a ==(red)b== c ==d=="));
+        let colors: Vec<&str> = tokens.iter().filter_map(|token| match token {
+            InlineToken::Highlight { color, pos: InlineTokenPos::Open } => Some(color.as_str()),
+            _ => None,
+        }).collect();
+        assert_eq!(colors, ["red", DEFAULT_HIGHLIGHT_COLOR]);
+        assert!(scan_highlight("== a==".chars().peekable()).is_none());
+    }
+
+    #[test]
+    fn highlights_keep_code_and_formatting() {
+        let code = highlighted("a ==x `c` y== z");
+        assert!(matches!(&code[..], [InlineToken::Text(x), InlineToken::Code(InlineTokenPos::Open), InlineToken::Text(c), InlineToken::Code(InlineTokenPos::Close), InlineToken::Text(y)]
+            if x == "x " && c == "c" && y == " y"));
+
+        let bold = highlighted("==a *b* c==");
+        assert!(bold.iter().any(|token| matches!(token, InlineToken::Bold(InlineTokenPos::Open))));
+        assert!(bold.iter().any(|token| matches!(token, InlineToken::Bold(InlineTokenPos::Close))));
+
+        assert_eq!(plain_text(&highlighted("==over\nlines==")), "over\nlines");
+    }
+
+    #[test]
+    fn code_spans_keep_their_text() {
+        let tokens = inline_tokenization(String::from("a `*b*` c"));
+        assert!(matches!(&tokens[..], [InlineToken::Text(a), InlineToken::Code(InlineTokenPos::Open), InlineToken::Text(b), InlineToken::Code(InlineTokenPos::Close), InlineToken::Text(c)]
+            if a == "a " && b == "*b*" && c == " c"));
+        assert_eq!(plain_text(&inline_tokenization(String::from("a ` b"))), "a ` b");
+    }
+
+    fn inline_link(text: &str)-> Option<(LinkToken, usize)> {
+        scan_inline_link(text.chars().peekable())
+    }
+
+    #[test]
+    fn inline_links_take_text_and_url() {
+        let (link, consumed) = inline_link("[the guide](guide.md#setup) after").unwrap();
+        assert_eq!(link.text, "the guide");
+        assert!(matches!(link.target, LinkTarget::Url(url) if url == "guide.md#setup"));
+        assert_eq!(consumed, 27);
+
+        let (link, _) = inline_link("[wiki](https://en.wikipedia.org/wiki/Rust_(language))").unwrap();
+        assert!(matches!(link.target, LinkTarget::Url(url) if url.ends_with("(language)")));
+    }
+
+    #[test]
+    fn inline_links_need_text_and_a_url_without_spaces() {
+        assert!(inline_link("[](url)").is_none());
+        assert!(inline_link("[text]()").is_none());
+        assert!(inline_link("[text] (url)").is_none());
+        assert!(inline_link("[text](a url)").is_none());
+        assert!(inline_link("[text](url").is_none());
+    }
+
+    #[test]
+    fn inline_links_refuse_script_urls() {
+        for url in ["javascript:alert(1)", "JavaScript:alert(1)", "vbscript:x", "data:text/html,x", "java\u{1}script:x"] {
+            assert!(inline_link(&format!("[text]({url})")).is_none(), "{url}");
+        }
+        assert!(inline_link("[mail](mailto:me@example.com)").is_some());
+    }
+
+    #[test]
+    fn inline_links_read_in_paragraphs() {
+        let tokens = inline_tokenization(String::from("See [the guide](guide.md) or [^1]"));
+        assert!(matches!(&tokens[1], InlineToken::Link(link) if link.text == "the guide"));
+        assert!(matches!(&tokens[3], InlineToken::FootnoteRef(reference) if reference.label == "1"));
+    }
+
+    fn math(text: &str)-> Option<(String, bool, usize)> {
+        scan_math(text.chars().peekable()).map(|(math, consumed)| (math.formula, math.display, consumed))
+    }
+
+    #[test]
+    fn math_spans_inline_and_display_formulas() {
+        assert_eq!(math("$x^2$ rest"), Some((String::from("x^2"), false, 5)));
+        assert_eq!(math("$$ y $$"), Some((String::from("y"), true, 7)));
+        assert_eq!(math(r"$a\$b$"), Some((String::from(r"a\$b"), false, 6)));
+    }
+
+    #[test]
+    fn amounts_stay_text() {
+        for text in ["$5 and $10", "$ a$", "$a $", "$$", "$x", "$$y$"] {
+            assert_eq!(math(text), None, "{text}");
+        }
+    }
 }
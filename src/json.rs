@@ -0,0 +1,308 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Deepest nesting of arrays and objects ```Json::parse``` accepts, deeper documents being refused
+/// rather than overflowing the stack
+pub const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+/// A JSON value, objects keeping their members in order
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Parses a JSON document, the error telling what was expected and at which character
+    pub fn parse(text: &str)-> Result<Json, String> {
+        let mut chars = text.chars().peekable();
+        let mut position = 0;
+        let value = parse_value(&mut chars, &mut position, 0)?;
+        skip_whitespace(&mut chars, &mut position);
+        match chars.next() {
+            Some(c) => Err(format!("unexpected {:?} after the value at character {}", c, position)),
+            None => Ok(value),
+        }
+    }
+
+    /// Builds an object from its members
+    pub fn object<const N: usize>(members: [(&str, Json); N])-> Json {
+        Json::Object(members.into_iter().map(|(key, value)| (key.to_owned(), value)).collect())
+    }
+
+    /// The member ```key``` of an object
+    pub fn get(&self, key: &str)-> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self)-> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The value of a number without a fractional part that fits a ```usize```
+    pub fn as_usize(&self)-> Option<usize> {
+        match self {
+            Json::Number(value) if *value >= 0.0 && value.fract() == 0.0 && *value <= usize::MAX as f64 => Some(*value as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self)-> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_owned())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(items: Vec<Json>) -> Self {
+        Json::Array(items)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+/// Serializes the value on a single line
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(value) if !value.is_finite() => write!(f, "null"),
+            Json::Number(value) if value.fract() == 0.0 && value.abs() < 1e15 => write!(f, "{}", *value as i64),
+            Json::Number(value) => write!(f, "{}", value),
+            Json::String(value) => write_string(f, value),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, value: &str)-> fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>, position: &mut usize) {
+    while chars.next_if(|c| matches!(c, ' ' | '\t' | '\n' | '\r')).is_some() {
+        *position += 1;
+    }
+}
+
+fn next(chars: &mut Peekable<Chars>, position: &mut usize)-> Result<char, String> {
+    *position += 1;
+    chars.next().ok_or_else(|| String::from("unexpected end of input"))
+}
+
+fn expect(chars: &mut Peekable<Chars>, position: &mut usize, expected: &str)-> Result<(), String> {
+    for c in expected.chars() {
+        if next(chars, position)? != c {
+            return Err(format!("expected {:?} at character {}", expected, position));
+        }
+    }
+    Ok(())
+}
+
+fn parse_value(chars: &mut Peekable<Chars>, position: &mut usize, depth: usize)-> Result<Json, String> {
+    skip_whitespace(chars, position);
+    if depth >= MAX_DEPTH && matches!(chars.peek(), Some('[' | '{')) {
+        return Err(format!("nesting deeper than {} at character {}", MAX_DEPTH, position));
+    }
+    match chars.peek() {
+        Some('n') => expect(chars, position, "null").map(|_| Json::Null),
+        Some('t') => expect(chars, position, "true").map(|_| Json::Bool(true)),
+        Some('f') => expect(chars, position, "false").map(|_| Json::Bool(false)),
+        Some('"') => parse_string(chars, position).map(Json::String),
+        Some('[') => {
+            next(chars, position)?;
+            let mut items = vec![];
+            skip_whitespace(chars, position);
+            if chars.next_if_eq(&']').is_some() {
+                *position += 1;
+                return Ok(Json::Array(items));
+            }
+            loop {
+                items.push(parse_value(chars, position, depth + 1)?);
+                skip_whitespace(chars, position);
+                match next(chars, position)? {
+                    ',' => continue,
+                    ']' => return Ok(Json::Array(items)),
+                    c => return Err(format!("unexpected {:?} in an array at character {}", c, position)),
+                }
+            }
+        }
+        Some('{') => {
+            next(chars, position)?;
+            let mut members = vec![];
+            skip_whitespace(chars, position);
+            if chars.next_if_eq(&'}').is_some() {
+                *position += 1;
+                return Ok(Json::Object(members));
+            }
+            loop {
+                skip_whitespace(chars, position);
+                let key = parse_string(chars, position)?;
+                skip_whitespace(chars, position);
+                expect(chars, position, ":")?;
+                members.push((key, parse_value(chars, position, depth + 1)?));
+                skip_whitespace(chars, position);
+                match next(chars, position)? {
+                    ',' => continue,
+                    '}' => return Ok(Json::Object(members)),
+                    c => return Err(format!("unexpected {:?} in an object at character {}", c, position)),
+                }
+            }
+        }
+        Some(c) if *c == '-' || c.is_ascii_digit() => {
+            let mut number = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+                *position += 1;
+                number.push(c);
+            }
+            number.parse().map(Json::Number).map_err(|_| format!("invalid number {:?} at character {}", number, position))
+        }
+        Some(c) => Err(format!("unexpected {:?} at character {}", c, position)),
+        None => Err(String::from("unexpected end of input")),
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>, position: &mut usize)-> Result<String, String> {
+    expect(chars, position, "\"")?;
+    let mut value = String::new();
+    loop {
+        match next(chars, position)? {
+            '"' => return Ok(value),
+            '\\' => match next(chars, position)? {
+                '"' => value.push('"'),
+                '\\' => value.push('\\'),
+                '/' => value.push('/'),
+                'b' => value.push('\u{8}'),
+                'f' => value.push('\u{c}'),
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                'u' => {
+                    let mut code = parse_hex(chars, position)?;
+                    // A high surrogate is followed by the low one of the pair
+                    if (0xD800..0xDC00).contains(&code) {
+                        expect(chars, position, "\\u")?;
+                        let low = parse_hex(chars, position)?;
+                        code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                    }
+                    value.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                }
+                c => return Err(format!("invalid escape \\{} at character {}", c, position)),
+            },
+            c => value.push(c),
+        }
+    }
+}
+
+fn parse_hex(chars: &mut Peekable<Chars>, position: &mut usize)-> Result<u32, String> {
+    let mut code = 0;
+    for _ in 0..4 {
+        let digit = next(chars, position)?.to_digit(16).ok_or_else(|| format!("invalid \\u escape at character {}", position))?;
+        code = code * 16 + digit;
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_serializes() {
+        let text = r#"{"a":[1,2.5,true,null],"b":"x\"\n\u00e9\ud83d\ude00"}"#;
+        let value = Json::parse(text).unwrap();
+        assert_eq!(value.get("a").and_then(Json::as_array).map(<[Json]>::len), Some(4));
+        assert_eq!(value.get("b").and_then(Json::as_str), Some("x\"\né😀"));
+        assert_eq!(value.to_string(), r#"{"a":[1,2.5,true,null],"b":"x\"\né😀"}"#);
+    }
+
+    #[test]
+    fn refuses_invalid_documents() {
+        for text in ["", "[1,", "{\"a\" 1}", "tru", "[1] 2", "\"\\q\""] {
+            assert!(Json::parse(text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Json::parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(Json::parse(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(Json::parse(&"[".repeat(200_000)).is_err());
+        assert!(Json::parse(&"{\"a\":".repeat(200_000)).is_err());
+    }
+}
@@ -1,30 +1,240 @@
 use std::iter::Peekable;
 
-use block_token::{block_tokenization, BlockToken, CodeBlock, HrToken, ListMeta, ListToken, ListType};
-use inline_token::{inline_tokenization, InlineToken, InlineTokenPos};
+use block_token::{block_tokenization_with_options, BlockToken, CaptionKind, CaptionToken, CodeBlock, HeadingToken, HrToken, ListMeta, ListToken, ListType};
+use inline_token::{inline_tokenization_with_options, plain_text, InlineToken, InlineTokenPos};
+use diagnostic::Diagnostic;
+use footnote::{footnote_diagnostics, footnote_ref_id, footnotes_parser, resolve_footnotes};
+use front_matter::{extract_front_matter, Metadata};
+use html::{escape_text, html_block_parser, html_tag_parser, HtmlOptions};
+use slug::Slugger;
+use template::{default_layout, Template};
+use toc::{table_of_contents, toc_parser, TocRange};
 
 pub mod block_token;
+pub mod diagnostic;
+pub mod emoji;
+pub mod fmt;
+pub mod footnote;
+pub mod front_matter;
+pub mod html;
 pub mod inline_token;
+pub mod json;
+pub mod lint;
+pub mod lsp;
+pub mod serve;
+pub mod site;
+pub mod slug;
+pub mod task_list;
+pub mod template;
+pub mod text;
+pub mod toc;
+pub mod watch;
 
 #[derive(Debug, Clone)]
 pub struct SecList {
     r#type: ListType,
+    /// Items in order, a nesting item having no tokens and standing for the next list of ```nests```
     items: Vec<(ListMeta, Vec<InlineToken>)>,
-    is_nested: usize,
     nests: Vec<SecList>
 }
 
+#[derive(Debug, Clone)]
+pub struct SecHeading {
+    pub level: usize,
+    /// Document unique anchor id, either explicit (```{#custom-id}```) or generated from the text
+    pub id: String,
+    pub tokens: Vec<InlineToken>,
+    /// 0 based index of the line the heading is on, not counting the front matter
+    pub line: usize
+}
+
+#[derive(Debug, Clone)]
+pub struct SecFootnote {
+    pub label: String,
+    /// Position in the footnotes section, ```None``` when nothing refers to this footnote
+    pub number: Option<usize>,
+    /// How many times the footnote is referenced
+    pub references: usize,
+    pub tokens: Vec<InlineToken>
+}
+
+#[derive(Debug, Clone)]
+pub struct SecCaption {
+    pub kind: CaptionKind,
+    /// Position among the captions of the same kind, starting at 1
+    pub number: usize,
+    /// Cross reference target, either explicit (```{#custom-id}```) or ```figure-N```/```table-N```
+    pub id: String,
+    pub tokens: Vec<InlineToken>
+}
+
+#[derive(Debug, Clone)]
+pub struct SecDefinition {
+    pub term: Vec<InlineToken>,
+    pub definitions: Vec<Vec<InlineToken>>
+}
+
+#[derive(Debug, Clone)]
+/// Tokenization switches for ```parse_document_with_options```
+pub struct TokenizerOptions {
+    /// Turns bare ```http(s)://``` and ```www.``` urls and email addresses into links
+    pub autolinks: bool,
+    /// Reads ```$inline$``` and ```$$display$$``` math, left as text otherwise
+    pub math: bool,
+    /// Reads ```[^label]``` references and ```[^label]: note``` definitions
+    pub footnotes: bool,
+    /// Reads a ```: definition``` line as defining the text line above it
+    pub definition_lists: bool,
+    /// Reads ```==text==``` and ```==(color)text==``` highlights
+    pub highlight: bool,
+    /// Reads ```- [ ] item``` and the other task states as task items, plain items otherwise
+    pub task_lists: bool,
+}
+
+impl Default for TokenizerOptions {
+    fn default() -> Self {
+        TokenizerOptions { autolinks: true, math: true, footnotes: true, definition_lists: true, highlight: true, task_lists: true }
+    }
+}
+
+/// Server side math renderer, given the verbatim formula and whether it is display math
+/// it returns the html to emit in place of the formula
+pub type MathRenderer = fn(&str, bool) -> String;
+
+#[derive(Debug, Clone, Default)]
+/// Rendering switches for ```parser_with_options```
+pub struct ParserOptions {
+    /// Emits a ```#``` permalink anchor next to each heading
+    pub permalinks: bool,
+    /// Renders math on the server, when ```None``` formulas are emitted inside ```math``` classed
+    /// elements with ```\(...\)```/```\[...\]``` delimiters for KaTeX or MathJax to pick up
+    pub math_renderer: Option<MathRenderer>,
+    /// How raw html in the document is emitted
+    pub html: HtmlOptions,
+    /// How a line break inside a paragraph is emitted
+    pub soft_break: SoftBreak,
+    /// Emits every line break inside a paragraph as ```<br>```, like each source line
+    /// being its own line
+    pub hardbreaks: bool,
+    /// Address of the stylesheet linked by ```page_parser```, which embeds ```DEFAULT_STYLESHEET``` when ```None```
+    pub stylesheet: Option<String>,
+    /// Layout of the pages of ```page_parser```, ```DEFAULT_LAYOUT``` when ```None```
+    pub template: Option<Template>,
+}
+
+/// Styles for every class the renderer emits, embedded in standalone pages
+pub const DEFAULT_STYLESHEET: &str = include_str!("style.css");
+
+#[derive(Debug, Clone, Copy, Default)]
+pub enum SoftBreak {
+    #[default]
+    Newline,
+    Space,
+}
+
 #[derive(Debug, Clone)]
 /// A wrapper type for the **Inline** tokens corresponding to the givens ```BlockToken```
 pub enum SecondaryToken {
     Br, Hr(HrToken),
-    P, List(SecList),
+    List(SecList),
     CodeBlock(CodeBlock),
     Text(Vec<InlineToken>),
     Form(Vec<InlineToken>),
     Table(Vec<InlineToken>),
-    Blockquote(Vec<InlineToken>),
-    Heading(usize, Vec<InlineToken>),
+    /// One run of **Inline** tokens per quoted line
+    Blockquote(Vec<Vec<InlineToken>>),
+    Heading(SecHeading),
+    Toc(TocRange),
+    Footnote(SecFootnote),
+    DefinitionList(Vec<SecDefinition>),
+    Math(String),
+    Html(String),
+    /// Caption of the code block, image or table right before it
+    Caption(SecCaption),
+}
+
+impl SecondaryToken {
+    /// Returns every run of **Inline** tokens held by this token, nested list items included
+    pub fn inline_tokens(&self)-> Vec<&Vec<InlineToken>> {
+        match self {
+            SecondaryToken::Text(tokens) | SecondaryToken::Form(tokens) |
+            SecondaryToken::Table(tokens) => vec![tokens],
+            SecondaryToken::Blockquote(lines) => lines.iter().collect(),
+            SecondaryToken::Heading(heading) => vec![&heading.tokens],
+            SecondaryToken::Footnote(footnote) => vec![&footnote.tokens],
+            SecondaryToken::Caption(caption) => vec![&caption.tokens],
+            SecondaryToken::List(sec_list) => sec_list.inline_tokens(),
+            SecondaryToken::DefinitionList(items) => items.iter()
+                .flat_map(|item| std::iter::once(&item.term).chain(&item.definitions))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Mutable counterpart of ```inline_tokens```
+    pub fn inline_tokens_mut(&mut self)-> Vec<&mut Vec<InlineToken>> {
+        match self {
+            SecondaryToken::Text(tokens) | SecondaryToken::Form(tokens) |
+            SecondaryToken::Table(tokens) => vec![tokens],
+            SecondaryToken::Blockquote(lines) => lines.iter_mut().collect(),
+            SecondaryToken::Heading(heading) => vec![&mut heading.tokens],
+            SecondaryToken::Footnote(footnote) => vec![&mut footnote.tokens],
+            SecondaryToken::Caption(caption) => vec![&mut caption.tokens],
+            SecondaryToken::List(sec_list) => sec_list.inline_tokens_mut(),
+            SecondaryToken::DefinitionList(items) => items.iter_mut()
+                .flat_map(|item| std::iter::once(&mut item.term).chain(&mut item.definitions))
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+impl SecList {
+    /// Item tokens in document order, a nested list's items take the place of its nesting item
+    fn inline_tokens(&self)-> Vec<&Vec<InlineToken>> {
+        let mut groups = vec![];
+        let mut nests = self.nests.iter();
+        for (_, tokens) in &self.items {
+            match tokens.is_empty() {
+                true => if let Some(nested) = nests.next() {
+                    groups.extend(nested.inline_tokens());
+                },
+                false => groups.push(tokens),
+            }
+        }
+        groups
+    }
+
+    fn inline_tokens_mut(&mut self)-> Vec<&mut Vec<InlineToken>> {
+        let mut groups = vec![];
+        let mut nests = self.nests.iter_mut();
+        for (_, tokens) in self.items.iter_mut() {
+            match tokens.is_empty() {
+                true => if let Some(nested) = nests.next() {
+                    groups.extend(nested.inline_tokens_mut());
+                },
+                false => groups.push(tokens),
+            }
+        }
+        groups
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// The parse result of a whole document
+pub struct Document {
+    /// Fields of the leading ```---``` or ```+++``` front matter block, empty when there's none
+    pub metadata: Metadata,
+    pub tokens: Vec<SecondaryToken>,
+    /// Problems found while parsing, like references to undefined footnotes
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Document {
+    /// The front matter ```title``` or else the text of the first heading
+    pub fn title(&self)-> Option<String> {
+        self.metadata.title().or_else(|| toc::headings(&self.tokens).into_iter().next().map(|heading| heading.text))
+    }
 }
 
 pub enum Action {
@@ -32,87 +242,250 @@ pub enum Action {
     Continue(bool)
 }
 
+/// Tokenizes the document after splitting off its front matter
+pub fn parse_document(input: &str)-> Document {
+    parse_document_with_options(input, &TokenizerOptions::default())
+}
+
+pub fn parse_document_with_options(input: &str, options: &TokenizerOptions)-> Document {
+    let (metadata, body, line_offset) = extract_front_matter(input).unwrap_or((Metadata::default(), input, 0));
+    let tokens = tokenizer_body(body, options);
+    let diagnostics = footnote_diagnostics(&tokens, body, line_offset);
+
+    Document { metadata, tokens, diagnostics }
+}
+
+/// Tokenizes the document, any front matter is dropped; use ```parse_document``` to keep it
 pub fn tokenizer(input: &str)-> Vec<SecondaryToken>{
+    parse_document(input).tokens
+}
+
+pub fn tokenizer_with_options(input: &str, options: &TokenizerOptions)-> Vec<SecondaryToken>{
+    parse_document_with_options(input, options).tokens
+}
+
+fn tokenizer_body(input: &str, options: &TokenizerOptions)-> Vec<SecondaryToken>{
     let input_lines: Vec<&str> = input.lines().collect();
 
     let mut block_tokens: Vec<BlockToken> = Vec::new();
     let mut secondary_tokens: Vec<SecondaryToken> = Vec::new();
     
-    block_tokenization(&mut block_tokens, &input_lines);
+    block_tokenization_with_options(&mut block_tokens, &input_lines, options);
+
+    // Explicit ids are reserved up front so generated slugs never take them
+    let mut slugger = Slugger::new();
+    for token in &block_tokens {
+        match token {
+            BlockToken::Heading(HeadingToken { id: Some(id), .. }) | BlockToken::Caption(CaptionToken { id: Some(id), .. }) => slugger.reserve(id),
+            _ => {}
+        }
+    }
+    let (mut figures, mut tables) = (0, 0);
 
     let mut token_iter = block_tokens.iter().peekable();
 
-    while let Some(token) = token_iter.peek() {
-        match *token {
+    while let Some(&token) = token_iter.peek() {
+        match token {
+            // A line break only leads a text line, whose paragraph takes care of it
+            BlockToken::Br if matches!(token_iter.clone().nth(1), Some(BlockToken::Text(_))) => {},
             BlockToken::Br => secondary_tokens.push(SecondaryToken::Br),
-            BlockToken::P => {
-                // TODO: Implement Paragrahping
-            }
+            BlockToken::P => {}
             BlockToken::Hr(hr_token) => secondary_tokens.push(SecondaryToken::Hr(hr_token.clone())),
-            BlockToken::Blockquote(content) => secondary_tokens.push(SecondaryToken::Blockquote(inline_tokenization(content.to_owned()))),
-            BlockToken::Text(content) => secondary_tokens.push(SecondaryToken::Text(inline_tokenization(content.to_owned()))),
-            BlockToken::Heading(heading_token) => secondary_tokens.push(SecondaryToken::Heading(heading_token.level, inline_tokenization(heading_token.value.to_owned()))),
-            BlockToken::CodeBlock(cblock_token) => secondary_tokens.push(SecondaryToken::CodeBlock(cblock_token.to_owned())),
-            BlockToken::List(list_token) => secondary_tokens.push(SecondaryToken::List(list_miner(list_token))),
-            _ => {
+            BlockToken::Blockquote(lines) => secondary_tokens.push(SecondaryToken::Blockquote(
+                lines.iter().map(|line| inline_tokenization_with_options(line.to_owned(), options)).collect()
+            )),
+            BlockToken::Html(raw) => secondary_tokens.push(SecondaryToken::Html(raw.to_owned())),
+            BlockToken::Text(content) => {
+                // Consecutive text lines, each led by a line break, make up one paragraph
+                let mut lines = vec![content.as_str()];
+                token_iter.next();
+                loop {
+                    let mut ahead = token_iter.clone();
+                    match (ahead.next(), ahead.next()) {
+                        (Some(BlockToken::Br), Some(BlockToken::Text(line))) => {
+                            lines.push(line);
+                            token_iter = ahead;
+                        }
+                        _ => break,
+                    }
+                }
+                secondary_tokens.push(SecondaryToken::Text(inline_tokenization_with_options(join_paragraph_lines(&lines), options)));
                 continue;
-            }
+            },
+            BlockToken::Heading(heading_token) => {
+                let tokens = inline_tokenization_with_options(heading_token.value.to_owned(), options);
+                let id = match &heading_token.id {
+                    Some(id) => id.to_owned(),
+                    None => slugger.slug(&plain_text(&tokens)),
+                };
+                secondary_tokens.push(SecondaryToken::Heading(SecHeading { level: heading_token.level, id, tokens, line: heading_token.line }))
+            },
+            BlockToken::Caption(caption_token) => {
+                let number = match caption_token.kind {
+                    CaptionKind::Figure => { figures += 1; figures },
+                    CaptionKind::Table => { tables += 1; tables },
+                };
+                let id = match &caption_token.id {
+                    Some(id) => id.to_owned(),
+                    None => slugger.slug(&format!("{} {}", caption_token.kind.label(), number)),
+                };
+                let tokens = inline_tokenization_with_options(caption_token.value.to_owned(), options);
+                secondary_tokens.push(SecondaryToken::Caption(SecCaption { kind: caption_token.kind, number, id, tokens }))
+            },
+            BlockToken::CodeBlock(cblock_token) => secondary_tokens.push(SecondaryToken::CodeBlock(cblock_token.to_owned())),
+            BlockToken::List(list_token) => secondary_tokens.push(SecondaryToken::List(list_miner_with_options(list_token, options))),
+            BlockToken::Toc(range) => secondary_tokens.push(SecondaryToken::Toc(*range)),
+            BlockToken::Math(formula) => secondary_tokens.push(SecondaryToken::Math(formula.to_owned())),
+            BlockToken::DefinitionList(items) => secondary_tokens.push(SecondaryToken::DefinitionList(
+                items.iter().map(|item| SecDefinition {
+                    term: inline_tokenization_with_options(item.term.to_owned(), options),
+                    definitions: item.definitions.iter().map(|definition| inline_tokenization_with_options(definition.to_owned(), options)).collect(),
+                }).collect()
+            )),
+            BlockToken::Footnote(footnote_token) => secondary_tokens.push(SecondaryToken::Footnote(SecFootnote {
+                label: footnote_token.label.to_owned(),
+                number: None,
+                references: 0,
+                tokens: inline_tokenization_with_options(footnote_token.value.to_owned(), options),
+            })),
+            _ => {}
         }
         token_iter.next();
     }
-   
+
+    resolve_footnotes(&mut secondary_tokens);
     secondary_tokens
 }
 
-pub fn parser(input: &Vec<SecondaryToken>)-> String {
+/// Joins the lines of a paragraph for the inline tokenizer
+///
+/// Lines are separated by ```\n``` (a soft break), or by a backslash and ```\n``` (a hard break)
+/// where the line ends with a backslash or two or more spaces
+fn join_paragraph_lines(lines: &[&str])-> String {
+    let mut paragraph = String::new();
+    for (index, line) in lines.iter().enumerate() {
+        let line = line.trim_start();
+        if index + 1 == lines.len() {
+            paragraph.push_str(line.trim_end());
+        } else if let Some(line) = line.strip_suffix('\\') {
+            paragraph.push_str(line);
+            paragraph.push_str("\\\n");
+        } else if line.ends_with("  ") {
+            paragraph.push_str(line.trim_end());
+            paragraph.push_str("\\\n");
+        } else {
+            paragraph.push_str(line.trim_end());
+            paragraph.push('\n');
+        }
+    }
+    paragraph
+}
+
+pub fn parser(input: &[SecondaryToken])-> String {
+    parser_with_options(input, &ParserOptions::default())
+}
+
+pub fn parser_with_options(input: &[SecondaryToken], options: &ParserOptions)-> String {
     let mut iterator  = input.iter().peekable();
     let mut html_stream = String::from("<div>\n");
-    let mut initi_p = false;
+    let mut task_index = 0;
 
-    while let Some(token) = iterator.peek() {
-        match *token {
+    while let Some(&token) = iterator.peek() {
+        let index = input.len() - iterator.len();
+        match token {
             SecondaryToken::Br => html_stream.push_str("<br>\n"),
-            SecondaryToken::P => {
-                match initi_p {
-                    true => html_stream.push_str("</p>\n<p>\n"),
-                    false => {
-                        initi_p = true;
-                        html_stream.push_str("<p>");
-                    }
-                    
-                }
-            },
             SecondaryToken::Hr(hr_token) => {
-                match hr_token {
-                    HrToken::Simple => html_stream.push_str("<hr class=\"hr\">\n"),
-                    HrToken::Bold => {
-                        html_stream.push_str("<hr style=\"height:0.2rem;\" class=\"hr\">\n")
-                    },
-                }
+                let rule = match hr_token {
+                    HrToken::Simple => String::from("<hr class=\"hr\">\n"),
+                    HrToken::Bold => String::from("<hr class=\"hr hr-bold\">\n"),
+                    HrToken::Dashed => String::from("<hr class=\"hr hr-dashed\">\n"),
+                    HrToken::Dotted => String::from("<hr class=\"hr hr-dotted\">\n"),
+                    HrToken::Double => String::from("<hr class=\"hr hr-double\">\n"),
+                    HrToken::Labeled(label) => format!("<div class=\"hr hr-labeled\" role=\"separator\"><span>{}</span></div>\n", escape_text(label)),
+                };
+                html_stream.push_str(&rule);
             },
             SecondaryToken::CodeBlock(cblock_token) => {
                 match cblock_token {
-                    CodeBlock::Open => html_stream.push_str("<div class=\"code_block\">\n"),
-                    CodeBlock::Close => html_stream.push_str("</div>\n"),
+                    CodeBlock::Open => {
+                        let close = input[index..].iter().position(|token| matches!(token, SecondaryToken::CodeBlock(CodeBlock::Close)));
+                        if let Some(caption) = close.and_then(|close| caption_after(input, index + close)) {
+                            html_stream.push_str(&format!("<figure id=\"{}\" class=\"figure\">\n", caption.id));
+                        }
+                        html_stream.push_str("<div class=\"code_block\">\n");
+                    },
+                    CodeBlock::Close => {
+                        html_stream.push_str("</div>\n");
+                        if let Some(caption) = caption_after(input, index) {
+                            html_stream.push_str(&format!("{}</figure>\n", caption_parser(caption, options)));
+                            iterator.next();
+                        }
+                    },
                 }
             },
             SecondaryToken::Text(inline_tokens) => {
-                let txt_content = format!("<span>{}</span>\n", inline_tokens_parser(&mut inline_tokens.clone()));
+                let txt_content = inline_tokens_parser_with_options(&mut inline_tokens.clone(), options);
+                match caption_after(input, index) {
+                    // Only a lone image takes a caption
+                    Some(caption) => {
+                        html_stream.push_str(&format!("<figure id=\"{}\" class=\"figure\">\n{}\n{}</figure>\n", caption.id, txt_content, caption_parser(caption, options)));
+                        iterator.next();
+                    },
+                    None => html_stream.push_str(&format!("<p>{}</p>\n", txt_content)),
+                }
+            },
+            SecondaryToken::Caption(caption) => {
+                let txt_content = format!("<p id=\"{}\" class=\"caption\">{}</p>\n", caption.id, caption_content(caption, options));
                 html_stream.push_str(&txt_content)
             },
-            SecondaryToken::Blockquote(inline_tokens) => {
-                let txt_content = format!("<blockquote>{}</blockquote>\n", inline_tokens_parser(&mut inline_tokens.clone()));
+            SecondaryToken::Blockquote(lines) => {
+                let quoted_lines: Vec<String> = lines.iter()
+                    .map(|inline_tokens| format!("<span>{}</span>", inline_tokens_parser_with_options(&mut inline_tokens.clone(), options)))
+                    .collect();
+                let txt_content = format!("<blockquote>{}</blockquote>\n", quoted_lines.join("<br>\n"));
                 html_stream.push_str(&txt_content)
             },
-            SecondaryToken::Heading(level, inline_tokens) => {
-                let txt_content = format!("<h{level}>{}</h{level}>\n", inline_tokens_parser(&mut inline_tokens.clone()));
+            SecondaryToken::Html(raw) => {
+                let mut html_content = format!("{}\n", html_block_parser(raw, &options.html));
+                // A table caption goes right after the opening tag, when the table made it through the html policy
+                if let Some(caption) = caption_after(input, index) {
+                    let open_end = html_content.find('>').filter(|_| html_content.to_ascii_lowercase().starts_with("<table"));
+                    if let Some(open_end) = open_end {
+                        html_content.insert_str(open_end + 1, &format!("\n{}", caption_parser(caption, options).trim_end()));
+                        iterator.next();
+                    }
+                }
+                html_stream.push_str(&html_content)
+            },
+            SecondaryToken::Heading(heading) => {
+                let (level, id) = (heading.level, &heading.id);
+                let permalink = match options.permalinks {
+                    true => format!(" <a class=\"permalink\" href=\"#{id}\" aria-hidden=\"true\">#</a>"),
+                    false => String::new(),
+                };
+                let txt_content = format!("<h{level} id=\"{id}\">{}{permalink}</h{level}>\n", inline_tokens_parser_with_options(&mut heading.tokens.clone(), options));
                 html_stream.push_str(&txt_content)
             },
+            SecondaryToken::Math(formula) => {
+                let math_content = format!("<div class=\"math_block\">{}</div>\n", math_parser(formula, true, options));
+                html_stream.push_str(&math_content);
+            },
+            SecondaryToken::DefinitionList(items) => {
+                html_stream.push_str("<dl class=\"definition_list\">\n");
+                for item in items {
+                    let term = format!("<dt>{}</dt>\n", inline_tokens_parser_with_options(&mut item.term.clone(), options));
+                    html_stream.push_str(&term);
+                    for definition in &item.definitions {
+                        let definition = format!("<dd>{}</dd>\n", inline_tokens_parser_with_options(&mut definition.clone(), options));
+                        html_stream.push_str(&definition);
+                    }
+                }
+                html_stream.push_str("</dl>\n");
+            },
+            SecondaryToken::Toc(range) => html_stream.push_str(&toc_parser(&table_of_contents(input, *range))),
             SecondaryToken::List(sec_list) => {
                 let mut list_element = String::new();
-                list_parser(sec_list, &mut list_element);
-
-                list_element.push_str("</ul>\n");
+                list_parser(sec_list, &mut list_element, &mut task_index, options);
                 html_stream.push_str(&list_element);
             },
             _ => {
@@ -124,66 +497,126 @@ pub fn parser(input: &Vec<SecondaryToken>)-> String {
         iterator.next();
     }
 
-    html_stream.push_str("</p>\n</div>\n");
+    html_stream.push_str(&footnotes_parser(input, options));
+    html_stream.push_str("</div>\n");
     html_stream
 }
 
-fn list_parser(sec_list: &SecList, list_element: &mut String) {
-    match &sec_list.r#type {
-        ListType::Todo => {
-            list_element.push_str("<ul style=\"list-style-type:none;\" class=\"todo_list\">\n");
-        },
+fn caption_after(input: &[SecondaryToken], index: usize)-> Option<&SecCaption> {
+    match input.get(index + 1) {
+        Some(SecondaryToken::Caption(caption)) => Some(caption),
+        _ => None,
+    }
+}
+
+/// Renders a caption as the ```<figcaption>``` of its figure or the ```<caption>``` of its table
+fn caption_parser(caption: &SecCaption, options: &ParserOptions)-> String {
+    match caption.kind {
+        CaptionKind::Figure => format!("<figcaption>{}</figcaption>\n", caption_content(caption, options)),
+        CaptionKind::Table => format!("<caption id=\"{}\">{}</caption>\n", caption.id, caption_content(caption, options)),
+    }
+}
+
+fn caption_content(caption: &SecCaption, options: &ParserOptions)-> String {
+    format!(
+        "<span class=\"caption_label\">{} {}:</span> {}",
+        caption.kind.label(), caption.number, inline_tokens_parser_with_options(&mut caption.tokens.clone(), options)
+    )
+}
+
+/// Renders the document as a standalone html page through the ```template``` of the options
+///
+/// The default layout takes the page ```<title>``` from the ```title``` field (or the first heading
+/// when missing) and emits ```author```, ```date```, ```description``` and ```tags``` as ```<meta>``` tags
+pub fn page_parser(document: &Document, options: &ParserOptions)-> String {
+    match &options.template {
+        Some(template) => template.render(document, options),
+        None => default_layout().render(document, options),
+    }
+}
+
+/// Renders a formula with the configured ```math_renderer``` or as delimited text for client side rendering
+pub fn math_parser(formula: &str, display: bool, options: &ParserOptions)-> String {
+    if let Some(renderer) = options.math_renderer {
+        return renderer(formula, display);
+    }
+    match display {
+        true => format!("<span class=\"math display\">\\[{}\\]</span>", escape_html(formula)),
+        false => format!("<span class=\"math inline\">\\({}\\)</span>", escape_html(formula)),
+    }
+}
+
+/// Escapes the html special characters in ```text```
+pub fn escape_html(text: &str)-> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Renders a list, numbering its task items from ```task_index``` on in a ```data-task-index``` attribute
+fn list_parser(sec_list: &SecList, list_element: &mut String, task_index: &mut usize, options: &ParserOptions) {
+    let (tag, open_tag) = match &sec_list.r#type {
+        ListType::Todo => ("ul", String::from("<ul style=\"list-style-type:none;\" class=\"todo_list\">\n")),
         ListType::Roman(case) => {
             match case {
-                block_token::RomanType::Upper => {
-                    list_element.push_str("<ul style=\"list-style-type:upper_roman;\" class=\"roman_list\">\n");
-                },
-                block_token::RomanType::Lower => {
-                    list_element.push_str("<ul style=\"list-style-type:upper_roman;\" class=\"roman_list\">\n");
-                },
+                block_token::RomanType::Upper => ("ol", String::from("<ol style=\"list-style-type: upper-roman;\" class=\"roman_list\">\n")),
+                block_token::RomanType::Lower => ("ol", String::from("<ol style=\"list-style-type: lower-roman;\" class=\"roman_list\">\n")),
             }
         },
-        ListType::Numbered => {
-            list_element.push_str("<ul style=\"list-style-type: decimal;\" class=\"numbered_list\">\n");
-        },
+        ListType::Numbered => ("ol", String::from("<ol style=\"list-style-type: decimal;\" class=\"numbered_list\">\n")),
         ListType::Bullet(bullet_type) => {
             match bullet_type {
-                block_token::BulletType::Default => {
-                    list_element.push_str("<ul style=\"list-style-type: disc;\" class=\"list\">\n");
-                },
-                block_token::BulletType::Custom(name) => {
-                    let open_tag = format!("<ul style=\"list-style-type: {0};\" class=\"{0}-list\">\n", name);
-                    list_element.push_str(&open_tag);
-                },
+                block_token::BulletType::Default => ("ul", String::from("<ul style=\"list-style-type: disc;\" class=\"list\">\n")),
+                shape => ("ul", format!("<ul style=\"list-style-type: {};\" class=\"list {}-list\">\n", shape.list_style(), shape.name())),
             }
         },
         ListType::Alphabetic(case) => {
             match case {
-                block_token::AlphaType::Upper => {
-                    list_element.push_str("<ul style=\"list-style-type: upper-alpha;\" class=\"numbered_list\">\n");
-                },
-                block_token::AlphaType::Lower => {
-                    list_element.push_str("<ul style=\"list-style-type: lower-alpha;\" class=\"numbered_list\">\n");
-                },
+                block_token::AlphaType::Upper => ("ol", String::from("<ol style=\"list-style-type: upper-alpha;\" class=\"alpha_list\">\n")),
+                block_token::AlphaType::Lower => ("ol", String::from("<ol style=\"list-style-type: lower-alpha;\" class=\"alpha_list\">\n")),
             }
         },
-    }
-    
+    };
+    list_element.push_str(&open_tag);
+
+    let mut nests = sec_list.nests.iter();
     for (meta, tokens) in &sec_list.items {
-        let mut text_content = String::new();
-        match meta {
-            ListMeta::None => list_element.push_str("<li>\n"),
-            ListMeta::Checked => list_element.push_str("<li class=\"checked\">\n\t<input type=\"checkbox\" checked>"),
-            ListMeta::Unchecked => list_element.push_str("<li class=\"unchecked\">\n\t<input type=\"checkbox\">"),
+        let task = match meta {
+            ListMeta::None => None,
+            ListMeta::Checked => Some(("checked", " checked")),
+            ListMeta::Unchecked => Some(("unchecked", "")),
+            ListMeta::InProgress => Some(("in_progress", " aria-checked=\"mixed\"")),
+            ListMeta::Cancelled => Some(("cancelled", " disabled")),
+            ListMeta::Important => Some(("important", "")),
+        };
+        match task {
+            Some((class, attributes)) => {
+                let item = format!("<li class=\"{}\" data-task-index=\"{}\">\n\t<input type=\"checkbox\"{}>", class, task_index, attributes);
+                list_element.push_str(&item);
+                *task_index += 1;
+            },
+            None => list_element.push_str("<li>"),
         }
-        if tokens.len() > 0 {
-            text_content.push_str(&inline_tokens_parser(&mut tokens.clone()));
+        if tokens.is_empty() {
+            if let Some(nested) = nests.next() {
+                list_element.push('\n');
+                list_parser(nested, list_element, task_index, options);
+            }
         } else {
-            list_element.push('\t');
-            list_parser(&sec_list.nests[sec_list.is_nested], list_element);
+            list_element.push_str(&inline_tokens_parser_with_options(&mut tokens.clone(), options));
         }
+        list_element.push_str("</li>\n");
     }
-    list_element.push_str("</li>\n");
+    list_element.push_str(&format!("</{}>\n", tag));
 }
 
 pub fn position_parser(position: &InlineTokenPos, tokens: &mut Peekable<std::slice::Iter<'_, InlineToken>>, html: &mut String, fall_back_str: &str)-> Action {
@@ -202,13 +635,41 @@ pub fn position_parser(position: &InlineTokenPos, tokens: &mut Peekable<std::sli
     }
 }
 
-pub  fn inline_tokens_parser(tokens: &mut Vec<InlineToken>)-> String {
+pub  fn inline_tokens_parser(tokens: &mut [InlineToken])-> String {
+    inline_tokens_parser_with_options(tokens, &ParserOptions::default())
+}
+
+pub fn inline_tokens_parser_with_options(tokens: &mut [InlineToken], options: &ParserOptions)-> String {
     let mut inline_formats = String::new();
     let mut peekable = tokens.iter().peekable();
 
     while let Some(token) = peekable.peek() {
         match token {
-            InlineToken::Text(content) => inline_formats.push_str(&content),
+            InlineToken::Text(content) => {
+                let soft_break = match (options.hardbreaks, options.soft_break) {
+                    (true, _) => "<br>\n",
+                    (false, SoftBreak::Newline) => "\n",
+                    (false, SoftBreak::Space) => " ",
+                };
+                inline_formats.push_str(&escape_text(content).replace('\n', soft_break))
+            },
+            InlineToken::HardBreak => inline_formats.push_str("<br>\n"),
+            InlineToken::Html(tag) => inline_formats.push_str(&html_tag_parser(tag, &options.html)),
+            InlineToken::Link(link) => {
+                let element = format!("<a href=\"{}\" class=\"link\">{}</a>", escape_html(&link.href()), escape_text(&link.text));
+                inline_formats.push_str(&element);
+            },
+            InlineToken::Math(math) => inline_formats.push_str(&math_parser(&math.formula, math.display, options)),
+            InlineToken::FootnoteRef(reference) => {
+                let element = match reference.number {
+                    Some(number) => format!(
+                        "<sup class=\"footnote-ref\" id=\"{}\"><a href=\"#fn-{}\">{number}</a></sup>",
+                        escape_html(&footnote_ref_id(&reference.label, reference.occurrence)), escape_html(&reference.label)
+                    ),
+                    None => format!("[^{}]", escape_text(&reference.label)),
+                };
+                inline_formats.push_str(&element);
+            },
             InlineToken::Emoji(inline_token_pos) => {
                 match position_parser(inline_token_pos, &mut peekable, &mut inline_formats, ":") {
                     Action::Break => break,
@@ -277,8 +738,8 @@ pub  fn inline_tokens_parser(tokens: &mut Vec<InlineToken>)-> String {
                     Action::Break => break,
                     Action::Continue(is_open) => {
                         match is_open {
-                            true => inline_formats.push_str("<caption class=\"caption\">"),
-                            false => inline_formats.push_str("</caption>"),
+                            true => inline_formats.push_str("<span class=\"caption\">"),
+                            false => inline_formats.push_str("</span>"),
                         }
                         peekable.next();
                         continue;
@@ -298,30 +759,14 @@ pub  fn inline_tokens_parser(tokens: &mut Vec<InlineToken>)-> String {
                     }
                 }
             },
-            InlineToken::Highlight(inline_token_pos) => {
-                match position_parser(inline_token_pos, &mut peekable, &mut inline_formats, "==") {
-                    Action::Break => break,
-                    Action::Continue(is_open) => {
-                        match is_open {
-                            true => {
-                                peekable.next();
-                                if let Some(token) = peekable.peek() {
-                                    match *token {
-                                        InlineToken::Text(value) => {
-                                            let element = format!("<span style=\"background-color: {value};\" class=\"highlight\">");
-                                            inline_formats.push_str(&element);
-                                            peekable.next();
-                                            continue;
-                                        }
-                                        _ => inline_formats.push_str("==")
-                                    }
-                                }
-                            },
-                            false => inline_formats.push_str("</span>"),
-                        }
-                        peekable.next();
-                        continue;
-                    }
+            InlineToken::Highlight { color, pos } => {
+                match pos {
+                    InlineTokenPos::Open => {
+                        let element = format!("<mark style=\"background-color: {};\" class=\"highlight\">", color);
+                        inline_formats.push_str(&element);
+                    },
+                    InlineTokenPos::Close => inline_formats.push_str("</mark>"),
+                    InlineTokenPos::None => {},
                 }
             },
             _ => {
@@ -336,10 +781,12 @@ pub  fn inline_tokens_parser(tokens: &mut Vec<InlineToken>)-> String {
 }
 
 pub fn list_miner(subject_list: &ListToken)-> SecList {
-    println!("List {:?}\n", subject_list);
+    list_miner_with_options(subject_list, &TokenizerOptions::default())
+}
+
+pub fn list_miner_with_options(subject_list: &ListToken, options: &TokenizerOptions)-> SecList {
     let mut list = SecList {
         r#type: subject_list.r#type.clone(),
-        is_nested: 0,
         items: vec![],
         nests: vec![],
     };
@@ -347,12 +794,10 @@ pub fn list_miner(subject_list: &ListToken)-> SecList {
     for item in &subject_list.items {
         match item.value {
             block_token::ItemValue::Value(ref value) => {
-                list.items.push((item.if_meta.clone(), inline_tokenization(value.clone())));
+                list.items.push((item.if_meta.clone(), inline_tokenization_with_options(value.clone(), options)));
             },
             block_token::ItemValue::Nesting(index) => {
-                let mut nested_list = list_miner(&subject_list.nests[index]);
-                nested_list.is_nested = index;
-                list.nests.push(nested_list);
+                list.nests.push(list_miner_with_options(&subject_list.nests[index], options));
                 list.items.push((item.if_meta.clone(), vec![]));
             },
         }
@@ -360,3 +805,105 @@ pub fn list_miner(subject_list: &ListToken)-> SecList {
 
     list
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXTENSIONS: &str = "Cost $x$, ==hi== and a note[^1]\n\nTerm\n: definition\n\n- [x] done\n\n[^1]: note\n";
+
+    #[test]
+    fn headings_get_unique_ids() {
+        let input = "# Intro\n\n## Intro\n\n## Custom {#intro-1}\n\nSee [the intro][intro-2].\n";
+        let html = parser(&parse_document(input).tokens);
+        for expected in ["<h1 id=\"intro\">Intro</h1>", "<h2 id=\"intro-2\">Intro</h2>", "<h2 id=\"intro-1\">Custom</h2>", "<a href=\"#intro-2\" class=\"link\">the intro</a>"] {
+            assert!(html.contains(expected), "{expected}: {html}");
+        }
+    }
+
+    #[test]
+    fn permalinks_follow_headings() {
+        let options = ParserOptions { permalinks: true, ..ParserOptions::default() };
+        let html = parser_with_options(&parse_document("## Usage\n").tokens, &options);
+        assert!(html.contains("<h2 id=\"usage\">Usage <a class=\"permalink\" href=\"#usage\" aria-hidden=\"true\">#</a></h2>"), "{html}");
+    }
+
+    #[test]
+    fn definitions_follow_their_terms() {
+        let html = parser(&parse_document("Term\n: first\n: second\nOther *term*\n: def\n\n: orphan\n").tokens);
+        assert!(html.contains("<dl class=\"definition_list\">\n<dt>Term</dt>\n<dd>first</dd>\n<dd>second</dd>\n<dt>Other <b class=\"bold\">term</b></dt>\n<dd>def</dd>\n</dl>\n"), "{html}");
+        assert!(html.contains("<p>: orphan</p>"), "{html}");
+    }
+
+    #[test]
+    fn math_goes_through_the_renderer() {
+        let input = "Cost $x<1$\n\n$$\ny\n$$\n";
+        let html = parser(&parse_document(input).tokens);
+        assert!(html.contains("<span class=\"math inline\">\\(x&lt;1\\)</span>"), "{html}");
+        assert!(html.contains("<div class=\"math_block\"><span class=\"math display\">\\[y\\]</span></div>"), "{html}");
+
+        let options = ParserOptions { math_renderer: Some(|formula, display| format!("<math display=\"{display}\">{formula}</math>")), ..ParserOptions::default() };
+        let html = parser_with_options(&parse_document(input).tokens, &options);
+        assert!(html.contains("Cost <math display=\"false\">x<1</math>"), "{html}");
+        assert!(html.contains("<math display=\"true\">y</math>"), "{html}");
+    }
+
+    #[test]
+    fn lines_join_into_paragraphs() {
+        let tokens = parse_document("one\ntwo  \nthree\\\nfour\n\nnext\n").tokens;
+        assert!(parser(&tokens).contains("<p>one\ntwo<br>\nthree<br>\nfour</p>\n<p>next</p>"));
+
+        let options = ParserOptions { soft_break: SoftBreak::Space, ..ParserOptions::default() };
+        assert!(parser_with_options(&tokens, &options).contains("<p>one two<br>\nthree<br>\nfour</p>"));
+        let options = ParserOptions { hardbreaks: true, ..ParserOptions::default() };
+        assert!(parser_with_options(&tokens, &options).contains("<p>one<br>\ntwo<br>\nthree<br>\nfour</p>"));
+    }
+
+    #[test]
+    fn rules_render_their_variant() {
+        let html = parser(&parse_document("____\n\n---(dotted)\n\n--- Part <2> ---\n").tokens);
+        for expected in ["<hr class=\"hr hr-bold\">", "<hr class=\"hr hr-dotted\">", "<div class=\"hr hr-labeled\" role=\"separator\"><span>Part &lt;2&gt;</span></div>"] {
+            assert!(html.contains(expected), "{expected}: {html}");
+        }
+    }
+
+    #[test]
+    fn captions_are_numbered_by_kind() {
+        let input = "```\ncode\n```\n**Code** {#code}\n\n<table><tr><td>1</td></tr></table>\n**Data**\n\n<img src=\"a.png\">\n**Logo**\n\nSee [the code][code].\n";
+        let html = parser(&parse_document(input).tokens);
+        for expected in [
+            "<figure id=\"code\" class=\"figure\">",
+            "<figcaption><span class=\"caption_label\">Figure 1:</span> Code</figcaption>",
+            "<caption id=\"table-1\"><span class=\"caption_label\">Table 1:</span> Data</caption>",
+            "<figure id=\"figure-2\" class=\"figure\">\n<img src=\"a.png\">\n<figcaption><span class=\"caption_label\">Figure 2:</span> Logo</figcaption>",
+            "<a href=\"#code\" class=\"link\">the code</a>",
+        ] {
+            assert!(html.contains(expected), "{expected}: {html}");
+        }
+    }
+
+    #[test]
+    fn extensions_are_read_by_default() {
+        let html = parser(&parse_document(EXTENSIONS).tokens);
+        for expected in ["class=\"math inline\"", "class=\"highlight\"", "class=\"footnote-ref\"", "<dt>Term</dt>", "class=\"checked\""] {
+            assert!(html.contains(expected), "{expected}");
+        }
+    }
+
+    #[test]
+    fn extensions_can_be_turned_off() {
+        let options = TokenizerOptions { autolinks: false, math: false, footnotes: false, definition_lists: false, highlight: false, task_lists: false };
+        let html = parser(&parse_document_with_options(EXTENSIONS, &options).tokens);
+        assert!(html.contains("Cost $x$, ==hi== and a note[^1]"));
+        assert!(html.contains(": definition"));
+        assert!(html.contains("<li>[x] done</li>"));
+        assert!(html.contains("[^1]: note"));
+    }
+
+    #[test]
+    fn short_alignment_lines_are_text() {
+        for input in ["|", "| ", "|<", "|é"] {
+            assert!(parser(&parse_document(input).tokens).starts_with("<div>"), "{input:?}");
+        }
+    }
+}
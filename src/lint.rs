@@ -0,0 +1,519 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::block_token::{atx_heading, block_tokenization_with_lines, bullet_marker, footnote_definition, ordered_list_marker, LineKind};
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::emoji::{emoji, is_shortcode_char};
+use crate::front_matter::extract_front_matter;
+use crate::inline_token::{inline_tokenization_with_options, plain_text, unbalanced_markers, InlineToken, InlineTokenPos, LinkTarget};
+use crate::{parse_document_with_options, Document, SecondaryToken, TokenizerOptions};
+
+/// Prefix of the html comments turning rules off and on, as in ```<!-- markdownit-disable rule -->```
+const COMMENT_PREFIX: &str = "markdownit-";
+
+/// A style check, reporting the 0 based lines it finds a problem on with a message
+pub struct Rule {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Severity of the rule's findings unless configured otherwise
+    pub severity: Severity,
+    check: fn(&Source) -> Vec<(usize, String)>,
+}
+
+/// Every rule, run in this order
+pub const RULES: &[Rule] = &[
+    Rule { name: "heading-increment", description: "Heading levels only go up one at a time", severity: Severity::Warning, check: heading_increment },
+    Rule { name: "duplicate-heading", description: "Headings have different texts", severity: Severity::Warning, check: duplicate_heading },
+    Rule { name: "empty-link", description: "Links have a target, on page references a heading or caption to point to", severity: Severity::Warning, check: empty_link },
+    Rule { name: "unbalanced-marker", description: "Formatting markers open and close in pairs", severity: Severity::Warning, check: unbalanced_marker },
+    Rule { name: "trailing-whitespace", description: "Lines don't end with whitespace, but for a two space hard break", severity: Severity::Info, check: trailing_whitespace },
+    Rule { name: "list-indentation", description: "Nested list items are indented by tabs or by four spaces, consistently", severity: Severity::Warning, check: list_indentation },
+    Rule { name: "unknown-emoji", description: "Emoji shortcodes are known ones", severity: Severity::Warning, check: unknown_emoji },
+];
+
+/// The rule of the given name
+pub fn rule(name: &str)-> Option<&'static Rule> {
+    RULES.iter().find(|rule| rule.name == name)
+}
+
+#[derive(Debug, Clone, Default)]
+/// Switches for ```lint```
+pub struct LintOptions {
+    /// Severity of the named rules in place of their default, ```None``` turning a rule off
+    pub severities: BTreeMap<String, Option<Severity>>,
+    pub tokenizer: TokenizerOptions,
+}
+
+impl LintOptions {
+    /// Sets the severity of a rule from ```error```, ```warning```, ```info``` or ```off```
+    pub fn set_severity(&mut self, name: &str, severity: &str)-> Result<(), String> {
+        if rule(name).is_none() {
+            return Err(format!("unknown rule {:?}", name));
+        }
+        let severity = match severity {
+            "off" => None,
+            severity => Some(severity.parse()?),
+        };
+        self.severities.insert(name.to_owned(), severity);
+        Ok(())
+    }
+
+    fn severity(&self, rule: &Rule)-> Option<Severity> {
+        self.severities.get(rule.name).copied().unwrap_or(Some(rule.severity))
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A problem found by a rule
+pub struct Lint {
+    pub rule: &'static str,
+    pub diagnostic: Diagnostic,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]", self.diagnostic, self.rule)
+    }
+}
+
+/// Checks the document against every rule that isn't turned off, returning the findings in line order
+///
+/// Html comments switch rules off for part of the document, each naming rules or, when naming
+/// none, applying to all of them:
+///
+/// - ```<!-- markdownit-disable rule -->``` from its line on
+/// - ```<!-- markdownit-enable rule -->``` back on from its line on
+/// - ```<!-- markdownit-disable-line rule -->``` on its own line
+/// - ```<!-- markdownit-disable-next-line rule -->``` on the line after it
+pub fn lint(input: &str, options: &LintOptions)-> Vec<Lint> {
+    let source = Source::new(input, &options.tokenizer);
+    let disabled = disabled_rules(&source);
+
+    let mut lints = vec![];
+    for rule in RULES {
+        let Some(severity) = options.severity(rule) else {
+            continue;
+        };
+        for (line, message) in (rule.check)(&source) {
+            if disabled.get(line).is_some_and(|disabled| disabled.covers(rule.name)) {
+                continue;
+            }
+            lints.push(Lint { rule: rule.name, diagnostic: Diagnostic::new(severity, Some(line + 1), message) });
+        }
+    }
+    lints.sort_by_key(|lint| lint.diagnostic.line);
+    lints
+}
+
+/// The document as the rules see it
+struct Source<'a> {
+    lines: Vec<&'a str>,
+    /// Number of lines the front matter takes before the body
+    offset: usize,
+    document: Document,
+    /// The kind of block each line of the body was read into
+    kinds: Vec<LineKind>,
+    /// Runs of inline content, each the 0 based line it starts on and the content of its lines
+    blocks: Vec<(usize, Vec<&'a str>)>,
+    options: &'a TokenizerOptions,
+}
+
+impl<'a> Source<'a> {
+    fn new(input: &'a str, options: &'a TokenizerOptions)-> Source<'a> {
+        let lines: Vec<&str> = input.lines().collect();
+        let offset = extract_front_matter(input).map_or(0, |(_, _, offset)| offset);
+        let kinds = block_tokenization_with_lines(&mut vec![], &lines[offset.min(lines.len())..], options);
+        let mut source = Source {
+            document: parse_document_with_options(input, options),
+            kinds,
+            blocks: vec![],
+            lines,
+            offset,
+            options,
+        };
+
+        // Paragraph lines make up one run, any other inline content is a run of its own
+        let mut blocks: Vec<(usize, Vec<&str>)> = vec![];
+        let mut previous = LineKind::Empty;
+        for index in offset..source.lines.len() {
+            let kind = source.kind(index);
+            if let Some(content) = source.inline_content(index) {
+                match blocks.last_mut() {
+                    Some((_, lines)) if kind == LineKind::Text && previous == LineKind::Text => lines.push(content),
+                    _ => blocks.push((index, vec![content])),
+                }
+            }
+            previous = kind;
+        }
+        source.blocks = blocks;
+        source
+    }
+
+    /// The kind of block the 0 based line was read into, the front matter being ```Other```
+    fn kind(&self, index: usize)-> LineKind {
+        match index.checked_sub(self.offset) {
+            Some(body_index) => self.kinds.get(body_index).copied().unwrap_or(LineKind::Empty),
+            None => LineKind::Other,
+        }
+    }
+
+    /// Whether the line is front matter, math or html, where there's no markdown
+    fn verbatim(&self, index: usize)-> bool {
+        index < self.offset || matches!(self.kind(index), LineKind::Math | LineKind::Html)
+    }
+
+    /// The text of the line the inline tokenizer reads, after the markers of its block
+    fn inline_content(&self, index: usize)-> Option<&'a str> {
+        let line = self.lines[index];
+        match self.kind(index) {
+            LineKind::Text => Some(line),
+            LineKind::Heading => atx_heading(line).map(|(_, text)| text),
+            LineKind::ListItem => list_item(line),
+            LineKind::Blockquote => Some(line.strip_prefix("> ").unwrap_or(line)),
+            LineKind::Definition => line.strip_prefix(": "),
+            LineKind::Footnote => footnote_definition(line).map(|(_, note)| note),
+            LineKind::Caption => Some(line),
+            _ => None,
+        }
+    }
+
+    /// Each line of the inline blocks with its 0 based line and inline tokens
+    fn inline_lines(&self)-> Vec<(usize, Vec<InlineToken>)> {
+        self.blocks.iter()
+            .flat_map(|(first, lines)| lines.iter().enumerate().map(move |(index, line)| (first + index, *line)))
+            .map(|(line, content)| (line, inline_tokenization_with_options(content.to_owned(), self.options)))
+            .collect()
+    }
+}
+
+/// The content of a list item line, after its indentation and marker
+fn list_item(line: &str)-> Option<&str> {
+    let item = line.trim_start_matches([' ', '\t']);
+    match bullet_marker(item) {
+        Some((_, _, marker_len)) => Some(&item[marker_len..]),
+        None => ordered_list_marker(item, None).map(|(_, marker_len)| &item[marker_len..]),
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Rules switched off on a line
+struct Disabled {
+    /// Whether rules not named in ```rules``` are off
+    all: bool,
+    /// Rules switched off, ```true```, or back on, ```false```, by name
+    rules: BTreeMap<String, bool>,
+}
+
+impl Disabled {
+    fn covers(&self, rule: &str)-> bool {
+        self.rules.get(rule).copied().unwrap_or(self.all)
+    }
+
+    fn apply(&mut self, off: bool, rules: &[&str]) {
+        if rules.is_empty() {
+            self.all = off;
+            self.rules.clear();
+        }
+        for rule in rules {
+            self.rules.insert(rule.to_string(), off);
+        }
+    }
+
+    /// Adds the rules ```other``` switches off, all of them when it names none
+    fn merge(&mut self, other: &Disabled) {
+        if other.all {
+            self.all = true;
+            self.rules.clear();
+        }
+        for (rule, off) in &other.rules {
+            if *off {
+                self.rules.insert(rule.clone(), true);
+            }
+        }
+    }
+}
+
+/// The rules switched off on each line by the ```markdownit-disable``` like comments
+fn disabled_rules(source: &Source)-> Vec<Disabled> {
+    let mut lines = vec![Disabled::default(); source.lines.len()];
+    let mut current = Disabled::default();
+    let mut next_line = Disabled::default();
+
+    for (index, line) in source.lines.iter().enumerate() {
+        let mut this_line = std::mem::take(&mut next_line);
+        if !source.verbatim(index) || source.kind(index) == LineKind::Html && line.trim_start().starts_with("<!--") {
+            let mut rest = *line;
+            while let Some(start) = rest.find("<!--") {
+                let Some(end) = rest[start..].find("-->") else { break };
+                let comment = rest[start + 4..start + end].trim();
+                rest = &rest[start + end + 3..];
+
+                let Some(directive) = comment.strip_prefix(COMMENT_PREFIX) else { continue };
+                let mut words = directive.split([' ', '\t', ',']).filter(|word| !word.is_empty());
+                let kind = words.next().unwrap_or_default();
+                let rules: Vec<&str> = words.collect();
+                match kind {
+                    "disable" => current.apply(true, &rules),
+                    "enable" => current.apply(false, &rules),
+                    "disable-line" => this_line.apply(true, &rules),
+                    "disable-next-line" => next_line.apply(true, &rules),
+                    _ => {}
+                }
+            }
+        }
+        lines[index] = current.clone();
+        lines[index].merge(&this_line);
+    }
+    lines
+}
+
+fn heading_increment(source: &Source)-> Vec<(usize, String)> {
+    let mut found = vec![];
+    let mut previous: Option<usize> = None;
+    for token in &source.document.tokens {
+        if let SecondaryToken::Heading(heading) = token {
+            if let Some(previous) = previous.filter(|previous| heading.level > previous + 1) {
+                found.push((source.offset + heading.line, format!("heading level jumps from {} to {}", previous, heading.level)));
+            }
+            previous = Some(heading.level);
+        }
+    }
+    found
+}
+
+fn duplicate_heading(source: &Source)-> Vec<(usize, String)> {
+    let mut found = vec![];
+    let mut seen: BTreeMap<String, usize> = BTreeMap::new();
+    for token in &source.document.tokens {
+        if let SecondaryToken::Heading(heading) = token {
+            let text = plain_text(&heading.tokens).trim().to_owned();
+            let line = source.offset + heading.line;
+            if text.is_empty() {
+                continue;
+            }
+            match seen.get(&text) {
+                Some(first) => found.push((line, format!("heading {:?} is the same as the one on line {}", text, first + 1))),
+                None => {
+                    seen.insert(text, line);
+                }
+            }
+        }
+    }
+    found
+}
+
+fn empty_link(source: &Source)-> Vec<(usize, String)> {
+    let ids: Vec<&str> = source.document.tokens.iter().filter_map(|token| match token {
+        SecondaryToken::Heading(heading) => Some(heading.id.as_str()),
+        SecondaryToken::Caption(caption) => Some(caption.id.as_str()),
+        _ => None,
+    }).collect();
+
+    let mut found = vec![];
+    for (line, tokens) in source.inline_lines() {
+        for token in &tokens {
+            let InlineToken::Link(link) = token else { continue };
+            let text = link.text.trim();
+            match &link.target {
+                LinkTarget::Url(url) if url == "#" => found.push((line, format!("link {:?} has no target", text))),
+                LinkTarget::Reference(id) if !ids.contains(&id.as_str()) => {
+                    found.push((line, format!("link {:?} points to #{}, which no heading or caption has", text, id)));
+                }
+                _ => {}
+            }
+        }
+    }
+    found
+}
+
+fn unbalanced_marker(source: &Source)-> Vec<(usize, String)> {
+    let mut found = vec![];
+    for (first, lines) in &source.blocks {
+        let content: Vec<&str> = lines.iter().map(|line| line.trim()).collect();
+        for marker in unbalanced_markers(&content.join("\n"), source.options) {
+            let line = content.iter().position(|line| line.contains(marker)).unwrap_or(0);
+            found.push((first + line, format!("unbalanced {:?} marker", marker)));
+        }
+    }
+    found
+}
+
+fn trailing_whitespace(source: &Source)-> Vec<(usize, String)> {
+    let mut found = vec![];
+    for (index, line) in source.lines.iter().enumerate().skip(source.offset) {
+        let content = line.trim_end();
+        let trailing = &line[content.len()..];
+        if trailing.is_empty() || trailing == "  " && !content.is_empty() && !source.verbatim(index) {
+            continue;
+        }
+        found.push((index, String::from("trailing whitespace")));
+    }
+    found
+}
+
+fn list_indentation(source: &Source)-> Vec<(usize, String)> {
+    let mut found = vec![];
+    let mut uses_tabs: Option<bool> = None;
+    for (index, line) in source.lines.iter().enumerate().skip(source.offset) {
+        let item = line.trim_start_matches([' ', '\t']);
+        let indentation = &line[..line.len() - item.len()];
+        let markdown = matches!(source.kind(index), LineKind::Text | LineKind::ListItem);
+        if !markdown || indentation.is_empty() || list_item(line).is_none() {
+            continue;
+        }
+
+        let tabs = indentation.chars().all(|c| c == '\t');
+        let spaces = indentation.chars().all(|c| c == ' ');
+        if !tabs && !spaces {
+            found.push((index, String::from("list item indented with both tabs and spaces is read as text")));
+        } else if spaces && !indentation.len().is_multiple_of(4) {
+            found.push((index, format!("list item indented by {} spaces is read as text, nesting takes four spaces per level", indentation.len())));
+        } else if uses_tabs.is_some_and(|uses_tabs| uses_tabs != tabs) {
+            let (used, other) = if tabs { ("spaces", "tabs") } else { ("tabs", "spaces") };
+            found.push((index, format!("list item indented with {} where the document uses {}", other, used)));
+        } else {
+            uses_tabs = Some(tabs);
+        }
+    }
+    found
+}
+
+fn unknown_emoji(source: &Source)-> Vec<(usize, String)> {
+    let mut found = vec![];
+    for (line, tokens) in source.inline_lines() {
+        for (index, token) in tokens.iter().enumerate() {
+            let (InlineToken::Emoji(InlineTokenPos::Open), Some(InlineToken::Text(name))) = (token, tokens.get(index + 1)) else {
+                continue;
+            };
+            let closed = match tokens.get(index + 2) {
+                Some(InlineToken::Text(after)) => after.starts_with(':'),
+                Some(InlineToken::Emoji(InlineTokenPos::Close)) => true,
+                _ => false,
+            };
+            let shortcode = name.chars().all(is_shortcode_char) && name.contains(|c: char| c.is_ascii_lowercase());
+            if closed && shortcode && emoji(name).is_none() {
+                found.push((line, format!("unknown emoji :{}:", name)));
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The rule and 1 based line of each finding
+    fn findings(input: &str)-> Vec<(&'static str, usize)> {
+        lint(input, &LintOptions::default()).into_iter()
+            .map(|lint| (lint.rule, lint.diagnostic.line.unwrap_or_default()))
+            .collect()
+    }
+
+    #[test]
+    fn markdown_after_a_disable_comment_is_checked() {
+        let input = "text\n<!-- markdownit-disable-next-line unknown-emoji -->\n:nope:\n- a\n  - b\n:nope:\n";
+        assert_eq!(findings(input), [("list-indentation", 5), ("unknown-emoji", 6)]);
+    }
+
+    fn rule_findings(input: &str, rule: &str)-> Vec<usize> {
+        findings(input).into_iter().filter(|(name, _)| *name == rule).map(|(_, line)| line).collect()
+    }
+
+    #[test]
+    fn severities_are_configured_by_rule() {
+        let mut options = LintOptions::default();
+        options.set_severity("trailing-whitespace", "error").unwrap();
+        options.set_severity("unknown-emoji", "off").unwrap();
+        assert!(options.set_severity("no-such-rule", "error").is_err());
+        assert!(options.set_severity("empty-link", "loud").is_err());
+
+        let lints = lint("text \n:nope:\n", &options);
+        assert_eq!(lints.len(), 1);
+        assert_eq!((lints[0].rule, lints[0].diagnostic.severity), ("trailing-whitespace", Severity::Error));
+    }
+
+    #[test]
+    fn comments_turn_rules_off() {
+        let input = "\
+<!-- markdownit-disable unknown-emoji -->
+:a1:
+<!-- markdownit-enable unknown-emoji -->
+:a2: <!-- markdownit-disable-line -->
+<!-- markdownit-disable-next-line trailing-whitespace, unknown-emoji -->
+:a3:\x20
+:a4:
+<!-- markdownit-disable -->
+:a5:
+<!-- markdownit-enable trailing-whitespace -->
+:a6:\x20
+";
+        assert_eq!(findings(input), [("unknown-emoji", 7), ("trailing-whitespace", 11)]);
+    }
+
+    #[test]
+    fn line_disables_add_to_the_current_ones() {
+        let mut current = Disabled::default();
+        current.apply(true, &[]);
+        current.apply(false, &["empty-link"]);
+        let mut line = current.clone();
+        let mut this_line = Disabled::default();
+        this_line.apply(true, &["empty-link"]);
+        line.merge(&this_line);
+
+        assert!(current.covers("unknown-emoji") && !current.covers("empty-link"));
+        assert!(line.covers("empty-link"));
+        let mut everything = Disabled::default();
+        everything.apply(true, &[]);
+        line.merge(&everything);
+        assert!(line.covers("unknown-emoji") && line.covers("empty-link"));
+    }
+
+    #[test]
+    fn heading_increment() {
+        assert_eq!(rule_findings("# A\n\n### B\n\n## C\n\n#### D\n", "heading-increment"), [3, 7]);
+        assert!(rule_findings("## A\n\n# B\n\n## C\n", "heading-increment").is_empty());
+    }
+
+    #[test]
+    fn duplicate_heading() {
+        let input = "---\ntitle: t\n---\n# Intro\n\n## *Intro*\n\nIntro\n---\n\n#\n\n#\n";
+        let lints = lint(input, &LintOptions::default());
+        let duplicates: Vec<String> = lints.iter().filter(|lint| lint.rule == "duplicate-heading").map(|lint| lint.to_string()).collect();
+        assert_eq!(duplicates, [
+            "warning: line 6: heading \"Intro\" is the same as the one on line 4 [duplicate-heading]",
+            "warning: line 8: heading \"Intro\" is the same as the one on line 4 [duplicate-heading]",
+        ]);
+    }
+
+    #[test]
+    fn empty_link() {
+        let input = "# Intro\n\n[a](#) [b][intro] [c][nowhere] `[d](#)` [e](x)\n\n<div>[f](#)</div>\n";
+        let messages: Vec<String> = lint(input, &LintOptions::default()).into_iter().map(|lint| lint.diagnostic.message).collect();
+        assert_eq!(messages, ["link \"a\" has no target", "link \"c\" points to #nowhere, which no heading or caption has"]);
+    }
+
+    #[test]
+    fn unbalanced_marker() {
+        assert_eq!(rule_findings("**bold\nstill\n\n- __item\n\n**fine**\n", "unbalanced-marker"), [1, 4]);
+    }
+
+    #[test]
+    fn trailing_whitespace() {
+        let input = "hard  \nbreak\nspace \n  \n- tab\t\n<div>\ntwo  \n</div>\n";
+        assert_eq!(rule_findings(input, "trailing-whitespace"), [3, 4, 5, 7]);
+    }
+
+    #[test]
+    fn list_indentation() {
+        assert_eq!(rule_findings("- a\n  - b\n\t - c\n", "list-indentation"), [2, 3]);
+        assert_eq!(rule_findings("- a\n    - b\n- c\n\t- d\n", "list-indentation"), [4]);
+        assert!(rule_findings("<div>\n  - b\n</div>\n\n```\n- a\n```\n", "list-indentation").is_empty());
+    }
+
+    #[test]
+    fn unknown_emoji() {
+        let input = "at 10:30:00, a:b:c, :smile: and `:nope:`\n\n- :nope: and :Nope:\n\n> :bad:\n\n$$\n:math:\n$$\n";
+        assert_eq!(rule_findings(input, "unknown-emoji"), [3, 5]);
+    }
+}
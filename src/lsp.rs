@@ -0,0 +1,544 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+use crate::block_token::{block_tokenization, BlockToken, ItemValue, ListToken};
+use crate::diagnostic::Severity;
+use crate::emoji::{is_shortcode_char, SHORTCODES};
+use crate::front_matter::extract_front_matter;
+use crate::inline_token::{plain_text, scan_footnote_ref};
+use crate::json::Json;
+use crate::{parse_document, SecondaryToken};
+
+/// Directives offered after ```::```, with the text inserted and what they do
+pub const DIRECTIVES: &[(&str, &str, &str)] = &[
+    ("toc", "toc min=1 max=6", "Table of contents of the headings from level min to max"),
+    ("form", "form method=\"post\"", "Opens a form, closed by ::endform"),
+    ("password", "password name=\"\" label=\"\" place_holder=\"\"", "Password input of a form"),
+    ("submit", "submit value=\"\"", "Submit button of a form"),
+    ("endform", "endform", "Closes a form"),
+    ("table", "table headers=\"\"", "Opens a table with comma separated headers, closed by ::endtable"),
+    ("row", "row ", "Row of comma separated cells of a table"),
+    ("endtable", "endtable", "Closes a table"),
+];
+
+#[derive(Debug, Clone)]
+/// A heading of the document along with the lines of its section, lines being 0 based
+pub struct Symbol {
+    pub name: String,
+    pub level: usize,
+    pub line: usize,
+    /// Last line before the next heading of the same or a shallower level
+    pub end_line: usize,
+    pub children: Vec<Symbol>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompletionKind {
+    Directive,
+    Emoji,
+}
+
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub kind: CompletionKind,
+    pub label: String,
+    pub detail: String,
+    /// Replaces the line from byte ```start``` up to the cursor
+    pub text: String,
+    pub start: usize,
+}
+
+/// Lines taken by the front matter, which the lines of the tokens don't count
+fn front_matter_lines(text: &str)-> usize {
+    extract_front_matter(text).map_or(0, |(_, _, lines)| lines)
+}
+
+/// The headings of the document as a hierarchy of sections
+pub fn document_symbols(text: &str)-> Vec<Symbol> {
+    let offset = front_matter_lines(text);
+    let last_line = text.lines().count().saturating_sub(1);
+
+    let headings: Vec<(usize, String, usize)> = parse_document(text).tokens.iter().filter_map(|token| match token {
+        SecondaryToken::Heading(heading) => Some((heading.level, plain_text(&heading.tokens), heading.line + offset)),
+        _ => None,
+    }).collect();
+
+    let symbols: Vec<Symbol> = headings.iter().enumerate().map(|(index, (level, name, line))| {
+        let next = headings[index + 1..].iter().find(|(next_level, _, _)| next_level <= level);
+        Symbol {
+            name: name.clone(),
+            level: *level,
+            line: *line,
+            end_line: next.map_or(last_line, |(_, _, next_line)| next_line.saturating_sub(1).max(*line)),
+            children: vec![],
+        }
+    }).collect();
+
+    let mut index = 0;
+    nest_symbols(&symbols, &mut index, 0)
+}
+
+fn nest_symbols(symbols: &[Symbol], index: &mut usize, parent_level: usize)-> Vec<Symbol> {
+    let mut nested = vec![];
+    while let Some(symbol) = symbols.get(*index) {
+        if symbol.level <= parent_level {
+            break;
+        }
+        *index += 1;
+
+        let mut symbol = symbol.clone();
+        symbol.children = nest_symbols(symbols, index, symbol.level);
+        nested.push(symbol);
+    }
+    nested
+}
+
+/// Foldable line ranges of the document: code blocks, blockquotes, lists and list items holding
+/// a nested list
+pub fn folding_ranges(text: &str)-> Vec<(usize, usize)> {
+    let offset = front_matter_lines(text);
+    let body_lines: Vec<&str> = text.lines().skip(offset).collect();
+    let mut ranges = vec![];
+
+    let mut fence_start = None;
+    let mut quote_start = None;
+    for (index, line) in body_lines.iter().enumerate() {
+        if line.trim() == "```" {
+            match fence_start.take() {
+                Some(start) => ranges.push((start, index)),
+                None => fence_start = Some(index),
+            }
+            continue;
+        }
+        if fence_start.is_some() {
+            continue;
+        }
+
+        // A blockquote goes on until an empty line
+        if line.trim().is_empty() {
+            if let Some(start) = quote_start.take() {
+                ranges.push((start, index - 1));
+            }
+        } else if quote_start.is_none() && line.starts_with('>') {
+            quote_start = Some(index);
+        }
+    }
+    if let Some(start) = quote_start {
+        ranges.push((start, body_lines.len().saturating_sub(1)));
+    }
+
+    let mut blocks = vec![];
+    block_tokenization(&mut blocks, &body_lines);
+    for block in &blocks {
+        if let BlockToken::List(list) = block {
+            list_ranges(list, &mut ranges);
+        }
+    }
+
+    let mut ranges: Vec<(usize, usize)> = ranges.into_iter()
+        .filter(|(start, end)| end > start)
+        .map(|(start, end)| (start + offset, end + offset))
+        .collect();
+    ranges.sort();
+    ranges.dedup();
+    ranges
+}
+
+/// Adds the range of the list and of its items holding a nested list, returning the first and last
+/// line of the list
+fn list_ranges(list: &ListToken, ranges: &mut Vec<(usize, usize)>)-> (usize, usize) {
+    let (mut first, mut last) = (usize::MAX, 0);
+    let mut previous_line = None;
+    for item in &list.items {
+        let (item_first, item_last) = match item.value {
+            ItemValue::Nesting(index) => {
+                let (nested_first, nested_last) = list_ranges(&list.nests[index], ranges);
+                if let Some(line) = previous_line {
+                    ranges.push((line, nested_last));
+                }
+                (nested_first, nested_last)
+            }
+            ItemValue::Value(_) => {
+                previous_line = Some(item.line);
+                (item.line, item.line)
+            }
+        };
+        first = first.min(item_first);
+        last = last.max(item_last);
+    }
+
+    ranges.push((first, last));
+    (first, last)
+}
+
+/// Completions at byte ```column``` of ```line```: directives on a line starting with ```::``` and
+/// emoji after a ```:```
+pub fn completions(text: &str, line: usize, column: usize)-> Vec<Completion> {
+    let Some(source_line) = text.lines().nth(line) else { return vec![] };
+    let Some(prefix) = source_line.get(..column.min(source_line.len())) else { return vec![] };
+
+    if let Some(name) = prefix.strip_prefix("::").filter(|name| name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')) {
+        return DIRECTIVES.iter()
+            .filter(|(directive, _, _)| directive.starts_with(name))
+            .map(|(directive, insert, detail)| Completion {
+                kind: CompletionKind::Directive,
+                label: format!("::{}", directive),
+                detail: detail.to_string(),
+                text: format!("::{}", insert),
+                start: 0,
+            })
+            .collect();
+    }
+
+    let Some(start) = prefix.rfind(':') else { return vec![] };
+    let typed = &prefix[start + 1..];
+    let opens = prefix[..start].chars().next_back().is_none_or(|c| !c.is_alphanumeric());
+    if !opens || !typed.chars().all(is_shortcode_char) {
+        return vec![];
+    }
+
+    SHORTCODES.iter()
+        .filter(|(shortcode, _)| shortcode.starts_with(typed))
+        .map(|(shortcode, emoji)| Completion {
+            kind: CompletionKind::Emoji,
+            label: format!(":{}:", shortcode),
+            detail: emoji.to_string(),
+            text: format!(":{}:", shortcode),
+            start,
+        })
+        .collect()
+}
+
+/// Line defining what the reference at byte ```column``` of ```line``` points to: the heading of
+/// a ```[text][id-ref]``` or ```[text](#id)``` link, or the definition of a ```[^label]``` footnote
+pub fn definition(text: &str, line: usize, column: usize)-> Option<usize> {
+    let source_line = text.lines().nth(line)?;
+
+    for (start, _) in source_line.match_indices("[^") {
+        let Some((label, _)) = scan_footnote_ref(source_line[start..].chars().peekable()) else { continue };
+        let end = start + label.len() + 2;
+        if (start..=end).contains(&column) {
+            let definition = format!("[^{}]:", label);
+            return text.lines().position(|candidate| candidate.starts_with(&definition));
+        }
+    }
+
+    for (separator, closing) in [("][", ']'), ("](#", ')')] {
+        for (index, _) in source_line.match_indices(separator) {
+            let id_start = index + separator.len();
+            let Some(id_length) = source_line[id_start..].find(closing) else { continue };
+            let start = source_line[..index].rfind('[').unwrap_or(index);
+            if !(start..=id_start + id_length).contains(&column) {
+                continue;
+            }
+
+            let id = &source_line[id_start..id_start + id_length];
+            let offset = front_matter_lines(text);
+            return parse_document(text).tokens.iter().find_map(|token| match token {
+                SecondaryToken::Heading(heading) if heading.id == id => Some(heading.line + offset),
+                _ => None,
+            });
+        }
+    }
+    None
+}
+
+/// Runs a language server over the given streams until the client sends ```exit``` or closes
+/// the input, returning whether the client asked for a ```shutdown``` first
+///
+/// Documents are synchronized in full, each change publishing the diagnostics of the tokenizer
+pub fn run(mut input: impl BufRead, output: impl Write)-> io::Result<bool> {
+    let mut server = Server { output, documents: BTreeMap::new(), shutdown: false };
+
+    while let Some(body) = read_message(&mut input)? {
+        let message = match Json::parse(&body) {
+            Ok(message) => message,
+            Err(error) => {
+                server.send(Json::object([
+                    ("jsonrpc", "2.0".into()),
+                    ("id", Json::Null),
+                    ("error", Json::object([("code", Json::Number(-32700.0)), ("message", error.into())])),
+                ]))?;
+                continue;
+            }
+        };
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+
+        match (message.get("method").and_then(Json::as_str), message.get("id")) {
+            (Some("exit"), _) => break,
+            (Some(method), Some(id)) => {
+                let response = match server.request(method, &params) {
+                    Ok(result) => ("result", result),
+                    Err((code, error)) => ("error", Json::object([("code", Json::Number(code as f64)), ("message", error.into())])),
+                };
+                server.send(Json::object([("jsonrpc", "2.0".into()), ("id", id.clone()), response]))?;
+            }
+            (Some(method), None) => server.notification(method, &params)?,
+            // Responses to requests of the server, which sends none
+            (None, _) => {}
+        }
+    }
+    Ok(server.shutdown)
+}
+
+struct Server<W> {
+    output: W,
+    /// Text of each open document by uri
+    documents: BTreeMap<String, String>,
+    shutdown: bool,
+}
+
+impl<W: Write> Server<W> {
+    fn send(&mut self, message: Json)-> io::Result<()> {
+        let body = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.output.flush()
+    }
+
+    fn request(&mut self, method: &str, params: &Json)-> Result<Json, (i64, String)> {
+        if method == "initialize" {
+            return Ok(Json::object([
+                ("capabilities", Json::object([
+                    ("textDocumentSync", 1.into()),
+                    ("documentSymbolProvider", true.into()),
+                    ("foldingRangeProvider", true.into()),
+                    ("completionProvider", Json::object([("triggerCharacters", vec![":".into()].into())])),
+                    ("definitionProvider", true.into()),
+                ])),
+                ("serverInfo", Json::object([("name", "markdownit".into()), ("version", env!("CARGO_PKG_VERSION").into())])),
+            ]));
+        }
+        if method == "shutdown" {
+            self.shutdown = true;
+            return Ok(Json::Null);
+        }
+
+        let uri = params.get("textDocument").and_then(|document| document.get("uri")).and_then(Json::as_str);
+        let Some(text) = uri.and_then(|uri| self.documents.get(uri)) else {
+            return match method.starts_with("textDocument/") {
+                true => Err((-32602, String::from("unknown document"))),
+                false => Err((-32601, format!("unsupported method {}", method))),
+            };
+        };
+        let position = params.get("position").and_then(|position| {
+            let line = position.get("line")?.as_usize()?;
+            let character = position.get("character")?.as_usize()?;
+            Some((line, to_column(text.lines().nth(line).unwrap_or_default(), character)))
+        });
+
+        match method {
+            "textDocument/documentSymbol" => Ok(document_symbols(text).iter().map(|symbol| symbol_json(text, symbol)).collect::<Vec<_>>().into()),
+            "textDocument/foldingRange" => Ok(folding_ranges(text).into_iter()
+                .map(|(start, end)| Json::object([("startLine", start.into()), ("endLine", end.into())]))
+                .collect::<Vec<_>>()
+                .into()),
+            "textDocument/completion" => {
+                let (line, column) = position.ok_or((-32602, String::from("missing position")))?;
+                let source_line = text.lines().nth(line).unwrap_or_default();
+                Ok(completions(text, line, column).into_iter().map(|completion| Json::object([
+                    ("label", completion.label.into()),
+                    ("kind", match completion.kind {
+                        CompletionKind::Directive => 14.into(),
+                        CompletionKind::Emoji => 21.into(),
+                    }),
+                    ("detail", completion.detail.into()),
+                    ("textEdit", Json::object([
+                        ("range", range(line, to_character(source_line, completion.start), line, to_character(source_line, column))),
+                        ("newText", completion.text.into()),
+                    ])),
+                ])).collect::<Vec<_>>().into())
+            }
+            "textDocument/definition" => {
+                let (line, column) = position.ok_or((-32602, String::from("missing position")))?;
+                Ok(definition(text, line, column).map_or(Json::Null, |target| Json::object([
+                    ("uri", uri.unwrap_or_default().into()),
+                    ("range", range(target, 0, target, 0)),
+                ])))
+            }
+            _ => Err((-32601, format!("unsupported method {}", method))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Json)-> io::Result<()> {
+        let document = params.get("textDocument");
+        let Some(uri) = document.and_then(|document| document.get("uri")).and_then(Json::as_str) else {
+            return Ok(());
+        };
+
+        match method {
+            "textDocument/didOpen" => {
+                let text = document.and_then(|document| document.get("text")).and_then(Json::as_str).unwrap_or_default();
+                self.documents.insert(uri.to_owned(), text.to_owned());
+            }
+            "textDocument/didChange" => {
+                let changes = params.get("contentChanges").and_then(Json::as_array).unwrap_or_default();
+                if let Some(text) = changes.iter().rev().find_map(|change| change.get("text")).and_then(Json::as_str) {
+                    self.documents.insert(uri.to_owned(), text.to_owned());
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+            }
+            _ => return Ok(()),
+        }
+        self.publish_diagnostics(uri)
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str)-> io::Result<()> {
+        let diagnostics: Vec<Json> = match self.documents.get(uri) {
+            Some(text) => parse_document(text).diagnostics.iter().map(|diagnostic| {
+                let line = diagnostic.line.map_or(0, |line| line.saturating_sub(1));
+                let length = to_character(text.lines().nth(line).unwrap_or_default(), usize::MAX);
+                Json::object([
+                    ("range", range(line, 0, line, length)),
+                    ("severity", match diagnostic.severity {
+                        Severity::Error => 1.into(),
+                        Severity::Warning => 2.into(),
+                        Severity::Info => 3.into(),
+                    }),
+                    ("source", "markdownit".into()),
+                    ("message", diagnostic.message.clone().into()),
+                ])
+            }).collect(),
+            None => vec![],
+        };
+
+        self.send(Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/publishDiagnostics".into()),
+            ("params", Json::object([("uri", uri.into()), ("diagnostics", diagnostics.into())])),
+        ]))
+    }
+}
+
+fn symbol_json(text: &str, symbol: &Symbol)-> Json {
+    let line_length = |line: usize| to_character(text.lines().nth(line).unwrap_or_default(), usize::MAX);
+    Json::object([
+        ("name", match symbol.name.is_empty() {
+            true => "(empty heading)".into(),
+            false => symbol.name.clone().into(),
+        }),
+        ("detail", format!("h{}", symbol.level).into()),
+        // String, as a section of text
+        ("kind", 15.into()),
+        ("range", range(symbol.line, 0, symbol.end_line, line_length(symbol.end_line))),
+        ("selectionRange", range(symbol.line, 0, symbol.line, line_length(symbol.line))),
+        ("children", symbol.children.iter().map(|child| symbol_json(text, child)).collect::<Vec<_>>().into()),
+    ])
+}
+
+fn range(start_line: usize, start_character: usize, end_line: usize, end_character: usize)-> Json {
+    Json::object([
+        ("start", Json::object([("line", start_line.into()), ("character", start_character.into())])),
+        ("end", Json::object([("line", end_line.into()), ("character", end_character.into())])),
+    ])
+}
+
+/// Byte index of a position given in UTF-16 code units, as protocol positions are
+fn to_column(line: &str, character: usize)-> usize {
+    let mut units = 0;
+    for (index, c) in line.char_indices() {
+        if units >= character {
+            return index;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+/// Position in UTF-16 code units of a byte index of the line
+fn to_character(line: &str, column: usize)-> usize {
+    line.char_indices().take_while(|(index, _)| *index < column).map(|(_, c)| c.len_utf16()).sum()
+}
+
+fn read_message(input: &mut impl BufRead)-> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            match length {
+                Some(_) => break,
+                None => continue,
+            }
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or_default()];
+    input.read_exact(&mut body)?;
+    Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(completions: &[Completion])-> Vec<&str> {
+        completions.iter().map(|completion| completion.label.as_str()).collect()
+    }
+
+    #[test]
+    fn completes_directives_and_emoji() {
+        assert_eq!(labels(&completions("::t", 0, 3)), ["::toc", "::table"]);
+        assert_eq!(completions("::t", 0, 3)[0].text, "::toc min=1 max=6");
+
+        let emoji = completions("Hi :smil there", 0, 8);
+        assert!(labels(&emoji).contains(&":smile:"));
+        assert!(emoji.iter().all(|completion| completion.kind == CompletionKind::Emoji && completion.start == 3 && completion.label.starts_with(":smil")));
+
+        assert!(completions("at 10:3", 0, 7).is_empty());
+        assert!(completions("a :b c", 0, 6).is_empty());
+        assert!(completions("é", 0, 1).is_empty());
+        assert!(completions("text", 3, 0).is_empty());
+    }
+
+    #[test]
+    fn finds_definitions() {
+        let text = "---\ntitle: t\n---\n# Intro\n\nSee [^1], [a][intro] and [b](#intro) [c][none]\nAn [^open then [x][intro]\nA [^bad label] and [y][intro]\n\n[^1]: note\n";
+        assert_eq!(definition(text, 5, 6), Some(9));
+        assert_eq!(definition(text, 5, 12), Some(3));
+        assert_eq!(definition(text, 5, 32), Some(3));
+        assert_eq!(definition(text, 5, 40), None);
+        assert_eq!(definition(text, 6, 20), Some(3));
+        assert_eq!(definition(text, 7, 22), Some(3));
+        assert_eq!(definition(text, 7, 5), None);
+    }
+
+    #[test]
+    fn folds_code_quotes_and_lists() {
+        let text = "---\na: b\n---\n```\ncode\n```\n\n> quote\n> more\n\n- a\n    - b\n    - c\n- d\n";
+        assert_eq!(folding_ranges(text), [(3, 5), (7, 8), (10, 12), (10, 13), (11, 12)]);
+    }
+
+    #[test]
+    fn lists_sections_as_symbols() {
+        let text = "---\na: b\n---\n# A\n\n## B\n\ntext\n\n### C\n\n# D\n";
+        let symbols = document_symbols(text);
+        let outline: Vec<(&str, usize, usize, usize)> = symbols.iter()
+            .flat_map(|symbol| std::iter::once(symbol).chain(symbol.children.iter()).chain(symbol.children.iter().flat_map(|child| child.children.iter())))
+            .map(|symbol| (symbol.name.as_str(), symbol.line, symbol.end_line, symbol.children.len()))
+            .collect();
+        assert_eq!(outline, [("A", 3, 10, 1), ("B", 5, 10, 1), ("C", 9, 10, 0), ("D", 11, 11, 0)]);
+    }
+
+    #[test]
+    fn converts_utf16_positions() {
+        let line = "é😀a";
+        assert_eq!(to_column(line, 0), 0);
+        assert_eq!(to_column(line, 1), 2);
+        assert_eq!(to_column(line, 3), 6);
+        assert_eq!(to_column(line, 4), 7);
+        assert_eq!(to_column(line, 9), line.len());
+        assert_eq!(to_character(line, 2), 1);
+        assert_eq!(to_character(line, 6), 3);
+        assert_eq!(to_character(line, usize::MAX), 4);
+    }
+}
@@ -1,11 +1,649 @@
+use std::env;
 use std::fs;
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-use markdownit::{parser, tokenizer};
+use markdownit::diagnostic::Severity;
+use markdownit::fmt::{format_document, FormatOptions, DEFAULT_WIDTH};
+use markdownit::html::HtmlPolicy;
+use markdownit::json::Json;
+use markdownit::lint::{lint, LintOptions, RULES};
+use markdownit::site::{build_site, Site, SiteOptions, SiteReport};
+use markdownit::template::Template;
+use markdownit::text::{text_parser, TextOptions};
+use markdownit::watch::Watcher;
+use markdownit::{page_parser, parse_document_with_options, parser_with_options, ParserOptions, SoftBreak, TokenizerOptions};
 
+const USAGE: &str = "\
+Usage: markdownit [OPTIONS] [FILE]...
+       markdownit build [OPTIONS] <DIR>
+       markdownit serve [OPTIONS] <DIR>
+       markdownit fmt [OPTIONS] [FILE]...
+       markdownit lint [OPTIONS] [FILE]...
 
-fn main() {
-    let input = fs::read_to_string("./simple.txt").unwrap();
-    let tokens = tokenizer(&input);
-    let html = parser(&tokens);
-    // println!("{}", html);
+Converts markdownit documents to html. Files are read in order as one document, standard
+input is read when no file is given or for a file named -.
+
+The build command converts every .md and .mdx file under DIR into a standalone page of a
+static site, mirroring the directory tree into the output directory (_site by default).
+Links to markdown files are pointed at their pages, other files are copied as they are and
+directories without an index.md get a generated index.html.
+
+The serve command previews DIR on http://127.0.0.1, rendering each document as it is
+requested and reloading the open pages whenever a file under DIR or the template changes.
+
+The fmt command rewrites each FILE in canonical form, or formats standard input to standard
+output when no file is given: normalized headings, list markers and indentation, and
+paragraphs wrapped at the --width. The formatted document always parses the same, a file
+that can't be formatted without changing how it parses is reported and left as it is.
+
+The lint command checks each FILE, or standard input, against the style rules below and
+prints what it finds. A rule is turned off or on again from a line of the document on with a
+<!-- markdownit-disable RULE... --> or <!-- markdownit-enable RULE... --> comment, for one line
+with markdownit-disable-line or markdownit-disable-next-line, every rule when none is named.
+
+Options:
+  -o, --output <FILE>       Write to FILE instead of standard output, or the site to directory FILE
+  -f, --format <FORMAT>     Output format: html (default), text or tokens, text (default) or json for lint
+  -s, --standalone          Emit a full html page with a <head> and stylesheet
+      --css <URL>           Link URL as the page stylesheet instead of embedding the default one
+      --template <FILE>     Lay standalone pages out with the template FILE, implies --standalone
+  -w, --watch               Convert again whenever an input or the template changes, rebuilding
+                            only the affected pages of a site
+  -p, --port <PORT>         Port the serve command listens on, 8000 by default
+      --width <COLUMNS>     Width fmt wraps paragraphs at, 80 by default, 0 to keep their lines;
+                            text output is only wrapped when given
+      --check               Only list the files fmt would change, exiting with status 1 if any
+      --rule <RULE=LEVEL>   Report the lint RULE as error, warning or info, or turn it off
+      --html <POLICY>       Raw html handling: passthrough (default), escape or strip
+      --allow-tag <NAME>    Keep the raw html tag NAME with its safe attributes, may be repeated
+      --no-autolinks        Leave bare urls and email addresses as text
+      --no-math             Leave $math$ and $$math$$ as text
+      --no-footnotes        Leave [^label] references and definitions as text
+      --no-definition-lists Leave : definition lines as text
+      --no-highlight        Leave ==highlights== as text
+      --no-task-lists       Read - [ ] items as plain list items
+      --permalinks          Add a # permalink to each heading
+      --hardbreaks          Render every line break inside a paragraph as <br>
+      --soft-break <MODE>   Render line breaks inside a paragraph as a newline (default) or space
+  -h, --help                Print this help
+  -V, --version             Print the version
+
+Exits with status 1 when a document has error diagnostics and 2 on usage or io errors.
+
+Lint rules:
+";
+
+/// The usage text, ending with the lint rules
+fn usage()-> String {
+    let mut usage = String::from(USAGE);
+    for rule in RULES {
+        usage.push_str(&format!("  {:<22}{} ({})\n", rule.name, rule.description, rule.severity));
+    }
+    usage
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Html,
+    /// Plain text with the formatting dropped
+    Text,
+    /// The tokenized document, for debugging the tokenizer
+    Tokens,
+    /// The lint findings as a JSON array
+    Json,
+}
+
+#[derive(Debug)]
+struct Convert {
+    inputs: Vec<String>,
+    output: Option<String>,
+    format: Format,
+    standalone: bool,
+    /// Path of the ```--template``` layout, loaded when running the command
+    template: Option<String>,
+    watch: bool,
+    port: Option<u16>,
+    width: Option<usize>,
+    check: bool,
+    lint_options: LintOptions,
+    tokenizer_options: TokenizerOptions,
+    parser_options: ParserOptions,
+}
+
+#[derive(Debug)]
+struct Build {
+    source: PathBuf,
+    output: PathBuf,
+    template: Option<String>,
+    watch: bool,
+    options: SiteOptions,
+}
+
+#[derive(Debug)]
+struct Serve {
+    root: PathBuf,
+    port: u16,
+    template: Option<String>,
+    options: SiteOptions,
+}
+
+#[derive(Debug)]
+struct Fmt {
+    inputs: Vec<String>,
+    check: bool,
+    options: FormatOptions,
+}
+
+#[derive(Debug)]
+struct Lint {
+    inputs: Vec<String>,
+    json: bool,
+    options: LintOptions,
+}
+
+#[derive(Debug)]
+enum Command {
+    Help,
+    Version,
+    Convert(Convert),
+    Build(Build),
+    Serve(Serve),
+    Fmt(Fmt),
+    Lint(Lint),
+}
+
+/// A source file of the converted document, with the line of the joined input it starts on
+struct Source {
+    name: String,
+    first_line: usize,
+}
+
+fn main()-> ExitCode {
+    let command = match parse_args(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("markdownit: {}\n\n{}", message, usage());
+            return ExitCode::from(2);
+        }
+    };
+
+    let result = match command {
+        Command::Help => {
+            print!("{}", usage());
+            return ExitCode::SUCCESS;
+        }
+        Command::Version => {
+            println!("markdownit {}", env!("CARGO_PKG_VERSION"));
+            return ExitCode::SUCCESS;
+        }
+        Command::Convert(convert) if convert.watch => watch_convert(&convert),
+        Command::Convert(convert) => {
+            with_template(&convert.parser_options, convert.template.as_deref())
+                .and_then(|parser_options| run_convert(&convert, &parser_options))
+        }
+        Command::Build(build) if build.watch => watch_build(&build),
+        Command::Build(build) => run_build(&build),
+        Command::Serve(serve) => run_serve(&serve),
+        Command::Fmt(fmt) => run_fmt(&fmt),
+        Command::Lint(lint) => run_lint(&lint),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(message) => {
+            eprintln!("markdownit: {}", message);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn parse_args(args: impl Iterator<Item = String>)-> Result<Command, String> {
+    let mut convert = Convert {
+        inputs: vec![],
+        output: None,
+        format: Format::Html,
+        standalone: false,
+        template: None,
+        watch: false,
+        port: None,
+        width: None,
+        check: false,
+        lint_options: LintOptions::default(),
+        tokenizer_options: TokenizerOptions::default(),
+        parser_options: ParserOptions::default(),
+    };
+
+    let mut args = args.peekable();
+    let subcommand = args.next_if(|arg| matches!(arg.as_str(), "build" | "serve" | "fmt" | "lint"));
+    while let Some(arg) = args.next() {
+        // ```--name=value``` is taken as ```--name value```
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_owned(), Some(value.to_owned())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| -> Result<String, String> {
+            match inline_value.clone().or_else(|| args.next()) {
+                Some(value) => Ok(value),
+                None => Err(format!("{} expects a value", name)),
+            }
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-o" | "--output" => convert.output = Some(value(&flag)?),
+            "-f" | "--format" => {
+                convert.format = match value(&flag)?.as_str() {
+                    "html" => Format::Html,
+                    "text" => Format::Text,
+                    "tokens" => Format::Tokens,
+                    "json" => Format::Json,
+                    other => return Err(format!("unknown format {:?}, expected html, text, tokens or json", other)),
+                }
+            }
+            "-s" | "--standalone" => convert.standalone = true,
+            "--css" => convert.parser_options.stylesheet = Some(value(&flag)?),
+            "--template" => {
+                convert.template = Some(value(&flag)?);
+                convert.standalone = true;
+            }
+            "-w" | "--watch" => convert.watch = true,
+            "-p" | "--port" => {
+                let port = value(&flag)?;
+                convert.port = Some(port.parse().map_err(|_| format!("invalid port {:?}", port))?);
+            }
+            "--width" => {
+                let width = value(&flag)?;
+                convert.width = Some(width.parse().map_err(|_| format!("invalid width {:?}", width))?);
+            }
+            "--check" => convert.check = true,
+            "--rule" => {
+                let rule = value(&flag)?;
+                let Some((name, severity)) = rule.split_once('=') else {
+                    return Err(format!("--rule expects RULE=LEVEL, got {:?}", rule));
+                };
+                convert.lint_options.set_severity(name, severity)?;
+            }
+            "--html" => {
+                convert.parser_options.html.policy = match value(&flag)?.as_str() {
+                    "passthrough" => HtmlPolicy::Passthrough,
+                    "escape" => HtmlPolicy::Escape,
+                    "strip" => HtmlPolicy::Strip,
+                    other => return Err(format!("unknown html policy {:?}, expected passthrough, escape or strip", other)),
+                }
+            }
+            "--allow-tag" => convert.parser_options.html.allowed_tags.push(value(&flag)?),
+            "--no-autolinks" => convert.tokenizer_options.autolinks = false,
+            "--no-math" => convert.tokenizer_options.math = false,
+            "--no-footnotes" => convert.tokenizer_options.footnotes = false,
+            "--no-definition-lists" => convert.tokenizer_options.definition_lists = false,
+            "--no-highlight" => convert.tokenizer_options.highlight = false,
+            "--no-task-lists" => convert.tokenizer_options.task_lists = false,
+            "--permalinks" => convert.parser_options.permalinks = true,
+            "--hardbreaks" => convert.parser_options.hardbreaks = true,
+            "--soft-break" => {
+                convert.parser_options.soft_break = match value(&flag)?.as_str() {
+                    "newline" => SoftBreak::Newline,
+                    "space" => SoftBreak::Space,
+                    other => return Err(format!("unknown soft break {:?}, expected newline or space", other)),
+                }
+            }
+            "-" => convert.inputs.push(arg),
+            unknown if unknown.starts_with('-') => return Err(format!("unknown option {}", unknown)),
+            _ => convert.inputs.push(arg),
+        }
+    }
+
+    let Some(subcommand) = subcommand else {
+        if convert.format == Format::Json {
+            return Err(String::from("json is the output format of lint"));
+        }
+        if convert.watch && (convert.inputs.is_empty() || convert.inputs.iter().any(|input| input == "-")) {
+            return Err(String::from("--watch needs input files, standard input can't be watched"));
+        }
+        return Ok(Command::Convert(convert));
+    };
+    if subcommand == "lint" {
+        if convert.output.is_some() || convert.watch {
+            return Err(String::from("lint only reports on its files"));
+        }
+        convert.lint_options.tokenizer = convert.tokenizer_options;
+        return Ok(Command::Lint(Lint {
+            inputs: convert.inputs,
+            json: match convert.format {
+                Format::Json => true,
+                Format::Html | Format::Text => false,
+                Format::Tokens => return Err(String::from("lint writes text or json")),
+            },
+            options: convert.lint_options,
+        }));
+    }
+    if subcommand == "fmt" {
+        if convert.output.is_some() || convert.watch {
+            return Err(String::from("fmt rewrites its files in place and doesn't watch them"));
+        }
+        if convert.inputs.len() > 1 && convert.inputs.iter().any(|input| input == "-") {
+            return Err(String::from("fmt formats standard input on its own, not along with files"));
+        }
+        return Ok(Command::Fmt(Fmt {
+            inputs: convert.inputs,
+            check: convert.check,
+            options: FormatOptions { width: convert.width.unwrap_or(DEFAULT_WIDTH) },
+        }));
+    }
+    if convert.format != Format::Html {
+        return Err(format!("{} only writes html", subcommand));
+    }
+    let [source] = &convert.inputs[..] else {
+        return Err(format!("{} expects one source directory", subcommand));
+    };
+    let options = SiteOptions { tokenizer: convert.tokenizer_options, parser: convert.parser_options };
+
+    if subcommand == "serve" {
+        if convert.output.is_some() || convert.watch {
+            return Err(String::from("serve writes no files and always watches its directory"));
+        }
+        return Ok(Command::Serve(Serve {
+            root: PathBuf::from(source),
+            port: convert.port.unwrap_or(8000),
+            template: convert.template,
+            options,
+        }));
+    }
+    Ok(Command::Build(Build {
+        source: PathBuf::from(source),
+        output: PathBuf::from(convert.output.as_deref().unwrap_or("_site")),
+        template: convert.template,
+        watch: convert.watch,
+        options,
+    }))
+}
+
+fn run_convert(convert: &Convert, parser_options: &ParserOptions)-> Result<ExitCode, String> {
+    let (input, sources) = read_inputs(&convert.inputs)?;
+    let document = parse_document_with_options(&input, &convert.tokenizer_options);
+
+    let mut has_errors = false;
+    for diagnostic in &document.diagnostics {
+        has_errors |= diagnostic.severity == Severity::Error;
+        match diagnostic.line {
+            Some(line) => {
+                let source = sources.iter().rev().find(|source| source.first_line <= line).unwrap_or(&sources[0]);
+                eprintln!("{}:{}: {}: {}", source.name, line - source.first_line + 1, diagnostic.severity, diagnostic.message);
+            }
+            None => eprintln!("{}: {}: {}", sources[0].name, diagnostic.severity, diagnostic.message),
+        }
+    }
+
+    let output = match (convert.format, convert.standalone) {
+        (Format::Tokens, _) => format!("{:#?}\n", document.tokens),
+        (Format::Html, true) => page_parser(&document, parser_options),
+        (Format::Html, false) => parser_with_options(&document.tokens, parser_options),
+        (Format::Text, _) => text_parser(&document.tokens, &TextOptions { width: convert.width.unwrap_or(0) }),
+        (Format::Json, _) => unreachable!("json is only taken by lint"),
+    };
+    match &convert.output {
+        Some(path) => fs::write(path, output).map_err(|error| format!("{}: {}", path, error))?,
+        None => io::stdout().write_all(output.as_bytes()).map_err(|error| error.to_string())?,
+    }
+
+    match has_errors {
+        true => Ok(ExitCode::from(1)),
+        false => Ok(ExitCode::SUCCESS),
+    }
+}
+
+/// Converts the inputs again on every change to them or to the template, until interrupted
+fn watch_convert(convert: &Convert)-> Result<ExitCode, String> {
+    loop {
+        let parser_options = with_template(&convert.parser_options, convert.template.as_deref());
+        let mut watched: Vec<PathBuf> = convert.inputs.iter().chain(&convert.template).map(PathBuf::from).collect();
+        if let Ok(parser_options) = &parser_options {
+            watched.extend(template_files(parser_options));
+        }
+        let mut watcher = Watcher::new(watched, &[]);
+
+        if let Err(message) = parser_options.and_then(|parser_options| run_convert(convert, &parser_options)) {
+            eprintln!("markdownit: {}", message);
+        }
+        report_changes(&watcher.wait());
+    }
+}
+
+fn run_build(build: &Build)-> Result<ExitCode, String> {
+    if !build.source.is_dir() {
+        return Err(format!("{}: not a directory", build.source.display()));
+    }
+    let report = build_site(&build.source, &build.output, &site_options(&build.options, build.template.as_deref())?)
+        .map_err(|error| format!("{}: {}", build.output.display(), error))?;
+
+    match report_build(build, "built", &report) {
+        true => Ok(ExitCode::from(1)),
+        false => Ok(ExitCode::SUCCESS),
+    }
+}
+
+/// Builds the site then rebuilds the pages affected by each change, or the whole site when the
+/// template changes, until interrupted
+fn watch_build(build: &Build)-> Result<ExitCode, String> {
+    if !build.source.is_dir() {
+        return Err(format!("{}: not a directory", build.source.display()));
+    }
+    let mut site = Site::new(&build.source, &build.output, site_options(&build.options, build.template.as_deref())?);
+    let report = site.build().map_err(|error| format!("{}: {}", build.output.display(), error))?;
+    report_build(build, "built", &report);
+
+    let site_watcher = |site: &Site| {
+        let mut watched = vec![build.source.clone()];
+        watched.extend(template_files(&site.options.parser));
+        Watcher::new(watched, std::slice::from_ref(&build.output))
+    };
+    let mut watcher = site_watcher(&site);
+    loop {
+        let changed = watcher.wait();
+        report_changes(&changed);
+
+        let template_changed = changed.iter().any(|path| template_files(&site.options.parser).contains(path));
+        let report = match template_changed {
+            true => site_options(&build.options, build.template.as_deref()).and_then(|options| {
+                site.options = options;
+                watcher = site_watcher(&site);
+                site.build().map_err(|error| format!("{}: {}", build.output.display(), error))
+            }),
+            false => {
+                let changed: Vec<PathBuf> = changed.iter()
+                    .filter_map(|path| path.strip_prefix(&build.source).ok())
+                    .map(Path::to_path_buf)
+                    .collect();
+                site.rebuild(&changed).map_err(|error| format!("{}: {}", build.output.display(), error))
+            }
+        };
+        match report {
+            Ok(report) => {
+                report_build(build, "rebuilt", &report);
+            }
+            Err(message) => eprintln!("markdownit: {}", message),
+        }
+    }
+}
+
+/// Serves the directory on localhost until interrupted
+fn run_serve(serve: &Serve)-> Result<ExitCode, String> {
+    if !serve.root.is_dir() {
+        return Err(format!("{}: not a directory", serve.root.display()));
+    }
+    let options = site_options(&serve.options, serve.template.as_deref())?;
+    let listener = TcpListener::bind(("127.0.0.1", serve.port)).map_err(|error| format!("127.0.0.1:{}: {}", serve.port, error))?;
+    let address = listener.local_addr().map_err(|error| error.to_string())?;
+
+    eprintln!("markdownit: serving {} at http://{}/", serve.root.display(), address);
+    markdownit::serve::serve(listener, &serve.root, options).map_err(|error| error.to_string())?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Formats the files in place, or standard input to standard output
+///
+/// Files that can't be formatted are reported and skipped, returning status 2 once the others are done
+fn run_fmt(fmt: &Fmt)-> Result<ExitCode, String> {
+    let mut unformatted = false;
+    let mut failed = false;
+    if fmt.inputs.is_empty() || fmt.inputs[0] == "-" {
+        let (input, _) = read_inputs(&[])?;
+        let output = format_document(&input, &fmt.options).map_err(|error| format!("<stdin>: {}", error))?;
+        match fmt.check {
+            true => unformatted = output != input,
+            false => io::stdout().write_all(output.as_bytes()).map_err(|error| error.to_string())?,
+        }
+    }
+
+    for path in fmt.inputs.iter().filter(|input| *input != "-") {
+        let input = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+        let output = match format_document(&input, &fmt.options) {
+            Ok(output) if output == input => continue,
+            Ok(output) => output,
+            Err(error) => {
+                eprintln!("markdownit: {}: {}", path, error);
+                failed = true;
+                continue;
+            }
+        };
+        match fmt.check {
+            true => {
+                println!("{}", path);
+                unformatted = true;
+            }
+            false => fs::write(path, output).map_err(|error| format!("{}: {}", path, error))?,
+        }
+    }
+
+    match (failed, unformatted) {
+        (true, _) => Ok(ExitCode::from(2)),
+        (false, true) => Ok(ExitCode::from(1)),
+        (false, false) => Ok(ExitCode::SUCCESS),
+    }
+}
+
+/// Prints the findings of every rule on each file, returning status 1 when one is an error
+fn run_lint(lint_command: &Lint)-> Result<ExitCode, String> {
+    let stdin = [String::from("-")];
+    let inputs = match lint_command.inputs.is_empty() {
+        true => &stdin[..],
+        false => &lint_command.inputs[..],
+    };
+
+    let mut has_errors = false;
+    let mut found = vec![];
+    for name in inputs {
+        let (input, sources) = read_inputs(std::slice::from_ref(name))?;
+        for finding in lint(&input, &lint_command.options) {
+            has_errors |= finding.diagnostic.severity == Severity::Error;
+            let line = finding.diagnostic.line.unwrap_or(1);
+            match lint_command.json {
+                true => found.push(Json::object([
+                    ("file", Json::from(sources[0].name.as_str())),
+                    ("line", Json::from(line)),
+                    ("rule", Json::from(finding.rule)),
+                    ("severity", Json::from(finding.diagnostic.severity.to_string())),
+                    ("message", Json::from(finding.diagnostic.message.as_str())),
+                ])),
+                false => println!("{}:{}: {}: {} [{}]", sources[0].name, line, finding.diagnostic.severity, finding.diagnostic.message, finding.rule),
+            }
+        }
+    }
+    if lint_command.json {
+        println!("{}", Json::Array(found));
+    }
+
+    match has_errors {
+        true => Ok(ExitCode::from(1)),
+        false => Ok(ExitCode::SUCCESS),
+    }
+}
+
+/// Prints the diagnostics of the built pages and a summary, returning whether there were errors
+fn report_build(build: &Build, verb: &str, report: &SiteReport)-> bool {
+    let mut has_errors = false;
+    for (path, diagnostic) in &report.diagnostics {
+        has_errors |= diagnostic.severity == Severity::Error;
+        let path = build.source.join(path);
+        match diagnostic.line {
+            Some(line) => eprintln!("{}:{}: {}: {}", path.display(), line, diagnostic.severity, diagnostic.message),
+            None => eprintln!("{}: {}: {}", path.display(), diagnostic.severity, diagnostic.message),
+        }
+    }
+
+    let mut summary = format!(
+        "{} {} pages, {} indexes and {} assets into {}",
+        verb, report.pages.len(), report.indexes.len(), report.assets.len(), build.output.display()
+    );
+    if !report.removed.is_empty() {
+        summary.push_str(&format!(", removed {}", report.removed.len()));
+    }
+    eprintln!("markdownit: {}", summary);
+    has_errors
+}
+
+fn report_changes(changed: &[PathBuf]) {
+    let names: Vec<String> = changed.iter().map(|path| path.display().to_string()).collect();
+    eprintln!("markdownit: {} changed", names.join(", "));
+}
+
+fn site_options(options: &SiteOptions, template: Option<&str>)-> Result<SiteOptions, String> {
+    Ok(SiteOptions {
+        tokenizer: options.tokenizer.clone(),
+        parser: with_template(&options.parser, template)?,
+    })
+}
+
+/// Files of the loaded template, watched for changes
+fn template_files(options: &ParserOptions)-> Vec<PathBuf> {
+    options.template.as_ref().map(|template| template.files().to_vec()).unwrap_or_default()
+}
+
+/// The options with the layout at ```template``` loaded, if any
+fn with_template(options: &ParserOptions, template: Option<&str>)-> Result<ParserOptions, String> {
+    let mut options = options.clone();
+    if let Some(path) = template {
+        options.template = Some(Template::load(Path::new(path)).map_err(|error| error.to_string())?);
+    }
+    Ok(options)
+}
+
+/// Reads the inputs into one document, separating files by an empty line
+fn read_inputs(inputs: &[String])-> Result<(String, Vec<Source>), String> {
+    let stdin = [String::from("-")];
+    let inputs = match inputs.is_empty() {
+        true => &stdin[..],
+        false => inputs,
+    };
+
+    let mut document = String::new();
+    let mut sources = vec![];
+    for name in inputs {
+        let content = match name.as_str() {
+            "-" => {
+                let mut content = String::new();
+                io::stdin().read_to_string(&mut content).map_err(|error| format!("<stdin>: {}", error))?;
+                content
+            }
+            path => fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?,
+        };
+
+        if !document.is_empty() {
+            if !document.ends_with('\n') {
+                document.push('\n');
+            }
+            document.push('\n');
+        }
+        sources.push(Source {
+            name: if name == "-" { String::from("<stdin>") } else { name.to_owned() },
+            first_line: document.lines().count() + 1,
+        });
+        document.push_str(&content);
+    }
+
+    Ok((document, sources))
 }
@@ -0,0 +1,305 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::escape_html;
+use crate::site::{directory_index, is_markdown, rewrite_links, SiteOptions};
+use crate::template::Template;
+use crate::watch::Watcher;
+use crate::{page_parser, parse_document_with_options};
+
+/// Path of the server-sent events stream a served page listens to for reloads
+pub const EVENTS_PATH: &str = "/__markdownit/events";
+
+/// Interval of the comments keeping an idle events stream open
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Longest request head read, the request line and headers
+const MAX_REQUEST_HEAD: u64 = 16 * 1024;
+
+/// Appended to every rendered page, reloading it on the ```reload``` events of ```EVENTS_PATH```
+const RELOAD_SCRIPT: &str = "<script>new EventSource(\"/__markdownit/events\").addEventListener(\"reload\", () => location.reload());</script>\n";
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    /// Extra header lines, each ending with ```\r\n```
+    headers: String,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn new(status: &'static str, content_type: &'static str, body: Vec<u8>)-> Response {
+        Response { status, content_type, headers: String::new(), body }
+    }
+
+    fn error(status: &'static str, message: &str)-> Response {
+        Response::new(status, "text/html; charset=utf-8", error_page(status, message).into_bytes())
+    }
+}
+
+fn error_page(status: &str, message: &str)-> String {
+    format!("<!DOCTYPE html>\n<html>\n<head>\n<title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>\n<p>{1}</p>\n</body>\n</html>\n", status, escape_html(message))
+}
+
+/// A live preview of a directory of documents, rendering them as they are requested
+struct Server {
+    root: PathBuf,
+    options: Mutex<SiteOptions>,
+    /// Why the template could not be read again after a change, shown instead of the pages
+    template_error: Mutex<Option<String>>,
+    /// One sender per open events stream
+    clients: Mutex<Vec<Sender<String>>>,
+}
+
+/// Serves the documents under ```root``` over http on ```listener```, never returning unless accepting
+/// a connection fails
+///
+/// Markdown files are rendered into pages on each request, as ```guide.md``` or ```guide.html```, and
+/// directories without an ```index.md``` get a generated index. Other files are served as they are.
+/// The pages reload themselves whenever a file under ```root``` or the template changes
+pub fn serve(listener: TcpListener, root: &Path, options: SiteOptions)-> io::Result<()> {
+    let server = Arc::new(Server {
+        root: root.to_path_buf(),
+        options: Mutex::new(options),
+        template_error: Mutex::new(None),
+        clients: Mutex::new(vec![]),
+    });
+
+    let watching = Arc::clone(&server);
+    thread::spawn(move || watching.watch());
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let server = Arc::clone(&server);
+        thread::spawn(move || {
+            // A client closing the connection early is no concern of the server
+            let _ = server.handle(stream);
+        });
+    }
+    Ok(())
+}
+
+impl Server {
+    fn template_files(&self)-> Vec<PathBuf> {
+        let options = self.options.lock().unwrap();
+        options.parser.template.as_ref().map(|template| template.files().to_vec()).unwrap_or_default()
+    }
+
+    /// Reloads the template on its changes and sends every change to the open events streams
+    fn watch(&self) {
+        let watched = |files: &[PathBuf]| {
+            let mut roots = vec![self.root.clone()];
+            roots.extend_from_slice(files);
+            Watcher::new(roots, &[])
+        };
+        let mut template_files = self.template_files();
+        let mut watcher = watched(&template_files);
+
+        loop {
+            let changed = watcher.wait();
+            if let Some(layout) = template_files.first().filter(|_| changed.iter().any(|path| template_files.contains(path))) {
+                let mut template_error = self.template_error.lock().unwrap();
+                match Template::load(layout) {
+                    Ok(template) => {
+                        self.options.lock().unwrap().parser.template = Some(template);
+                        *template_error = None;
+                    }
+                    Err(error) => *template_error = Some(error.to_string()),
+                }
+                drop(template_error);
+
+                template_files = self.template_files();
+                watcher = watched(&template_files);
+            }
+
+            let names: Vec<String> = changed.iter().map(|path| path.display().to_string()).collect();
+            let message = names.join(" ");
+            self.clients.lock().unwrap().retain(|client| client.send(message.clone()).is_ok());
+        }
+    }
+
+    fn handle(&self, mut stream: TcpStream)-> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?.take(MAX_REQUEST_HEAD));
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+                break;
+            }
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let (method, target) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+        let path = target.split(['?', '#']).next().unwrap_or_default();
+
+        let response = match method {
+            "GET" if path == EVENTS_PATH => return self.events(stream),
+            "GET" | "HEAD" => self.respond(path),
+            _ => Response::error("405 Method Not Allowed", "Only GET and HEAD requests are served"),
+        };
+
+        let head = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n{}\r\n",
+            response.status, response.content_type, response.body.len(), response.headers
+        );
+        stream.write_all(head.as_bytes())?;
+        if method != "HEAD" {
+            stream.write_all(&response.body)?;
+        }
+        stream.flush()
+    }
+
+    /// Holds the connection open as a server-sent events stream, sending a ```reload``` event
+    /// with the changed paths on every change
+    fn events(&self, mut stream: TcpStream)-> io::Result<()> {
+        let (sender, receiver) = mpsc::channel();
+        self.clients.lock().unwrap().push(sender);
+
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n")?;
+        stream.flush()?;
+        loop {
+            match receiver.recv_timeout(KEEP_ALIVE) {
+                Ok(changed) => stream.write_all(format!("event: reload\ndata: {}\n\n", changed).as_bytes())?,
+                Err(RecvTimeoutError::Timeout) => stream.write_all(b": keep-alive\n\n")?,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+            stream.flush()?;
+        }
+    }
+
+    fn respond(&self, path: &str)-> Response {
+        let Some(relative) = request_path(path) else {
+            return Response::error("400 Bad Request", "The requested path is not valid");
+        };
+        let file = self.root.join(&relative);
+
+        if file.is_dir() {
+            if !path.ends_with('/') {
+                let mut response = Response::new("301 Moved Permanently", "text/plain; charset=utf-8", vec![]);
+                response.headers = format!("Location: {}/\r\n", path);
+                return response;
+            }
+            return self.directory(&relative);
+        }
+        if file.is_file() {
+            return match is_markdown(&file) {
+                true => self.page(&file),
+                false => match fs::read(&file) {
+                    Ok(body) => Response::new("200 OK", content_type(&file), body),
+                    Err(error) => Response::error("500 Internal Server Error", &error.to_string()),
+                },
+            };
+        }
+
+        // ```guide.html``` is the page of ```guide.md```, as links are rewritten
+        if file.extension().is_some_and(|extension| extension == "html") {
+            for extension in ["md", "mdx"] {
+                if file.with_extension(extension).is_file() {
+                    return self.page(&file.with_extension(extension));
+                }
+            }
+            if file.file_name().is_some_and(|name| name == "index.html") && file.parent().is_some_and(Path::is_dir) {
+                return self.directory(relative.parent().unwrap_or(Path::new("")));
+            }
+        }
+        Response::error("404 Not Found", &format!("Nothing found at {}", path))
+    }
+
+    fn directory(&self, relative: &Path)-> Response {
+        for index in ["index.md", "index.mdx"] {
+            if self.root.join(relative).join(index).is_file() {
+                return self.page(&self.root.join(relative).join(index));
+            }
+        }
+
+        let options = self.options.lock().unwrap().clone();
+        match directory_index(&self.root, relative, None, &options) {
+            Ok(page) => self.html(page),
+            Err(error) => Response::error("500 Internal Server Error", &error.to_string()),
+        }
+    }
+
+    fn page(&self, file: &Path)-> Response {
+        if let Some(error) = self.template_error.lock().unwrap().as_ref() {
+            // With the reload script, the page comes back once the template is fixed
+            let mut response = self.html(error_page("500 Internal Server Error", error));
+            response.status = "500 Internal Server Error";
+            return response;
+        }
+        let input = match fs::read_to_string(file) {
+            Ok(input) => input,
+            Err(error) => return Response::error("500 Internal Server Error", &error.to_string()),
+        };
+
+        let options = self.options.lock().unwrap().clone();
+        let mut document = parse_document_with_options(&input, &options.tokenizer);
+        rewrite_links(&mut document.tokens);
+        self.html(page_parser(&document, &options.parser))
+    }
+
+    /// A page response, with the reload script added to its body
+    fn html(&self, mut page: String)-> Response {
+        match page.rfind("</body>") {
+            Some(end) => page.insert_str(end, RELOAD_SCRIPT),
+            None => page.push_str(RELOAD_SCRIPT),
+        }
+        Response::new("200 OK", "text/html; charset=utf-8", page.into_bytes())
+    }
+}
+
+/// The percent decoded path of a request relative to the served directory, or ```None``` when it
+/// leaves the directory or points at a hidden file
+fn request_path(path: &str)-> Option<PathBuf> {
+    let decoded = percent_decode(path)?;
+    let mut relative = PathBuf::new();
+    for segment in decoded.split('/').filter(|segment| !segment.is_empty()) {
+        if segment.starts_with('.') || segment.contains('\\') {
+            return None;
+        }
+        relative.push(segment);
+    }
+    Some(relative)
+}
+
+fn percent_decode(text: &str)-> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.bytes();
+    while let Some(byte) = chars.next() {
+        match byte {
+            b'%' => {
+                let hex = [chars.next()?, chars.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn content_type(path: &Path)-> &'static str {
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_ascii_lowercase();
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "pdf" => "application/pdf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
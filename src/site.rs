@@ -0,0 +1,533 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::diagnostic::Diagnostic;
+use crate::html::scan_html_tag;
+use crate::block_token::{BulletType, ListMeta, ListType};
+use crate::front_matter::Metadata;
+use crate::inline_token::{InlineToken, LinkTarget, LinkToken};
+use crate::slug::Slugger;
+use crate::{page_parser, parse_document_with_options, Document, ParserOptions, SecHeading, SecList, SecondaryToken, TokenizerOptions};
+
+#[derive(Debug, Clone, Default)]
+pub struct SiteOptions {
+    pub tokenizer: TokenizerOptions,
+    pub parser: ParserOptions,
+}
+
+#[derive(Debug, Clone, Default)]
+/// What a build wrote, every path relative to the output directory unless stated otherwise
+pub struct SiteReport {
+    pub pages: Vec<PathBuf>,
+    /// Index pages generated for directories without an ```index.md```
+    pub indexes: Vec<PathBuf>,
+    pub assets: Vec<PathBuf>,
+    /// Pages and assets deleted as their source is gone
+    pub removed: Vec<PathBuf>,
+    /// Diagnostics of each page, along with its source path relative to the source directory
+    pub diagnostics: Vec<(PathBuf, Diagnostic)>,
+}
+
+/// A converted page, listed in the index of its directory
+struct PageEntry {
+    file_name: String,
+    title: String,
+}
+
+#[derive(Debug, Clone)]
+/// A static site built from a directory of documents, keeping track of the links between pages
+/// so that a change to one document only rebuilds the pages affected by it
+pub struct Site {
+    source: PathBuf,
+    output: PathBuf,
+    pub options: SiteOptions,
+    /// Canonical ```output``` path, skipped when it is inside ```source```
+    skip: Option<PathBuf>,
+    /// The markdown files each page links to, all relative to ```source```
+    links: BTreeMap<PathBuf, BTreeSet<PathBuf>>,
+}
+
+/// Converts every ```.md```/```.mdx``` file under ```source``` into a standalone html page under ```output```,
+/// mirroring the directory tree
+///
+/// Relative links to markdown files are pointed at the generated ```.html``` pages, other files are copied
+/// as assets and each directory without an ```index.md``` gets a generated ```index.html``` listing its pages
+/// and subdirectories. Hidden files and directories are skipped, as is ```output``` when inside ```source```
+pub fn build_site(source: &Path, output: &Path, options: &SiteOptions)-> io::Result<SiteReport> {
+    Site::new(source, output, options.clone()).build()
+}
+
+impl Site {
+    pub fn new(source: &Path, output: &Path, options: SiteOptions)-> Site {
+        Site { source: source.to_path_buf(), output: output.to_path_buf(), options, skip: None, links: BTreeMap::new() }
+    }
+
+    /// Converts the whole source directory, like ```build_site```
+    pub fn build(&mut self)-> io::Result<SiteReport> {
+        let mut report = SiteReport::default();
+        fs::create_dir_all(&self.output)?;
+        self.skip = self.output.canonicalize().ok();
+        self.links.clear();
+        self.build_directory(Path::new(""), &mut report)?;
+        Ok(report)
+    }
+
+    /// Brings the output up to date with the given added, modified or removed files, relative to
+    /// the source directory
+    ///
+    /// Changed documents are rebuilt along with the pages linking to them, assets are copied or deleted
+    /// and the generated indexes of the directories holding a change are regenerated
+    pub fn rebuild(&mut self, changed: &[PathBuf])-> io::Result<SiteReport> {
+        let mut report = SiteReport::default();
+        let mut pages = BTreeSet::new();
+        let mut directories = BTreeSet::new();
+
+        for path in changed {
+            if is_hidden(path) {
+                continue;
+            }
+            directories.extend(path.ancestors().skip(1).map(Path::to_path_buf));
+
+            if is_markdown(path) {
+                pages.insert(path.clone());
+                let linking = self.links.iter().filter(|(_, links)| links.contains(path));
+                pages.extend(linking.map(|(page, _)| page.clone()));
+            } else if self.source.join(path).is_file() {
+                fs::create_dir_all(self.output.join(path).parent().unwrap_or(&self.output))?;
+                fs::copy(self.source.join(path), self.output.join(path))?;
+                report.assets.push(path.clone());
+            } else if remove_output(&self.output.join(path))? {
+                report.removed.push(path.clone());
+            }
+        }
+
+        for page in pages {
+            if self.source.join(&page).is_file() {
+                self.build_page(&page, &mut report)?;
+                continue;
+            }
+            self.links.remove(&page);
+            let page_path = page.with_extension("html");
+            if remove_output(&self.output.join(&page_path))? {
+                report.removed.push(page_path);
+            }
+        }
+
+        for directory in directories.iter().rev() {
+            if !self.source.join(directory).is_dir() {
+                remove_output(&self.output.join(directory))?;
+                continue;
+            }
+            if !self.has_index(directory)? {
+                self.write_index(directory, &mut report)?;
+            }
+        }
+        Ok(report)
+    }
+
+    fn entries(&self, relative: &Path)-> io::Result<Vec<(String, PathBuf)>> {
+        source_entries(&self.source.join(relative), self.skip.as_deref())
+    }
+
+    fn build_directory(&mut self, relative: &Path, report: &mut SiteReport)-> io::Result<()> {
+        fs::create_dir_all(self.output.join(relative))?;
+
+        let mut pages = vec![];
+        let mut subdirectories = vec![];
+        let mut has_index = false;
+        for (file_name, path) in self.entries(relative)? {
+            let path_relative = relative.join(&file_name);
+
+            if path.is_dir() {
+                self.build_directory(&path_relative, report)?;
+                subdirectories.push(file_name);
+            } else if is_markdown(&path) {
+                let page = self.build_page(&path_relative, report)?;
+                has_index |= page.file_name == "index.html";
+                pages.push(page);
+            } else {
+                fs::copy(&path, self.output.join(&path_relative))?;
+                report.assets.push(path_relative);
+            }
+        }
+
+        if !has_index {
+            let index = relative.join("index.html");
+            fs::write(self.output.join(&index), index_page(relative, &pages, &subdirectories, &self.options))?;
+            report.indexes.push(index);
+        }
+        Ok(())
+    }
+
+    /// Converts the markdown file at ```relative``` under ```source``` into its page under ```output```
+    fn build_page(&mut self, relative: &Path, report: &mut SiteReport)-> io::Result<PageEntry> {
+        let input = fs::read_to_string(self.source.join(relative))?;
+        let mut document = parse_document_with_options(&input, &self.options.tokenizer);
+
+        let directory = relative.parent().unwrap_or(Path::new(""));
+        let links = rewrite_links(&mut document.tokens).iter()
+            .filter_map(|href| link_source(directory, href))
+            .collect();
+        self.links.insert(relative.to_path_buf(), links);
+
+        let page_path = relative.with_extension("html");
+        fs::create_dir_all(self.output.join(directory))?;
+        fs::write(self.output.join(&page_path), page_parser(&document, &self.options.parser))?;
+
+        let title = document.title().unwrap_or_else(|| path_stem(relative));
+        for diagnostic in document.diagnostics {
+            report.diagnostics.push((relative.to_path_buf(), diagnostic));
+        }
+        report.pages.push(page_path.clone());
+
+        Ok(PageEntry { file_name: path_name(&page_path), title })
+    }
+
+    /// Whether a source directory holds an ```index.md```, which takes the place of the generated index
+    fn has_index(&self, relative: &Path)-> io::Result<bool> {
+        Ok(self.entries(relative)?.iter().any(|(_, path)| is_markdown(path) && path_stem(path) == "index"))
+    }
+
+    /// Regenerates the index of a directory
+    fn write_index(&self, relative: &Path, report: &mut SiteReport)-> io::Result<()> {
+        let page = directory_index(&self.source, relative, self.skip.as_deref(), &self.options)?;
+        let index = relative.join("index.html");
+        fs::create_dir_all(self.output.join(relative))?;
+        fs::write(self.output.join(&index), page)?;
+        report.indexes.push(index);
+        Ok(())
+    }
+}
+
+/// Non hidden entries of a source directory in order, without the ```skip``` directory
+fn source_entries(directory: &Path, skip: Option<&Path>)-> io::Result<Vec<(String, PathBuf)>> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<_>>()?;
+    entries.sort();
+
+    Ok(entries.into_iter().filter_map(|path| {
+        let file_name = path.file_name()?.to_str()?.to_owned();
+        let skipped = path.is_dir() && skip.is_some() && path.canonicalize().ok().as_deref() == skip;
+        match file_name.starts_with('.') || skipped {
+            true => None,
+            false => Some((file_name, path)),
+        }
+    }).collect())
+}
+
+/// Renders the generated index page of the directory at ```relative``` under ```source```, reading the
+/// title of each of its pages
+pub(crate) fn directory_index(source: &Path, relative: &Path, skip: Option<&Path>, options: &SiteOptions)-> io::Result<String> {
+    let mut pages = vec![];
+    let mut subdirectories = vec![];
+    for (file_name, path) in source_entries(&source.join(relative), skip)? {
+        if path.is_dir() {
+            subdirectories.push(file_name);
+        } else if is_markdown(&path) {
+            let document = parse_document_with_options(&fs::read_to_string(&path)?, &options.tokenizer);
+            pages.push(PageEntry {
+                file_name: path_name(&path.with_extension("html")),
+                title: document.title().unwrap_or_else(|| path_stem(&path)),
+            });
+        }
+    }
+    Ok(index_page(relative, &pages, &subdirectories, options))
+}
+
+fn path_name(path: &Path)-> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+fn path_stem(path: &Path)-> String {
+    path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+fn is_hidden(path: &Path)-> bool {
+    path.components().any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+}
+
+/// Deletes an output file or directory, returning whether there was one
+fn remove_output(path: &Path)-> io::Result<bool> {
+    let removed = match path.is_dir() {
+        true => fs::remove_dir_all(path),
+        false => fs::remove_file(path),
+    };
+    match removed {
+        Ok(()) => Ok(true),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// The markdown file a link of a page in ```directory``` points to, relative to the source directory,
+/// or ```None``` when the link leaves it
+fn link_source(directory: &Path, href: &str)-> Option<PathBuf> {
+    let path = &href[..href.find(['#', '?']).unwrap_or(href.len())];
+    let mut source = PathBuf::new();
+    for component in directory.join(path).components() {
+        match component {
+            Component::Normal(name) => source.push(name),
+            Component::ParentDir => {
+                if !source.pop() {
+                    return None;
+                }
+            }
+            Component::CurDir => {}
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(source)
+}
+
+/// Builds the listing page of a directory
+///
+/// The page is made of tokens rather than parsed from generated markdown, so titles and file
+/// names holding ```*```, ```<``` or ```==``` are shown as they are instead of read as formatting
+fn index_page(relative: &Path, pages: &[PageEntry], subdirectories: &[String], options: &SiteOptions)-> String {
+    let name = match relative.as_os_str().is_empty() {
+        true => String::from("Index"),
+        false => format!("Index of {}", relative.to_string_lossy().replace('\\', "/")),
+    };
+
+    let link = |text: String, url: String| (ListMeta::None, vec![InlineToken::Link(LinkToken { text, target: LinkTarget::Url(url) })]);
+    let mut items: Vec<_> = subdirectories.iter()
+        .map(|directory| link(format!("{}/", directory), format!("{}/index.html", directory)))
+        .collect();
+    items.extend(pages.iter().map(|page| link(page.title.clone(), page.file_name.clone())));
+
+    let mut tokens = vec![SecondaryToken::Heading(SecHeading {
+        level: 1,
+        id: Slugger::new().slug(&name),
+        tokens: vec![InlineToken::Text(name)],
+        line: 0,
+    })];
+    if !items.is_empty() {
+        tokens.push(SecondaryToken::List(SecList { r#type: ListType::Bullet(BulletType::Default), items, nests: vec![] }));
+    }
+
+    let document = Document { metadata: Metadata::default(), tokens, diagnostics: vec![] };
+    page_parser(&document, &options.parser)
+}
+
+/// Whether the file is a markdownit document by its ```.md``` or ```.mdx``` extension
+pub fn is_markdown(path: &Path)-> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("md") || extension.eq_ignore_ascii_case("mdx"))
+}
+
+/// Points a relative link to a markdown file at its generated page, ```guide.md#setup``` becoming
+/// ```guide.html#setup```
+///
+/// Returns ```None``` for absolute urls, fragments and links to anything but markdown files
+pub fn rewrite_link(href: &str)-> Option<String> {
+    if href.starts_with('/') || href.starts_with('#') || href.contains(':') {
+        return None;
+    }
+    let path_end = href.find(['#', '?']).unwrap_or(href.len());
+    let (path, rest) = href.split_at(path_end);
+    if !is_markdown(Path::new(path)) {
+        return None;
+    }
+
+    let stem = &path[..path.rfind('.')?];
+    Some(format!("{}.html{}", stem, rest))
+}
+
+/// Rewrites the markdown file links of the document, in links and in the ```href``` of raw html anchors
+///
+/// Returns the rewritten links as they were written in the document
+pub fn rewrite_links(tokens: &mut [SecondaryToken])-> Vec<String> {
+    let mut rewritten = vec![];
+    for token in tokens.iter_mut() {
+        if let SecondaryToken::Html(raw) = token {
+            *raw = rewrite_html_links(raw, &mut rewritten);
+            continue;
+        }
+        for group in token.inline_tokens_mut() {
+            for inline_token in group.iter_mut() {
+                match inline_token {
+                    InlineToken::Link(link) => {
+                        if let LinkTarget::Url(url) = &mut link.target {
+                            if let Some(href) = rewrite_link(url) {
+                                rewritten.push(std::mem::replace(url, href));
+                            }
+                        }
+                    }
+                    InlineToken::Html(tag) => tag.raw = rewrite_html_links(&tag.raw, &mut rewritten),
+                    _ => {}
+                }
+            }
+        }
+    }
+    rewritten
+}
+
+/// Rewrites the ```href``` of every ```<a>``` tag in a run of raw html
+fn rewrite_html_links(raw: &str, rewritten_links: &mut Vec<String>)-> String {
+    let mut rewritten = String::with_capacity(raw.len());
+    let mut rest = raw;
+
+    while let Some(open) = rest.find('<') {
+        rewritten.push_str(&rest[..open]);
+        rest = &rest[open..];
+        let Some((tag, _)) = scan_html_tag(rest) else {
+            rewritten.push('<');
+            rest = &rest[1..];
+            continue;
+        };
+
+        let mut raw_tag = tag.raw.clone();
+        if tag.name == "a" && !tag.closing {
+            raw_tag = rewrite_href(&raw_tag, rewritten_links);
+        }
+        rewritten.push_str(&raw_tag);
+        rest = &rest[tag.raw.len()..];
+    }
+
+    rewritten.push_str(rest);
+    rewritten
+}
+
+fn rewrite_href(tag: &str, rewritten_links: &mut Vec<String>)-> String {
+    let lowercase = tag.to_ascii_lowercase();
+    let Some(attribute) = lowercase.find("href=") else { return tag.to_owned() };
+    let value_start = attribute + 5;
+
+    let (start, end) = match tag[value_start..].chars().next() {
+        Some(quote @ ('"' | '\'')) => match tag[value_start + 1..].find(quote) {
+            Some(length) => (value_start + 1, value_start + 1 + length),
+            None => return tag.to_owned(),
+        },
+        _ => (value_start, value_start + tag[value_start..].find(|c: char| c.is_whitespace() || c == '>').unwrap_or(tag.len() - value_start)),
+    };
+
+    match rewrite_link(&tag[start..end]) {
+        Some(href) => {
+            rewritten_links.push(tag[start..end].to_owned());
+            format!("{}{}{}", &tag[..start], href, &tag[end..])
+        }
+        None => tag.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh source directory holding the given files, along with the output directory next to it
+    fn directories(name: &str, files: &[(&str, &str)])-> (PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(format!("markdownit-site-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, content) in files {
+            let path = root.join("source").join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        (root.join("source"), root.join("output"))
+    }
+
+    fn paths(paths: &[&str])-> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn index_titles_are_not_read_as_markdown() {
+        let pages = [PageEntry { file_name: String::from("p.html"), title: String::from("*a* <b>c</b> ==d== [e](f)") }];
+        let html = index_page(Path::new("docs"), &pages, &[String::from("sub")], &SiteOptions::default());
+        assert!(html.contains("<a href=\"p.html\" class=\"link\">*a* &lt;b&gt;c&lt;/b&gt; ==d== [e](f)</a>"));
+        assert!(html.contains("<a href=\"sub/index.html\" class=\"link\">sub/</a>"));
+        assert!(html.contains("<h1 id=\"index-of-docs\">Index of docs</h1>"));
+    }
+
+    #[test]
+    fn links_to_documents_point_at_their_pages() {
+        assert_eq!(rewrite_link("guide.md#setup").as_deref(), Some("guide.html#setup"));
+        assert_eq!(rewrite_link("../notes/a.mdx?x=1").as_deref(), Some("../notes/a.html?x=1"));
+        assert_eq!(rewrite_link("https://example.com/a.md"), None);
+        assert_eq!(rewrite_link("image.png"), None);
+    }
+
+    #[test]
+    fn rebuilds_the_pages_linking_to_a_change() {
+        let (source, output) = directories("links", &[
+            ("guide.md", "# Guide\n\nSee [the notes](notes.md#setup).\n"),
+            ("notes.md", "# Notes\n"),
+            ("other.md", "# Other\n"),
+            ("docs/a.md", "Back to [the notes](../notes.md)\n"),
+        ]);
+        let mut site = Site::new(&source, &output, SiteOptions::default());
+        site.build().unwrap();
+
+        fs::write(source.join("notes.md"), "# Release notes\n").unwrap();
+        let report = site.rebuild(&paths(&["notes.md"])).unwrap();
+        assert_eq!(report.pages, paths(&["docs/a.html", "guide.html", "notes.html"]));
+        assert_eq!(report.indexes, paths(&["index.html"]));
+        assert!(fs::read_to_string(output.join("notes.html")).unwrap().contains("Release notes"));
+        assert!(fs::read_to_string(output.join("index.html")).unwrap().contains("Release notes"));
+
+        // A page that stopped linking is left alone by the next change
+        fs::write(source.join("guide.md"), "# Guide\n").unwrap();
+        site.rebuild(&paths(&["guide.md"])).unwrap();
+        let report = site.rebuild(&paths(&["notes.md"])).unwrap();
+        assert_eq!(report.pages, paths(&["docs/a.html", "notes.html"]));
+        let _ = fs::remove_dir_all(source.parent().unwrap());
+    }
+
+    #[test]
+    fn removes_the_outputs_of_deleted_sources() {
+        let (source, output) = directories("removed", &[
+            ("index.md", "# Home\n"),
+            ("page.md", "# Page\n"),
+            ("logo.png", "png"),
+            ("docs/a.md", "# A\n"),
+            ("docs/b.md", "# B\n"),
+            ("old/c.md", "# C\n"),
+        ]);
+        let mut site = Site::new(&source, &output, SiteOptions::default());
+        site.build().unwrap();
+        assert!(output.join("logo.png").is_file());
+
+        fs::remove_file(source.join("page.md")).unwrap();
+        fs::remove_file(source.join("logo.png")).unwrap();
+        fs::remove_file(source.join("docs/a.md")).unwrap();
+        fs::remove_dir_all(source.join("old")).unwrap();
+        fs::write(source.join("docs/new.png"), "png").unwrap();
+        let report = site.rebuild(&paths(&["docs/a.md", "docs/new.png", "logo.png", "old/c.md", "page.md"])).unwrap();
+
+        assert_eq!(report.removed, paths(&["logo.png", "docs/a.html", "old/c.html", "page.html"]));
+        assert_eq!(report.assets, paths(&["docs/new.png"]));
+        assert!(report.pages.is_empty());
+        // The root has its own index.md, only the generated index of docs is written again
+        assert_eq!(report.indexes, paths(&["docs/index.html"]));
+        assert!(!output.join("old").exists());
+        assert!(output.join("docs/new.png").is_file());
+        assert!(output.join("index.html").is_file());
+
+        let docs_index = fs::read_to_string(output.join("docs/index.html")).unwrap();
+        assert!(docs_index.contains("b.html") && !docs_index.contains("a.html"));
+        let _ = fs::remove_dir_all(source.parent().unwrap());
+    }
+
+    #[test]
+    fn regenerates_the_indexes_of_changed_directories() {
+        let (source, output) = directories("indexes", &[("docs/guides/a.md", "# A\n")]);
+        let mut site = Site::new(&source, &output, SiteOptions::default());
+        site.build().unwrap();
+
+        fs::write(source.join("docs/guides/b.md"), "# Beta guide\n").unwrap();
+        let report = site.rebuild(&paths(&["docs/guides/b.md"])).unwrap();
+        assert_eq!(report.pages, paths(&["docs/guides/b.html"]));
+        assert_eq!(report.indexes, paths(&["docs/guides/index.html", "docs/index.html", "index.html"]));
+        assert!(fs::read_to_string(output.join("docs/guides/index.html")).unwrap().contains("Beta guide"));
+
+        // Hidden files are neither built nor indexed
+        fs::write(source.join(".draft.md"), "# Draft\n").unwrap();
+        let report = site.rebuild(&paths(&[".draft.md"])).unwrap();
+        assert!(report.pages.is_empty() && report.indexes.is_empty());
+        let _ = fs::remove_dir_all(source.parent().unwrap());
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+/// Turns heading text into an anchor friendly id
+///
+/// Letters are lowercased (unicode aware), whitespace and hyphens collapse into a single
+/// ```-``` and any other punctuation is dropped. For example ```"Hello, Wörld!"``` becomes ```"hello-wörld"```
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    let mut pending_hyphen = false;
+
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() || c == '_' {
+            if pending_hyphen && !slug.is_empty() {
+                slug.push('-');
+            }
+            pending_hyphen = false;
+            slug.extend(c.to_lowercase());
+        } else if c.is_whitespace() || c == '-' {
+            pending_hyphen = true;
+        }
+    }
+
+    slug
+}
+
+/// Checks whether the given explicit id (as in ```## Title {#custom-id}```) is usable as an html id
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
+}
+
+#[derive(Debug, Clone, Default)]
+/// Hands out document unique ids for headings
+///
+/// Repeated slugs get a ```-1```, ```-2```, ... suffix in order of appearance
+pub struct Slugger {
+    seen: HashMap<String, usize>,
+}
+
+impl Slugger {
+    pub fn new() -> Self {
+        Slugger { seen: HashMap::new() }
+    }
+
+    /// Marks an explicit id as taken so generated slugs never collide with it
+    pub fn reserve(&mut self, id: &str) {
+        self.seen.entry(id.to_owned()).or_insert(0);
+    }
+
+    /// Returns a unique slug for the given heading text
+    pub fn slug(&mut self, text: &str) -> String {
+        let mut base = slugify(text);
+        if base.is_empty() {
+            base = String::from("section");
+        }
+        self.unique(base)
+    }
+
+    fn unique(&mut self, base: String) -> String {
+        let Some(count) = self.seen.get(&base).copied() else {
+            self.seen.insert(base.clone(), 0);
+            return base;
+        };
+
+        let mut suffix = count + 1;
+        let mut candidate = format!("{base}-{suffix}");
+        while self.seen.contains_key(&candidate) {
+            suffix += 1;
+            candidate = format!("{base}-{suffix}");
+        }
+        self.seen.insert(base, suffix);
+        self.seen.insert(candidate.clone(), 0);
+        candidate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugifies_heading_text() {
+        assert_eq!(slugify("Hello, Wörld!"), "hello-wörld");
+        assert_eq!(slugify("  a  --  b_c "), "a-b_c");
+        assert_eq!(slugify("?!"), "");
+    }
+
+    #[test]
+    fn validates_explicit_ids() {
+        assert!(is_valid_id("intro-2:a.b_c"));
+        assert!(!is_valid_id(""));
+        assert!(!is_valid_id("with space"));
+        assert!(!is_valid_id("a\"b"));
+    }
+
+    #[test]
+    fn repeated_slugs_get_suffixes() {
+        let mut slugger = Slugger::new();
+        slugger.reserve("intro-1");
+        assert_eq!(slugger.slug("Intro"), "intro");
+        assert_eq!(slugger.slug("Intro"), "intro-2");
+        assert_eq!(slugger.slug("Intro 2"), "intro-2-1");
+        assert_eq!(slugger.slug("!"), "section");
+        assert_eq!(slugger.slug(""), "section-1");
+    }
+}
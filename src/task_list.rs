@@ -0,0 +1,82 @@
+use crate::block_token::{block_tokenization, BlockToken, ItemValue, ListMeta, ListToken};
+use crate::front_matter::extract_front_matter;
+
+/// Returns ```source``` with the checkbox of a task list item toggled
+///
+/// Tasks are counted from 0 in document order, the numbering of the ```data-task-index``` attribute
+/// of the rendered items. A checked box is cleared and a box in any other state checked, changing only
+/// the character between its brackets. Returns ```None``` when the document has no such task
+pub fn toggle_task(source: &str, task_index: usize)-> Option<String> {
+    let body_start = match extract_front_matter(source) {
+        Some((_, body, _)) => source.len() - body.len(),
+        None => 0,
+    };
+    let body = &source[body_start..];
+    let input_lines: Vec<&str> = body.lines().collect();
+
+    let mut block_tokens = vec![];
+    block_tokenization(&mut block_tokens, &input_lines);
+    let mut tasks = vec![];
+    for token in &block_tokens {
+        if let BlockToken::List(list_token) = token {
+            task_items(list_token, &mut tasks);
+        }
+    }
+
+    let (line_number, meta) = tasks.get(task_index)?;
+    let line = input_lines[*line_number];
+    let line_start = body_start + body.split_inclusive('\n').take(*line_number).map(str::len).sum::<usize>();
+    let marker = line.len() - line.trim_start().len();
+    let state = line_start + marker + line[marker..].find('[')? + 1;
+
+    let mut toggled = String::with_capacity(source.len());
+    toggled.push_str(&source[..state]);
+    toggled.push(match meta {
+        ListMeta::Checked => ' ',
+        _ => 'x',
+    });
+    toggled.push_str(&source[state + 1..]);
+    Some(toggled)
+}
+
+/// Collects the line and state of every task item of the list, nested lists included, in document order
+fn task_items(list_token: &ListToken, tasks: &mut Vec<(usize, ListMeta)>) {
+    for item in &list_token.items {
+        match item.value {
+            ItemValue::Nesting(index) => task_items(&list_token.nests[index], tasks),
+            ItemValue::Value(_) => {
+                if item.if_meta != ListMeta::None {
+                    tasks.push((item.line, item.if_meta.clone()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toggles_tasks_in_document_order() {
+        let source = "- [ ] a\n    - [x] b\n- c\n-[~] d\n";
+        assert_eq!(toggle_task(source, 0).as_deref(), Some("- [x] a\n    - [x] b\n- c\n-[~] d\n"));
+        assert_eq!(toggle_task(source, 1).as_deref(), Some("- [ ] a\n    - [ ] b\n- c\n-[~] d\n"));
+        assert_eq!(toggle_task(source, 2).as_deref(), Some("- [ ] a\n    - [x] b\n- c\n-[x] d\n"));
+        assert_eq!(toggle_task(source, 3), None);
+    }
+
+    #[test]
+    fn counts_lines_after_the_front_matter() {
+        let source = "---\ntitle: [ ]\n---\r\ntext\r\n- [ ] task\r\n";
+        assert_eq!(toggle_task(source, 0).as_deref(), Some("---\ntitle: [ ]\n---\r\ntext\r\n- [x] task\r\n"));
+    }
+
+    #[test]
+    fn indexes_match_the_rendered_items() {
+        let source = "- [ ] a\n\ntext\n\n1. one\n\n- [!] b\n";
+        let html = crate::parser(&crate::tokenizer(source));
+        assert!(html.contains("<li class=\"important\" data-task-index=\"1\">"), "{html}");
+        assert_eq!(toggle_task(source, 1).as_deref(), Some("- [ ] a\n\ntext\n\n1. one\n\n- [x] b\n"));
+    }
+}
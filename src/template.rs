@@ -0,0 +1,242 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::toc::{table_of_contents, toc_parser, TocRange};
+use crate::{escape_html, parser_with_options, Document, ParserOptions, DEFAULT_STYLESHEET};
+
+/// Layout used by ```page_parser``` when no template is set, a plain page with the default stylesheet
+pub const DEFAULT_LAYOUT: &str = include_str!("layout.html");
+
+/// Deepest chain of partials including one another
+const MAX_PARTIAL_DEPTH: usize = 16;
+
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    /// A ```{{name}}``` placeholder, ```line``` when it stands alone on its line so that the
+    /// line is dropped when the value is empty
+    Field { name: String, line: bool },
+}
+
+#[derive(Debug, Clone)]
+/// A page layout, html with ```{{name}}``` placeholders filled in from the document
+///
+/// - ```{{content}}``` the rendered document
+/// - ```{{title}}``` the front matter ```title``` or else the first heading
+/// - ```{{toc}}``` the table of contents of every heading, empty when there's none
+/// - ```{{stylesheet}}``` a ```<link>``` to ```ParserOptions::stylesheet``` or the embedded ```DEFAULT_STYLESHEET```
+/// - ```{{meta}}``` ```<meta>``` tags for the author, date, description and tags of the front matter
+/// - ```{{field}}``` any other name is the front matter field of that name, empty when missing
+///
+/// ```{{> name}}``` includes the partial template ```name``` in place, partials may include others
+pub struct Template {
+    parts: Vec<Part>,
+    /// The template file and its partials when read by ```load```
+    files: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum TemplateError {
+    Io(PathBuf, io::Error),
+    /// A ```{{``` with no ```}}``` on the given 1 based line of the template or partial
+    Unclosed { template: String, line: usize },
+    MissingPartial(String),
+    /// A partial including itself, directly or through other partials
+    RecursivePartial(String),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            TemplateError::Unclosed { template, line } => write!(f, "{}: line {}: unclosed {{{{ placeholder", template, line),
+            TemplateError::MissingPartial(name) => write!(f, "partial {:?} not found", name),
+            TemplateError::RecursivePartial(name) => write!(f, "partial {:?} includes itself", name),
+        }
+    }
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        default_layout().clone()
+    }
+}
+
+/// The ```DEFAULT_LAYOUT``` template, parsed on first use and shared by every page rendered after
+pub fn default_layout()-> &'static Template {
+    static LAYOUT: OnceLock<Template> = OnceLock::new();
+    LAYOUT.get_or_init(|| Template::new(DEFAULT_LAYOUT).expect("the default layout is a valid template"))
+}
+
+impl Template {
+    /// Parses a template with no partials, any ```{{> name}}``` is a ```MissingPartial``` error
+    pub fn new(source: &str)-> Result<Template, TemplateError> {
+        Template::parse(source, |_| None)
+    }
+
+    /// Parses a template, ```partial``` returning the source of the partial of the given name
+    pub fn parse(source: &str, mut partial: impl FnMut(&str) -> Option<String>)-> Result<Template, TemplateError> {
+        let mut parts = vec![];
+        let mut stack = vec![String::from("template")];
+        parse_parts(source, &mut parts, &mut partial, &mut stack)?;
+        Ok(Template { parts, files: vec![] })
+    }
+
+    /// Reads the template at ```path```, partials being the files of the same directory named
+    /// by ```{{> header.html}}``` like placeholders
+    pub fn load(path: &Path)-> Result<Template, TemplateError> {
+        let source = fs::read_to_string(path).map_err(|error| TemplateError::Io(path.to_path_buf(), error))?;
+        let directory = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let mut files = vec![path.to_path_buf()];
+        let mut template = Template::parse(&source, |name| {
+            files.push(directory.join(name));
+            fs::read_to_string(directory.join(name)).ok()
+        })?;
+
+        template.files = files;
+        Ok(template)
+    }
+
+    /// Files the template was read from by ```load```, the layout followed by its partials
+    pub fn files(&self)-> &[PathBuf] {
+        &self.files
+    }
+
+    /// Fills the placeholders in from the document, rendered with the given options
+    pub fn render(&self, document: &Document, options: &ParserOptions)-> String {
+        let mut page = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => page.push_str(text),
+                Part::Field { name, line } => {
+                    let value = field_value(name, document, options);
+                    let value = value.trim_end_matches('\n');
+                    if value.is_empty() && *line {
+                        continue;
+                    }
+                    page.push_str(value);
+                    if *line {
+                        page.push('\n');
+                    }
+                }
+            }
+        }
+        page
+    }
+}
+
+fn parse_parts(source: &str, parts: &mut Vec<Part>, partial: &mut impl FnMut(&str) -> Option<String>, stack: &mut Vec<String>)-> Result<(), TemplateError> {
+    let mut rest = source;
+    let mut line = 1;
+
+    while let Some(open) = rest.find("{{") {
+        let text = &rest[..open];
+        let Some(close) = rest[open + 2..].find("}}") else {
+            line += text.matches('\n').count();
+            return Err(TemplateError::Unclosed { template: stack[stack.len() - 1].clone(), line });
+        };
+        let placeholder = rest[open + 2..open + 2 + close].trim();
+        let after = &rest[open + 4 + close..];
+
+        // A placeholder alone on its line takes the line break with it
+        let before = &source[..source.len() - rest.len() + open];
+        let standalone = (before.is_empty() || before.ends_with('\n')) && after.starts_with('\n');
+
+        line += text.matches('\n').count();
+        if !text.is_empty() {
+            parts.push(Part::Text(text.to_owned()));
+        }
+        rest = after;
+
+        match placeholder.strip_prefix('>') {
+            Some(name) => {
+                let name = name.trim();
+                if stack.iter().any(|included| included == name) || stack.len() > MAX_PARTIAL_DEPTH {
+                    return Err(TemplateError::RecursivePartial(name.to_owned()));
+                }
+                let included = partial(name).ok_or_else(|| TemplateError::MissingPartial(name.to_owned()))?;
+
+                stack.push(name.to_owned());
+                parse_parts(&included, parts, partial, stack)?;
+                stack.pop();
+
+                if standalone && included.ends_with('\n') {
+                    rest = &rest[1..];
+                    line += 1;
+                }
+            }
+            None => {
+                parts.push(Part::Field { name: placeholder.to_owned(), line: standalone });
+                if standalone {
+                    rest = &rest[1..];
+                    line += 1;
+                }
+            }
+        }
+    }
+
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest.to_owned()));
+    }
+    Ok(())
+}
+
+fn field_value(name: &str, document: &Document, options: &ParserOptions)-> String {
+    match name {
+        "content" => parser_with_options(&document.tokens, options),
+        "title" => escape_html(&document.title().unwrap_or_default()),
+        "toc" => {
+            let entries = table_of_contents(&document.tokens, TocRange::default());
+            match entries.is_empty() {
+                true => String::new(),
+                false => toc_parser(&entries),
+            }
+        }
+        "stylesheet" => match &options.stylesheet {
+            Some(href) => format!("<link rel=\"stylesheet\" href=\"{}\">", escape_html(href)),
+            None => format!("<style>\n{}</style>", DEFAULT_STYLESHEET),
+        },
+        "meta" => meta_tags(document),
+        field => document.metadata.get(field).map(|value| escape_html(&value.to_text())).unwrap_or_default(),
+    }
+}
+
+fn meta_tags(document: &Document)-> String {
+    let mut tags = String::new();
+    for (key, name) in [("author", "author"), ("date", "date"), ("description", "description"), ("tags", "keywords")] {
+        if let Some(value) = document.metadata.get(key) {
+            tags.push_str(&format!("<meta name=\"{name}\" content=\"{}\">\n", escape_html(&value.to_text())));
+        }
+    }
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{page_parser, parse_document};
+
+    #[test]
+    fn default_layout_is_parsed_once() {
+        assert!(std::ptr::eq(default_layout(), default_layout()));
+        let page = page_parser(&parse_document("# Hello\n\ntext\n"), &ParserOptions::default());
+        assert!(page.contains("<title>Hello</title>"));
+        assert!(page.contains("<p>text</p>"));
+    }
+
+    #[test]
+    fn fields_and_partials() {
+        let template = Template::parse("<h1>{{title}}</h1>{{> footer}}", |name| (name == "footer").then(|| String::from("<footer>{{title}}</footer>"))).unwrap();
+        let page = template.render(&parse_document("# A & B\n"), &ParserOptions::default());
+        assert_eq!(page, "<h1>A &amp; B</h1><footer>A &amp; B</footer>");
+    }
+
+    #[test]
+    fn unclosed_fields_and_missing_partials_are_errors() {
+        assert!(Template::new("{{title").is_err());
+        assert!(Template::new("{{> nowhere}}").is_err());
+    }
+}
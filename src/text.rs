@@ -0,0 +1,483 @@
+use crate::block_token::{AlphaType, BulletType, CodeBlock, HrToken, ListMeta, ListType, RomanType};
+use crate::emoji::emoji_at;
+use crate::html::{is_block_tag, scan_html_tag};
+use crate::inline_token::{InlineToken, LinkTarget};
+use crate::toc::{table_of_contents, TocEntry};
+use crate::{SecList, SecondaryToken};
+
+#[derive(Debug, Clone, Default)]
+/// Rendering switches for ```text_parser```
+pub struct TextOptions {
+    /// Column paragraphs are wrapped at, ```0``` keeps them on the lines they are written on
+    pub width: usize,
+}
+
+/// Renders the tokens as plain text, for places that can't show html like search snippets,
+/// notifications or the text part of an email
+///
+/// Formatting is dropped, links read ```text (url)```, lists keep their markers and nesting,
+/// html tables are laid out in aligned columns and footnotes are listed at the end
+pub fn text_parser(tokens: &[SecondaryToken], options: &TextOptions)-> String {
+    let width = options.width;
+    let mut blocks: Vec<Vec<String>> = vec![];
+    let mut code = false;
+
+    for token in tokens {
+        let lines = match token {
+            SecondaryToken::Br | SecondaryToken::Form(_) | SecondaryToken::Footnote(_) => continue,
+            SecondaryToken::CodeBlock(position) => {
+                code = matches!(position, CodeBlock::Open);
+                continue;
+            },
+            // Code is kept on its lines, indented like an indented code block
+            SecondaryToken::Text(inline_tokens) if code => indented(&inline_text(inline_tokens, 0)),
+            SecondaryToken::Text(inline_tokens) | SecondaryToken::Table(inline_tokens) => wrap(&inline_text(inline_tokens, width), width, "", ""),
+            SecondaryToken::Heading(heading) => wrap(&inline_text(&heading.tokens, width), width, "", ""),
+            SecondaryToken::Caption(caption) => {
+                let label = format!("{} {}: ", caption.kind.label(), caption.number);
+                wrap(&format!("{}{}", label, inline_text(&caption.tokens, width)), width, "", "")
+            },
+            SecondaryToken::Blockquote(lines) => lines.iter()
+                .flat_map(|inline_tokens| wrap(&inline_text(inline_tokens, width), width, "> ", "> "))
+                .collect(),
+            SecondaryToken::Hr(hr_token) => match hr_token {
+                HrToken::Labeled(label) => vec![format!("--- {} ---", label)],
+                HrToken::Double => vec!["=".repeat(width.max(3))],
+                _ => vec![rule(width)],
+            },
+            SecondaryToken::Math(formula) => indented(formula),
+            SecondaryToken::Html(raw) => html_text(raw),
+            SecondaryToken::DefinitionList(items) => items.iter().flat_map(|item| {
+                let mut lines = wrap(&inline_text(&item.term, width), width, "", "");
+                for definition in &item.definitions {
+                    lines.extend(wrap(&inline_text(definition, width), width, "    ", "    "));
+                }
+                lines
+            }).collect(),
+            SecondaryToken::Toc(range) => {
+                let mut lines = vec![];
+                toc_lines(&table_of_contents(tokens, *range), 0, &mut lines);
+                lines
+            },
+            SecondaryToken::List(sec_list) => {
+                let mut lines = vec![];
+                list_lines(sec_list, 0, width, &mut lines);
+                lines
+            },
+        };
+        if !lines.is_empty() {
+            blocks.push(lines);
+        }
+    }
+
+    let mut footnotes: Vec<_> = tokens.iter().filter_map(|token| match token {
+        SecondaryToken::Footnote(footnote) => footnote.number.map(|number| (number, footnote)),
+        _ => None,
+    }).collect();
+    if !footnotes.is_empty() {
+        footnotes.sort_by_key(|(number, _)| *number);
+        blocks.push(vec![rule(width)]);
+        blocks.push(footnotes.iter().flat_map(|(number, footnote)| {
+            let marker = format!("[{}] ", number);
+            wrap(&inline_text(&footnote.tokens, width), width, &marker, &" ".repeat(marker.chars().count()))
+        }).collect());
+    }
+
+    let mut text = blocks.iter().map(|lines| lines.join("\n")).collect::<Vec<String>>().join("\n\n");
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    text
+}
+
+/// The readable text of the **Inline** tokens, line breaks inside the paragraph kept when
+/// ```width``` is ```0``` and turned into spaces for wrapping otherwise
+fn inline_text(tokens: &[InlineToken], width: usize)-> String {
+    let soft_break = match width {
+        0 => "\n",
+        _ => " ",
+    };
+    let mut text = String::new();
+    let mut index = 0;
+
+    while let Some(token) = tokens.get(index) {
+        match token {
+            InlineToken::Text(content) => text.push_str(&content.replace('\n', soft_break)),
+            InlineToken::Link(link) => {
+                let link_text = link.text.replace('\n', soft_break);
+                match &link.target {
+                    // Autolinks and mailto links already read as their address
+                    LinkTarget::Url(url) if *url != link.text && url.strip_prefix("mailto:") != Some(&link.text)
+                        && url.strip_prefix("http://") != Some(&link.text) => {
+                        text.push_str(&format!("{} ({})", link_text, url))
+                    },
+                    _ => text.push_str(&link_text),
+                }
+            },
+            InlineToken::FootnoteRef(footnote_ref) => match footnote_ref.number {
+                Some(number) => text.push_str(&format!("[{}]", number)),
+                None => text.push_str(&format!("[^{}]", footnote_ref.label)),
+            },
+            InlineToken::Math(math) => text.push_str(&math.formula),
+            InlineToken::HardBreak => text.push('\n'),
+            InlineToken::Html(tag) if tag.name == "br" => text.push('\n'),
+            InlineToken::Emoji(_) => match emoji_at(tokens, index) {
+                Some((glyph, after)) => {
+                    text.push_str(glyph);
+                    text.push_str(&after.replace('\n', soft_break));
+                    index += 3;
+                    continue;
+                },
+                None => text.push(':'),
+            },
+            _ => {}
+        }
+        index += 1;
+    }
+    text
+}
+
+/// Lays out the lines of ```text``` after ```first```, the following ones after ```rest```,
+/// wrapping the words at ```width``` unless it is ```0```
+fn wrap(text: &str, width: usize, first: &str, rest: &str)-> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+
+    for line in text.split('\n') {
+        let mut current = match lines.is_empty() {
+            true => first.to_owned(),
+            false => rest.to_owned(),
+        };
+        if width == 0 {
+            current.push_str(line.trim());
+            lines.push(current.trim_end().to_owned());
+            continue;
+        }
+
+        let mut length = current.chars().count();
+        let mut empty = true;
+        for word in line.split_whitespace() {
+            let word_length = word.chars().count();
+            if !empty && length + 1 + word_length > width {
+                lines.push(current);
+                current = rest.to_owned();
+                length = rest.chars().count();
+                empty = true;
+            }
+            if !empty {
+                current.push(' ');
+                length += 1;
+            }
+            current.push_str(word);
+            length += word_length;
+            empty = false;
+        }
+        lines.push(current.trim_end().to_owned());
+    }
+
+    // A paragraph ending on a hard break leaves an empty line behind
+    while lines.len() > 1 && lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+fn indented(text: &str)-> Vec<String> {
+    text.lines().map(|line| format!("    {}", line).trim_end().to_owned()).collect()
+}
+
+fn rule(width: usize)-> String {
+    "-".repeat(width.max(3))
+}
+
+/// Appends the items of the list, nested lists lined up with the text of the item above them
+fn list_lines(sec_list: &SecList, indent: usize, width: usize, lines: &mut Vec<String>) {
+    let mut nests = sec_list.nests.iter();
+    let mut number = 0;
+    let mut hanging = indent + 2;
+
+    for (meta, tokens) in &sec_list.items {
+        if tokens.is_empty() {
+            if let Some(nested) = nests.next() {
+                list_lines(nested, hanging, width, lines);
+            }
+            continue;
+        }
+        number += 1;
+        let first = format!("{}{} ", " ".repeat(indent), item_marker(&sec_list.r#type, meta, number));
+        hanging = first.chars().count();
+        lines.extend(wrap(&inline_text(tokens, width), width, &first, &" ".repeat(hanging)));
+    }
+}
+
+fn item_marker(list_type: &ListType, meta: &ListMeta, number: usize)-> String {
+    let task = match meta {
+        ListMeta::None => None,
+        ListMeta::Checked => Some("[x]"),
+        ListMeta::Unchecked => Some("[ ]"),
+        ListMeta::InProgress => Some("[-]"),
+        ListMeta::Cancelled => Some("[~]"),
+        ListMeta::Important => Some("[!]"),
+    };
+    let marker = match list_type {
+        ListType::Todo => return task.unwrap_or("-").to_owned(),
+        ListType::Numbered => format!("{}.", number),
+        ListType::Roman(RomanType::Upper) => format!("{}.", roman(number)),
+        ListType::Roman(RomanType::Lower) => format!("{}.", roman(number).to_lowercase()),
+        ListType::Alphabetic(case) => {
+            let letters = alphabetic(number);
+            match case {
+                AlphaType::Upper => format!("{}.", letters.to_uppercase()),
+                AlphaType::Lower => format!("{}.", letters),
+            }
+        },
+        ListType::Bullet(bullet_type) => match bullet_type {
+            BulletType::Default => String::from("-"),
+            BulletType::Circle => String::from("◦"),
+            BulletType::Square => String::from("▪"),
+            BulletType::Arrow => String::from("→"),
+            BulletType::Check => String::from("✓"),
+            BulletType::Star => String::from("★"),
+            BulletType::Glyph(glyph) => glyph.clone(),
+        },
+    };
+    match task {
+        Some(task) => format!("{} {}", marker, task),
+        None => marker,
+    }
+}
+
+/// ```1``` as ```I```, ```14``` as ```XIV```
+fn roman(mut number: usize)-> String {
+    const NUMERALS: &[(usize, &str)] = &[
+        (1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"),
+        (50, "L"), (40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I"),
+    ];
+    let mut numeral = String::new();
+    for (value, symbols) in NUMERALS {
+        while number >= *value {
+            numeral.push_str(symbols);
+            number -= value;
+        }
+    }
+    numeral
+}
+
+/// ```1``` as ```a```, ```27``` as ```aa```, like css ```lower-alpha```
+fn alphabetic(mut number: usize)-> String {
+    let mut letters = vec![];
+    while number > 0 {
+        number -= 1;
+        letters.push((b'a' + (number % 26) as u8) as char);
+        number /= 26;
+    }
+    letters.iter().rev().collect()
+}
+
+fn toc_lines(entries: &[TocEntry], depth: usize, lines: &mut Vec<String>) {
+    for entry in entries {
+        lines.push(format!("{}- {}", "  ".repeat(depth), entry.text));
+        toc_lines(&entry.children, depth + 1, lines);
+    }
+}
+
+/// The text of an html block, tags dropped and tables laid out in columns
+fn html_text(raw: &str)-> Vec<String> {
+    let mut lines = vec![];
+    let mut text = String::new();
+    // Rows of the table being read, with whether they only hold header cells
+    let mut rows: Vec<(Vec<String>, bool)> = vec![];
+    let mut cell: Option<String> = None;
+    let mut skipped: Option<String> = None;
+    let mut rest = raw;
+
+    while !rest.is_empty() {
+        let text_len = rest.find('<').unwrap_or(rest.len());
+        if skipped.is_none() {
+            match cell.as_mut() {
+                Some(cell) => cell.push_str(&rest[..text_len]),
+                None => text.push_str(&rest[..text_len]),
+            }
+        }
+        rest = &rest[text_len..];
+        if rest.is_empty() {
+            break;
+        }
+
+        let Some((tag, _)) = scan_html_tag(rest) else {
+            match cell.as_mut() {
+                Some(cell) => cell.push('<'),
+                None => text.push('<'),
+            }
+            rest = &rest[1..];
+            continue;
+        };
+        rest = &rest[tag.raw.len()..];
+
+        if let Some(name) = &skipped {
+            if tag.closing && tag.name == *name {
+                skipped = None;
+            }
+            continue;
+        }
+        match (tag.name.as_str(), tag.closing) {
+            // Neither scripts nor styles are readable text
+            ("script" | "style", false) => skipped = Some(tag.name.clone()),
+            ("tr", false) => {
+                end_cell(&mut cell, &mut rows);
+                rows.push((vec![], true));
+            },
+            ("td" | "th", false) => {
+                end_cell(&mut cell, &mut rows);
+                if rows.is_empty() {
+                    rows.push((vec![], true));
+                }
+                if let Some((_, header)) = rows.last_mut() {
+                    *header &= tag.name == "th";
+                }
+                cell = Some(String::new());
+            },
+            ("td" | "th" | "tr", true) => end_cell(&mut cell, &mut rows),
+            ("table", true) => {
+                end_cell(&mut cell, &mut rows);
+                end_text(&mut text, &mut lines);
+                lines.extend(table_lines(&rows));
+                rows.clear();
+            },
+            ("br", _) => end_text(&mut text, &mut lines),
+            _ if is_block_tag(&tag) && cell.is_none() => end_text(&mut text, &mut lines),
+            _ => {}
+        }
+    }
+
+    end_cell(&mut cell, &mut rows);
+    end_text(&mut text, &mut lines);
+    lines.extend(table_lines(&rows));
+    lines
+}
+
+fn end_cell(cell: &mut Option<String>, rows: &mut [(Vec<String>, bool)]) {
+    if let (Some(content), Some((cells, _))) = (cell.take(), rows.last_mut()) {
+        cells.push(collapse_whitespace(&decode_entities(&content)));
+    }
+}
+
+fn end_text(text: &mut String, lines: &mut Vec<String>) {
+    let line = collapse_whitespace(&decode_entities(text));
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    text.clear();
+}
+
+fn collapse_whitespace(text: &str)-> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Lays out the rows in columns as wide as their widest cell, a header row underlined with dashes
+fn table_lines(rows: &[(Vec<String>, bool)])-> Vec<String> {
+    let rows: Vec<&(Vec<String>, bool)> = rows.iter().filter(|(cells, _)| !cells.is_empty()).collect();
+    let columns = rows.iter().map(|(cells, _)| cells.len()).max().unwrap_or(0);
+    let mut widths = vec![0; columns];
+    for (cells, _) in &rows {
+        for (column, content) in cells.iter().enumerate() {
+            widths[column] = widths[column].max(content.chars().count());
+        }
+    }
+
+    let mut lines = vec![];
+    for (index, (cells, header)) in rows.iter().enumerate() {
+        let line: Vec<String> = widths.iter().enumerate().map(|(column, width)| {
+            let content = cells.get(column).map_or("", String::as_str);
+            format!("{}{}", content, " ".repeat(width - content.chars().count()))
+        }).collect();
+        lines.push(line.join("  ").trim_end().to_owned());
+        if *header && rows.get(index + 1).is_some_and(|(_, header)| !header) {
+            lines.push(widths.iter().map(|width| "-".repeat(*width)).collect::<Vec<String>>().join("  "));
+        }
+    }
+    lines
+}
+
+/// Replaces the named entities common in hand written html and numeric references by their character
+fn decode_entities(text: &str)-> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').filter(|end| *end <= 10).and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                "copy" => Some('©'),
+                "mdash" => Some('—'),
+                "ndash" => Some('–'),
+                "hellip" => Some('…'),
+                name => match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => name.strip_prefix('#').and_then(|decimal| decimal.parse().ok()),
+                }.and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer;
+
+    fn text(input: &str, width: usize)-> String {
+        text_parser(&tokenizer(input), &TextOptions { width })
+    }
+
+    #[test]
+    fn wraps_paragraphs_at_the_width() {
+        let input = "one two three four five six\nseven eight\n";
+        assert_eq!(text(input, 0), "one two three four five six\nseven eight\n");
+        assert_eq!(text(input, 12), "one two\nthree four\nfive six\nseven eight\n");
+        assert_eq!(text("a supercalifragilistic word\n", 8), "a\nsupercalifragilistic\nword\n");
+    }
+
+    #[test]
+    fn lists_keep_markers_and_nesting() {
+        assert_eq!(text("- a\n- b\n    1. c\n    2. d\n- e\n", 0), "- a\n- b\n  1. c\n  2. d\n- e\n");
+        assert_eq!(text("- one two three four\n    - five six seven eight\n", 12), "- one two\n  three four\n  - five six\n    seven\n    eight\n");
+    }
+
+    #[test]
+    fn html_tables_are_aligned_columns() {
+        let input = "<table><tr><th>Name</th><th>Qty</th></tr><tr><td>apple</td><td>10</td></tr></table>\n";
+        assert_eq!(text(input, 0), "Name   Qty\n-----  ---\napple  10\n");
+    }
+
+    #[test]
+    fn links_read_text_and_url() {
+        assert_eq!(text("See [the docs](https://example.com/docs) or [mail](mailto:a@b.c)\n", 0), "See the docs (https://example.com/docs) or mail (mailto:a@b.c)\n");
+        assert_eq!(text("[https://x.y](https://x.y) and [x.y](http://x.y)\n", 0), "https://x.y and x.y\n");
+    }
+
+    #[test]
+    fn footnotes_are_listed_at_the_end() {
+        assert_eq!(text("A[^b] and[^a]\n\n[^a]: first\n[^b]: second note\n", 0), "A[1] and[2]\n\n---\n\n[1] second note\n[2] first\n");
+        assert_eq!(text("A[^a]\n\n[^a]: a long footnote that wraps around\n", 16), "A[1]\n\n----------------\n\n[1] a long\n    footnote\n    that wraps\n    around\n");
+    }
+}
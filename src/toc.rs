@@ -0,0 +1,114 @@
+use crate::html::escape_text;
+use crate::inline_token::plain_text;
+use crate::SecondaryToken;
+
+#[derive(Debug, Clone)]
+/// A heading of the document as listed in the table of contents
+pub struct TocEntry {
+    pub level: usize,
+    pub text: String,
+    /// Anchor id of the heading, the same used by ```[text][id-ref]``` links
+    pub id: String,
+    pub children: Vec<TocEntry>
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Range of heading levels included by a ```::toc``` placeholder
+pub struct TocRange {
+    pub min: usize,
+    pub max: usize
+}
+
+impl Default for TocRange {
+    fn default() -> Self {
+        TocRange { min: 1, max: 6 }
+    }
+}
+
+/// Returns every heading of the tokenized document in order of appearance, with no nesting
+pub fn headings(tokens: &[SecondaryToken])-> Vec<TocEntry> {
+    tokens.iter().filter_map(|token| match token {
+        SecondaryToken::Heading(heading) => Some(TocEntry {
+            level: heading.level,
+            text: plain_text(&heading.tokens),
+            id: heading.id.clone(),
+            children: vec![],
+        }),
+        _ => None,
+    }).collect()
+}
+
+/// Returns the heading hierarchy of the tokenized document for levels within ```range```
+///
+/// Each heading holds the deeper headings following it as ```children```, a skipped level
+/// (```#``` followed by ```###```) nests directly under the closest shallower heading
+pub fn table_of_contents(tokens: &[SecondaryToken], range: TocRange)-> Vec<TocEntry> {
+    let entries: Vec<TocEntry> = headings(tokens).into_iter()
+        .filter(|entry| entry.level >= range.min && entry.level <= range.max)
+        .collect();
+
+    let mut index = 0;
+    nest_entries(&entries, &mut index, 0)
+}
+
+fn nest_entries(entries: &[TocEntry], index: &mut usize, parent_level: usize)-> Vec<TocEntry> {
+    let mut nested = vec![];
+    while let Some(entry) = entries.get(*index) {
+        if entry.level <= parent_level {
+            break;
+        }
+        *index += 1;
+
+        let mut entry = entry.clone();
+        entry.children = nest_entries(entries, index, entry.level);
+        nested.push(entry);
+    }
+    nested
+}
+
+/// Renders the given hierarchy as a ```<nav>``` of nested ordered lists
+pub fn toc_parser(entries: &[TocEntry])-> String {
+    let mut html = String::from("<nav class=\"toc\">\n");
+    toc_list_parser(entries, &mut html);
+    html.push_str("</nav>\n");
+    html
+}
+
+fn toc_list_parser(entries: &[TocEntry], html: &mut String) {
+    if entries.is_empty() {
+        return;
+    }
+
+    html.push_str("<ol>\n");
+    for entry in entries {
+        let item = format!("<li><a href=\"#{}\">{}</a>\n", entry.id, escape_text(&entry.text));
+        html.push_str(&item);
+        toc_list_parser(&entry.children, html);
+        html.push_str("</li>\n");
+    }
+    html.push_str("</ol>\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer;
+
+    fn outline(entries: &[TocEntry])-> String {
+        entries.iter().map(|entry| format!("{}({})", entry.id, outline(&entry.children))).collect()
+    }
+
+    #[test]
+    fn nests_headings_under_shallower_ones() {
+        let tokens = tokenizer("# A\n\n### B\n\n## C\n\n#### D\n\n# E\n");
+        assert_eq!(outline(&table_of_contents(&tokens, TocRange::default())), "a(b()c(d()))e()");
+        assert_eq!(outline(&table_of_contents(&tokens, TocRange { min: 2, max: 3 })), "b()c()");
+        assert_eq!(headings(&tokens).len(), 5);
+    }
+
+    #[test]
+    fn renders_the_placeholder_range() {
+        let html = crate::parser(&tokenizer("::toc min=2 max=3\n\n# A\n\n## B & c\n\n#### D\n"));
+        assert!(html.contains("<nav class=\"toc\">\n<ol>\n<li><a href=\"#b-c\">B &amp; c</a>\n</li>\n</ol>\n</nav>\n"), "{html}");
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Interval between two polls of ```Watcher::wait```
+pub const POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Last modification time and length of a file, a change to either counting as a modification
+type Stamp = (Option<SystemTime>, u64);
+
+#[derive(Debug, Clone)]
+/// Polls files and directory trees for added, modified and removed files
+///
+/// Hidden files and directories are not watched, nor are the ```ignored``` directories, like the
+/// output directory of a site built inside its source directory
+pub struct Watcher {
+    roots: Vec<PathBuf>,
+    /// Canonical paths of the ignored directories
+    ignored: Vec<PathBuf>,
+    stamps: BTreeMap<PathBuf, Stamp>,
+}
+
+impl Watcher {
+    /// Starts watching the given files and directories, changes being relative to their current state
+    pub fn new(roots: Vec<PathBuf>, ignored: &[PathBuf])-> Watcher {
+        let ignored = ignored.iter().filter_map(|path| path.canonicalize().ok()).collect();
+        let mut watcher = Watcher { roots, ignored, stamps: BTreeMap::new() };
+        watcher.stamps = watcher.snapshot();
+        watcher
+    }
+
+    /// Files added, modified or removed since the last call, as paths under the watched roots
+    pub fn changes(&mut self)-> Vec<PathBuf> {
+        let stamps = self.snapshot();
+        let mut changed: Vec<PathBuf> = stamps.iter()
+            .filter(|(path, stamp)| self.stamps.get(*path) != Some(stamp))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.extend(self.stamps.keys().filter(|path| !stamps.contains_key(*path)).cloned());
+        changed.sort();
+
+        self.stamps = stamps;
+        changed
+    }
+
+    /// Blocks until some files change and returns them
+    ///
+    /// Once a change is seen it polls one more time, so that a save touching several files
+    /// or writing a file in steps is reported at once
+    pub fn wait(&mut self)-> Vec<PathBuf> {
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let mut changed = self.changes();
+            if changed.is_empty() {
+                continue;
+            }
+
+            thread::sleep(POLL_INTERVAL);
+            changed.extend(self.changes());
+            changed.sort();
+            changed.dedup();
+            return changed;
+        }
+    }
+
+    fn snapshot(&self)-> BTreeMap<PathBuf, Stamp> {
+        let mut stamps = BTreeMap::new();
+        for root in &self.roots {
+            match root.is_dir() {
+                true => self.snapshot_directory(root, &mut stamps),
+                false => {
+                    if let Ok(metadata) = fs::metadata(root) {
+                        stamps.insert(root.clone(), (metadata.modified().ok(), metadata.len()));
+                    }
+                }
+            }
+        }
+        stamps
+    }
+
+    fn snapshot_directory(&self, directory: &Path, stamps: &mut BTreeMap<PathBuf, Stamp>) {
+        let Ok(entries) = fs::read_dir(directory) else { return };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else { continue };
+
+            if metadata.is_dir() {
+                if !path.canonicalize().is_ok_and(|path| self.ignored.contains(&path)) {
+                    self.snapshot_directory(&path, stamps);
+                }
+            } else {
+                stamps.insert(path, (metadata.modified().ok(), metadata.len()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_added_modified_and_removed_files() {
+        let root = std::env::temp_dir().join(format!("markdownit-watch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("output")).unwrap();
+        fs::write(root.join("a.md"), "# A\n").unwrap();
+
+        let mut watcher = Watcher::new(vec![root.clone()], &[root.join("output")]);
+        assert!(watcher.changes().is_empty());
+
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("docs/b.md"), "# B\n").unwrap();
+        fs::write(root.join("output/a.html"), "<h1>A</h1>").unwrap();
+        fs::write(root.join(".a.md.swp"), "").unwrap();
+        assert_eq!(watcher.changes(), [root.join("docs/b.md")]);
+        assert!(watcher.changes().is_empty());
+
+        fs::write(root.join("a.md"), "# A changed\n").unwrap();
+        fs::remove_dir_all(root.join("docs")).unwrap();
+        assert_eq!(watcher.changes(), [root.join("a.md"), root.join("docs/b.md")]);
+
+        let mut file_watcher = Watcher::new(vec![root.join("a.md")], &[]);
+        fs::remove_file(root.join("a.md")).unwrap();
+        assert_eq!(file_watcher.changes(), [root.join("a.md")]);
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,25 @@
+use markdownit::lint::{lint, LintOptions};
+use markdownit::{parse_document, parser};
+
+/// The documents bundled with the repository, rendered as any user document would be
+const SAMPLES: &[(&str, &str)] = &[
+    ("README.md", include_str!("../README.md")),
+    ("doc.md", include_str!("../doc.md")),
+    ("sample.md", include_str!("../sample.md")),
+    ("simple.txt", include_str!("../simple.txt")),
+];
+
+#[test]
+fn samples_render() {
+    for (name, input) in SAMPLES {
+        let document = parse_document(input);
+        assert!(parser(&document.tokens).starts_with("<div>"), "{name}");
+    }
+}
+
+#[test]
+fn samples_lint() {
+    for (_, input) in SAMPLES {
+        lint(input, &LintOptions::default());
+    }
+}
@@ -0,0 +1,95 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use markdownit::serve::{serve, EVENTS_PATH};
+use markdownit::site::SiteOptions;
+
+/// Serves a fresh directory holding ```guide.md``` on a free localhost port
+fn start(name: &str)-> (SocketAddr, PathBuf) {
+    let root = std::env::temp_dir().join(format!("markdownit-serve-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("guide.md"), "# Guide\n\nSee [the notes](notes.md).\n").unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let served = root.clone();
+    thread::spawn(move || serve(listener, &served, SiteOptions::default()));
+    (address, root)
+}
+
+fn get(address: SocketAddr, target: &str)-> String {
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    stream.write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target).as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn serves_rendered_pages() {
+    let (address, root) = start("pages");
+
+    for target in ["/guide.md", "/guide.html"] {
+        let response = get(address, target);
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{target}: {response}");
+        assert!(response.contains("<h1 id=\"guide\">Guide</h1>"));
+        assert!(response.contains("href=\"notes.html\""));
+        assert!(response.contains(EVENTS_PATH));
+    }
+    assert!(get(address, "/").contains("guide.html"));
+    assert!(get(address, "/missing.md").starts_with("HTTP/1.1 404"));
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn refuses_paths_out_of_the_root() {
+    let (address, root) = start("escape");
+
+    for target in ["/../", "/../Cargo.toml", "/a/../../guide.md"] {
+        let response = get(address, target);
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{target}: {response}");
+    }
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn sends_reload_events_on_changes() {
+    let (address, root) = start("events");
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    stream.write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", EVENTS_PATH).as_bytes()).unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut status = String::new();
+    reader.read_line(&mut status).unwrap();
+    assert_eq!(status, "HTTP/1.1 200 OK\r\n");
+
+    // The watcher may take its first snapshot after a write, so the file keeps changing until an event comes
+    let mut event = None;
+    for attempt in 0..20 {
+        fs::write(root.join("guide.md"), format!("# Guide\n\nEdit {}\n", "!".repeat(attempt + 1))).unwrap();
+        let mut line = String::new();
+        while reader.read_line(&mut line).is_ok_and(|read| read > 0) {
+            if line.starts_with("event: ") {
+                event = Some(line.trim_end().to_owned());
+                break;
+            }
+            line.clear();
+        }
+        if event.is_some() {
+            break;
+        }
+    }
+    assert_eq!(event.as_deref(), Some("event: reload"));
+
+    fs::remove_dir_all(root).unwrap();
+}